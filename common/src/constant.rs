@@ -4,11 +4,21 @@ pub const CONSTANT_LONG: u8   = 0x1;
 pub const CONSTANT_DOUBLE: u8 = 0x2;
 pub const CONSTANT_STRING: u8 = 0x3;
 
-pub const CURRENT_VERSION_MINOR: u8 = 0;
+pub const METHOD_KIND_NORMAL: u8 = 0x0;
+pub const METHOD_KIND_GETTER: u8 = 0x1;
+pub const METHOD_KIND_SETTER: u8 = 0x2;
+
+pub const ATTRIBUTE_LINE_NUMBER_TABLE: u8 = 0x1;
+pub const ATTRIBUTE_LOCAL_VARIABLE_TABLE: u8 = 0x2;
+
+pub const CURRENT_VERSION_MINOR: u8 = 9;
 pub const CURRENT_VERSION_MAJOR: u8 = 1;
 
 pub const ENTRY_NAME: &str = "$";
 
+// the ffi functions defined as globals by the vm, with their param count
+pub const FFI_FUNCTIONS: [(&str, u8); 3] = [("__print", 1), ("__println", 1), ("__len", 1)];
+
 #[derive(Debug)]
 pub enum ConstantItem {
     Long(i64),
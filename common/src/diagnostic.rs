@@ -0,0 +1,189 @@
+use std::fmt::{Display, Formatter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::is_terminal;
+use crate::json::json_string;
+
+// the renderer shared by lexer, parser, semantic and runtime errors:
+//
+// error: unexpected token: `;`
+//  --> hello.charon:3:9
+//   |
+// 3 | var a = ;
+//   |         ^
+//   = help: ...
+//
+// or one json object per line with `--error-format=json`, for editor integration
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    Json
+}
+
+impl ErrorFormat {
+    // the value of `--error-format=`
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "human" => Some(Self::Human),
+            "json" => Some(Self::Json),
+            _ => None
+        }
+    }
+}
+
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_error_format(format: ErrorFormat) {
+    JSON_FORMAT.store(format == ErrorFormat::Json, Ordering::Relaxed);
+}
+
+pub fn error_format() -> ErrorFormat {
+    if JSON_FORMAT.load(Ordering::Relaxed) {
+        ErrorFormat::Json
+    } else {
+        ErrorFormat::Human
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Error,
+    Warning
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning"
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Level::Error => "\x1b[1;31m",
+            Level::Warning => "\x1b[1;33m"
+        }
+    }
+}
+
+// line and column start from 1, `len` is the count of chars to underline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    pub source_line: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub code: Option<String>,// such as `W0001` for warnings
+    pub msg: String,
+    pub file: Option<String>,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>
+}
+
+impl Diagnostic {
+    pub fn new(level: Level, msg: String) -> Self {
+        Self {
+            level,
+            code: None,
+            msg,
+            file: None,
+            span: None,
+            notes: Vec::new(),
+            help: Vec::new()
+        }
+    }
+
+    pub fn error(msg: String) -> Self {
+        Self::new(Level::Error, msg)
+    }
+
+    // print to stderr in the format set by `set_error_format`
+    pub fn emit(&self) {
+        match error_format() {
+            ErrorFormat::Json => eprintln!("{}", self.to_json()),
+            ErrorFormat::Human => {
+                let mut s = String::new();
+                let _ = self.render(&mut s, is_terminal());
+                // blank line between diagnostics
+                eprintln!("{s}");
+            }
+        }
+    }
+
+    pub fn warning(msg: String) -> Self {
+        Self::new(Level::Warning, msg)
+    }
+
+    fn render(&self, s: &mut String, color: bool) -> std::fmt::Result {
+        let title = match &self.code {
+            Some(code) => format!("{}[{code}]", self.level.name()),
+            None => self.level.name().to_owned()
+        };
+        if color {
+            write!(s, "{}{title}\x1b[0m\x1b[1m: {}\x1b[0m", self.level.color(), self.msg)?;
+        } else {
+            write!(s, "{title}: {}", self.msg)?;
+        }
+        writeln!(s)?;
+
+        let width = self.span.as_ref().map_or(0, |span| span.line.to_string().len());
+        let pad = " ".repeat(width);
+        match (&self.file, &self.span) {
+            (Some(file), Some(span)) => writeln!(s, "{pad}--> {file}:{}:{}", span.line, span.column)?,
+            (None, Some(span)) => writeln!(s, "{pad}--> {}:{}", span.line, span.column)?,
+            (Some(file), None) => writeln!(s, " --> {file}")?,
+            (None, None) => ()
+        }
+
+        if let Some(span) = &self.span {
+            // tab is replaced by one space, so that the underline stays at the column
+            let source_line = span.source_line.trim_end().replace('\t', " ");
+            writeln!(s, "{pad} |")?;
+            writeln!(s, "{} | {source_line}", span.line)?;
+            writeln!(s, "{pad} | {}{}", " ".repeat(span.column - 1), "^".repeat(span.len.max(1)))?;
+        }
+
+        for note in &self.notes {
+            writeln!(s, "{pad} = note: {note}")?;
+        }
+        for help in &self.help {
+            writeln!(s, "{pad} = help: {help}")?;
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let code = self.code.as_deref().map_or("null".to_owned(), json_string);
+        let mut s = format!("{{\"level\":\"{}\",\"code\":{code},\"message\":{}", self.level.name(), json_string(&self.msg));
+        let file = self.file.as_deref().map_or("null".to_owned(), json_string);
+        let _ = write!(s, ",\"file\":{file}");
+        match &self.span {
+            Some(span) => {
+                let _ = write!(s, ",\"line\":{},\"column\":{},\"end_column\":{}", span.line, span.column, span.column + span.len.max(1));
+            }
+            None => s.push_str(",\"line\":null,\"column\":null,\"end_column\":null")
+        }
+        let _ = write!(s, ",\"notes\":{},\"help\":{}}}", json_array(&self.notes), json_array(&self.help));
+        s
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut s = String::new();
+        self.render(&mut s, false)?;
+        write!(f, "{}", s.trim_end())
+    }
+}
+
+fn json_array(items: &[String]) -> String {
+    let items: Vec<_> = items.iter().map(|v| json_string(v)).collect();
+    format!("[{}]", items.join(","))
+}
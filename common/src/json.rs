@@ -0,0 +1,284 @@
+use std::fmt::{Display, Formatter, Write};
+use crate::Result;
+
+// json values of the tools, such as `--error-format=json`, `charonc --emit-format=json` and the messages of `charon-lsp`,
+// objects keep the insertion order of the keys
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Long(i64),
+    Double(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>)
+}
+
+impl Json {
+    pub fn object<const N: usize>(entries: [(&str, Json); N]) -> Self {
+        Json::Object(entries.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+    }
+
+    pub fn string(v: &str) -> Self {
+        Json::String(v.to_owned())
+    }
+
+    pub fn parse(s: &str) -> Result<Json> {
+        let mut parser = JsonParser { bytes: s.as_bytes(), offset: 0 };
+        let v = parser.value()?;
+        parser.skip_whitespace();
+        if parser.offset != parser.bytes.len() {
+            return Err(format!("unexpected trailing characters at {}", parser.offset));
+        }
+        Ok(v)
+    }
+
+    // the value of the key if it's an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(v) => Some(v),
+            _ => None
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Long(v) => Some(*v),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None
+        }
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(v) => write!(f, "{v}"),
+            Json::Long(v) => write!(f, "{v}"),
+            // json has no NaN or infinity
+            Json::Double(v) if !v.is_finite() => f.write_str("null"),
+            Json::Double(v) => write!(f, "{v:?}"),
+            Json::String(v) => f.write_str(&json_string(v)),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(entries) => {
+                f.write_char('{')?;
+                for (i, (k, v)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}:{v}", json_string(k))?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+pub fn json_string(v: &str) -> String {
+    let mut s = String::with_capacity(v.len() + 2);
+    s.push('"');
+    for c in v.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            _ if (c as u32) < 0x20 => {
+                let _ = write!(s, "\\u{:04x}", c as u32);
+            }
+            _ => s.push(c)
+        }
+    }
+    s.push('"');
+    s
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    offset: usize
+}
+
+impl JsonParser<'_> {
+    fn value(&mut self) -> Result<Json> {
+        self.skip_whitespace();
+        match self.bytes.get(self.offset) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(c) => Err(format!("unexpected character: {} at {}", *c as char, self.offset)),
+            None => Err("unexpected end of json".to_owned())
+        }
+    }
+
+    fn object(&mut self) -> Result<Json> {
+        self.offset += 1;
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.consume(b'}') {
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.bytes.get(self.offset) != Some(&b'"') {
+                return Err(format!("expected object key at {}", self.offset));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.consume(b':') {
+                return Err(format!("expected `:` at {}", self.offset));
+            }
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.consume(b'}') {
+                return Ok(Json::Object(entries));
+            }
+            if !self.consume(b',') {
+                return Err(format!("expected `,` or `}}` at {}", self.offset));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json> {
+        self.offset += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.consume(b']') {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            if self.consume(b']') {
+                return Ok(Json::Array(items));
+            }
+            if !self.consume(b',') {
+                return Err(format!("expected `,` or `]` at {}", self.offset));
+            }
+        }
+    }
+
+    // the opening `"` is at `offset`
+    fn string(&mut self) -> Result<String> {
+        self.offset += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(c) = self.bytes.get(self.offset).copied() else {
+                return Err("unclosed json string".to_owned());
+            };
+            self.offset += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let Some(escape) = self.bytes.get(self.offset).copied() else {
+                        return Err("unclosed json string".to_owned());
+                    };
+                    self.offset += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(format!("invalid escape in json string at {}", self.offset - 1))
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                _ => bytes.push(c)
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| "invalid utf-8 in json string".to_owned())
+    }
+
+    // `\uXXXX` after `\u`, utf-16 surrogate pairs are two escapes
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| format!("invalid unicode escape at {}", self.offset));
+        }
+        if !self.bytes[self.offset..].starts_with(b"\\u") {
+            return Err(format!("unpaired surrogate at {}", self.offset));
+        }
+        self.offset += 2;
+        let low = self.hex4()?;
+        let v = 0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+        char::from_u32(v).ok_or_else(|| format!("invalid unicode escape at {}", self.offset))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self.bytes.get(self.offset..self.offset + 4).and_then(|v| std::str::from_utf8(v).ok());
+        let Some(v) = digits.and_then(|s| u32::from_str_radix(s, 16).ok()) else {
+            return Err(format!("invalid unicode escape at {}", self.offset));
+        };
+        self.offset += 4;
+        Ok(v)
+    }
+
+    fn number(&mut self) -> Result<Json> {
+        let start = self.offset;
+        while self.bytes.get(self.offset).is_some_and(|c| matches!(c, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.offset += 1;
+        }
+        let s = std::str::from_utf8(&self.bytes[start..self.offset]).unwrap_or_default();
+        if let Ok(v) = s.parse::<i64>() {
+            return Ok(Json::Long(v));
+        }
+        s.parse::<f64>().map(Json::Double).map_err(|_| format!("invalid number: {s} at {start}"))
+    }
+
+    fn keyword(&mut self, keyword: &str, v: Json) -> Result<Json> {
+        if self.bytes[self.offset..].starts_with(keyword.as_bytes()) {
+            self.offset += keyword.len();
+            Ok(v)
+        } else {
+            Err(format!("unexpected character at {}", self.offset))
+        }
+    }
+
+    fn consume(&mut self, c: u8) -> bool {
+        if self.bytes.get(self.offset) == Some(&c) {
+            self.offset += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.offset).is_some_and(|c| c.is_ascii_whitespace()) {
+            self.offset += 1;
+        }
+    }
+}
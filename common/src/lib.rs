@@ -7,7 +7,10 @@ pub mod constant;
 pub mod reader;
 pub mod program;
 pub mod loader;
+pub mod verifier;
 pub mod line_column_info;
+pub mod diagnostic;
+pub mod json;
 
 pub type Result<T> = std::result::Result<T, String>;
 
@@ -1,9 +1,10 @@
-pub struct LineColumnInfo {
+pub struct LineColumnInfo<'a> {
+    source: &'a [u8],
     line_end_offset: Vec<usize>
 }
 
-impl LineColumnInfo {
-    pub fn new(source: &[u8]) -> Self {
+impl<'a> LineColumnInfo<'a> {
+    pub fn new(source: &'a [u8]) -> Self {
         let mut line_end_offset = Vec::new();
         
         for (off, ch) in source.iter().enumerate() {
@@ -12,37 +13,38 @@ impl LineColumnInfo {
             }
         }
         
-        Self {line_end_offset}
+        Self {source, line_end_offset}
     }
     
-    pub fn line_column_info(&self, mut off: usize) -> (usize, usize) {
-        if self.line_end_offset.is_empty() {
-            return (0, 0);
-        }
-        
-        let mut line: Option<usize> = None;
-        for (l, o) in self.line_end_offset.iter().enumerate() {
-            if off <= *o {
-                line = Some(l);
-                break;
-            }
-        }
-        
-        let line = match line {
-            None => {
-                let l = self.line_end_offset.len() - 1;
-                off = unsafe {*self.line_end_offset.get_unchecked(l)};
-                l
-            }
-            Some(v) => v
-        };
-        
-        let column = if line == 0 {
-            off + 1
+    // line and column start from 1, column counts chars (unicode scalar values), not bytes
+    pub fn line_column_info(&self, off: usize) -> (usize, usize) {
+        let off = off.min(self.source.len());
+        let line = self.line_end_offset.partition_point(|o| *o < off);
+        let line_start = if line == 0 {
+            0
         } else {
-            let pre_line_offset = unsafe {*self.line_end_offset.get_unchecked(line - 1)};
-            off - pre_line_offset
+            self.line_end_offset[line - 1] + 1
         };
+
+        let column = String::from_utf8_lossy(&self.source[line_start..off]).chars().count() + 1;
         (line + 1, column)
     }
-}
\ No newline at end of file
+
+    // text of the line (from 1), without the line break
+    pub fn line_text(&self, line: usize) -> String {
+        let start = if line <= 1 {
+            0
+        } else {
+            self.line_end_offset.get(line - 2).map_or(self.source.len(), |o| o + 1)
+        };
+        let end = self.line_end_offset.get(line - 1).copied().unwrap_or(self.source.len()).max(start);
+        String::from_utf8_lossy(&self.source[start..end]).trim_end_matches('\r').to_owned()
+    }
+
+    // count of chars in `off..off + len`, at least 1
+    pub fn char_len(&self, off: usize, len: usize) -> usize {
+        let start = off.min(self.source.len());
+        let end = off.saturating_add(len).min(self.source.len());
+        String::from_utf8_lossy(&self.source[start..end]).chars().count().max(1)
+    }
+}
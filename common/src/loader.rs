@@ -1,11 +1,13 @@
 use ahash::{HashMap, HashMapExt};
 use crate::constant::*;
-use crate::program::{Class, Function, Method, Program};
+use crate::program::{Class, DebugInfo, Function, LineNumber, LocalVariable, Method, Program};
 use crate::reader::LEReader;
+use crate::verifier::{max_stack, verify};
 use crate::Result;
 
 pub struct Loader<'a> {
     reader: LEReader<'a>,
+    minor: u8,
     cp: Vec<ConstantItem>,
     classes: HashMap<String, Class>,
     functions: HashMap<String, Function>
@@ -15,13 +17,22 @@ impl Loader<'_> {
     pub fn new(bytes: &[u8]) -> Loader {
         Loader {
             reader: LEReader::new(bytes),
+            minor: 0,
             cp: Vec::new(),
             classes: HashMap::new(),
             functions: HashMap::new()
         }
     }
 
-    pub fn load(mut self) -> Result<Program> {
+    pub fn load(self) -> Result<Program> {
+        let program = self.load_unverified()?;
+        // bad code is rejected before it runs
+        verify(&program)?;
+        Ok(program)
+    }
+
+    // the code may be invalid, for tools which show the file as it is, such as the disassembler
+    pub fn load_unverified(mut self) -> Result<Program> {
         for magic in MAGIC.as_bytes() {
             if *magic != self.reader.next_u8()? {
                 return Err("input is not charon bytecode, magic not match".to_owned());
@@ -34,25 +45,38 @@ impl Loader<'_> {
             || (major == CURRENT_VERSION_MAJOR && minor > CURRENT_VERSION_MINOR) {
             return Err(format!("unsupport version: {major}.{minor}"));
         }
+        self.minor = minor;
+
+        // source file name is added in version 1.7, empty if the debug info is stripped
+        let mut source_file = None;
+        if minor >= 7 {
+            let len = self.reader.next_u16()? as usize;
+            let mut v = Vec::with_capacity(len);
+            self.reader.read_to(&mut v, len)?;
+            if len > 0 {
+                source_file = Some(String::from_utf8(v).map_err(|_| "source file name is not valid utf-8 format".to_owned())?);
+            }
+        }
 
         self.load_constant_pool()?;
         self.load_classes()?;
         self.load_functions()?;
 
-        Ok(Program::new(minor, major, self.cp, self.classes, self.functions))
+        Ok(Program::new(minor, major, source_file, self.cp, self.classes, self.functions))
     }
 
     fn load_constant_pool(&mut self) -> Result<()> {
-        let pool_item_count = self.reader.next_u16()? as usize;
-        self.cp.reserve(pool_item_count);
+        let pool_item_count = self.next_u16_or_u32()? as usize;
+        // a string constant is the shortest item: tag & length
+        self.cp.reserve(self.capacity(pool_item_count, 3));
 
         for _ in 0 .. pool_item_count {
             let item = match self.reader.next_u8()? {
                 CONSTANT_LONG => ConstantItem::Long(self.reader.next_u64()? as i64),
                 CONSTANT_DOUBLE => ConstantItem::Double(f64::from_bits(self.reader.next_u64()?)),
                 CONSTANT_STRING => {
-                    let len = self.reader.next_u16()? as usize;
-                    let mut v = Vec::with_capacity(len);
+                    let len = self.next_u16_or_u32()? as usize;
+                    let mut v = Vec::with_capacity(self.capacity(len, 1));
                     self.reader.read_to(&mut v, len)?;
                     ConstantItem::String(String::from_utf8(v)
                         .map_err(|_| "constant string is not valid utf-8 format".to_owned())?)
@@ -76,22 +100,49 @@ impl Loader<'_> {
     }
 
     fn load_class(&mut self) -> Result<Class> {
-        let name_index = self.reader.next_u16()?;
+        let name_index = self.next_u16_or_u32()?;
         let class_name = self.load_string_constant(name_index)?;
+
+        // implemented traits are added in version 1.2
+        let mut traits = Vec::new();
+        if self.minor >= 2 {
+            let trait_count = self.reader.next_u16()? as usize;
+            traits.reserve(trait_count);
+            for _ in 0 .. trait_count {
+                let trait_index = self.next_u16_or_u32()?;
+                traits.push(self.load_string_constant(trait_index)?);
+            }
+        }
         
         let method_count = self.reader.next_u16()? as usize;
         let mut methods = HashMap::with_capacity(method_count);
+        let mut getters = HashMap::new();
+        let mut setters = HashMap::new();
         for _ in 0 .. method_count {
-            let method = self.load_method(&class_name)?;
-            methods.insert(method.name.clone(), method);
+            let (kind, method) = self.load_method(&class_name)?;
+            let map = match kind {
+                METHOD_KIND_NORMAL => &mut methods,
+                METHOD_KIND_GETTER => &mut getters,
+                METHOD_KIND_SETTER => &mut setters,
+                other => return Err(format!("unknown method kind: {other}"))
+            };
+            map.insert(method.name.clone(), method);
         }
         
-        Ok(Class::new(class_name, methods))
+        Ok(Class::new(class_name, traits, methods, getters, setters))
     }
 
-    fn load_method(&mut self, class_name: &str) -> Result<Method> {
-        let Function {name, params, max_locals, code} = self.load_function()?;
-        Ok(Method::new(class_name.to_owned(), name, params, max_locals, code))
+    fn load_method(&mut self, class_name: &str) -> Result<(u8, Method)> {
+        let name_idx = self.next_u16_or_u32()?;
+        let name = self.load_string_constant(name_idx)?;
+        // method kind is added in version 1.1
+        let kind = if self.minor >= 1 {
+            self.reader.next_u8()?
+        } else {
+            METHOD_KIND_NORMAL
+        };
+        let Function {name, params, max_locals, max_stack, code, debug_info} = self.load_callable(name)?;
+        Ok((kind, Method::new(class_name.to_owned(), name, params, max_locals, max_stack, code, debug_info)))
     }
     
     fn load_functions(&mut self) -> Result<()> {
@@ -105,17 +156,89 @@ impl Loader<'_> {
     }
     
     fn load_function(&mut self) -> Result<Function> {
-        let name_idx = self.reader.next_u16()?;
+        let name_idx = self.next_u16_or_u32()?;
         let name = self.load_string_constant(name_idx)?;
+        self.load_callable(name)
+    }
+
+    fn load_callable(&mut self, name: String) -> Result<Function> {
         let params = self.reader.next_u8()?;
-        let max_locals = self.reader.next_u8()?;
-        let code_len = self.reader.next_u16()? as usize;
-        let mut code = Vec::with_capacity(code_len);
+        // max locals is u16 from version 1.8
+        let max_locals = if self.minor >= 8 { self.reader.next_u16()? } else { self.reader.next_u8()? as u16 };
+        // max stack is added in version 1.9
+        let declared_max_stack = if self.minor >= 9 { Some(self.reader.next_u16()?) } else { None };
+        // code length is u32 from version 1.8
+        let code_len = if self.minor >= 8 { self.reader.next_u32()? as usize } else { self.reader.next_u16()? as usize };
+        let mut code = Vec::with_capacity(self.capacity(code_len, 1));
         self.reader.read_to(&mut code, code_len)?;
-        Ok(Function::new(name, params, max_locals, code))
+        // computed for older files, invalid code is reported by the verifier
+        let max_stack = declared_max_stack.unwrap_or_else(|| {
+            max_stack(&code).map_or(0, |depth| depth.min(u16::MAX as usize) as u16)
+        });
+        let debug_info = self.load_attributes()?;
+        Ok(Function::new(name, params, max_locals, max_stack, code, debug_info))
+    }
+
+    // attributes are added in version 1.7, unknown attributes are skipped
+    fn load_attributes(&mut self) -> Result<DebugInfo> {
+        let mut debug_info = DebugInfo::default();
+        if self.minor < 7 {
+            return Ok(debug_info);
+        }
+
+        let attribute_count = self.reader.next_u16()?;
+        for _ in 0 .. attribute_count {
+            let tag = self.reader.next_u8()?;
+            let len = self.reader.next_u32()? as usize;
+            let end = self.reader.offset() + len;
+            if tag == ATTRIBUTE_LINE_NUMBER_TABLE {
+                let count = self.next_u16_or_u32()? as usize;
+                // u16 start_pc before 1.8, line & column
+                let mut line_numbers = Vec::with_capacity(self.capacity(count, 10));
+                for _ in 0 .. count {
+                    let start_pc = self.next_u16_or_u32()?;
+                    let line = self.reader.next_u32()?;
+                    let column = self.reader.next_u32()?;
+                    line_numbers.push(LineNumber { start_pc, line, column });
+                }
+                debug_info.line_numbers = line_numbers;
+            } else if tag == ATTRIBUTE_LOCAL_VARIABLE_TABLE {
+                let count = self.next_u16_or_u32()? as usize;
+                // u16 start_pc, end_pc & name_index, u8 slot before 1.8
+                let mut local_variables = Vec::with_capacity(self.capacity(count, 7));
+                for _ in 0 .. count {
+                    let start_pc = self.next_u16_or_u32()?;
+                    let end_pc = self.next_u16_or_u32()?;
+                    let slot = if self.minor >= 8 { self.reader.next_u16()? } else { self.reader.next_u8()? as u16 };
+                    let name_index = self.next_u16_or_u32()?;
+                    let name = self.load_string_constant(name_index)?;
+                    local_variables.push(LocalVariable { start_pc, end_pc, slot, name });
+                }
+                debug_info.local_variables = local_variables;
+            }
+            if self.reader.offset() > end {
+                return Err(format!("attribute: {tag} is longer than its length: {len}"));
+            }
+            self.reader.skip(end - self.reader.offset())?;
+        }
+        Ok(debug_info)
     }
     
-    fn load_string_constant(&mut self, idx: u16) -> Result<String> {
+    // counts are read from the file, the entries must fit in the rest of it before the memory is reserved
+    fn capacity(&self, count: usize, min_entry_len: usize) -> usize {
+        count.min(self.reader.remaining() / min_entry_len)
+    }
+
+    // constant indexes, lengths, counts & pcs are widened from u16 to u32 in version 1.8
+    fn next_u16_or_u32(&mut self) -> Result<u32> {
+        if self.minor >= 8 {
+            self.reader.next_u32()
+        } else {
+            self.reader.next_u16().map(|v| v as u32)
+        }
+    }
+
+    fn load_string_constant(&mut self, idx: u32) -> Result<String> {
         let Some(cp_item) = self.cp.get(idx as usize) else {
             return Err(format!("no constant pool item exists for index: {idx}"));
         };
@@ -126,4 +249,38 @@ impl Loader<'_> {
             ConstantItem::String(s) => Ok(s.clone())
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::constant::*;
+    use crate::loader::Loader;
+    use crate::opcode::OP_RETURN;
+
+    // the entry returns without a return value
+    fn invalid_program() -> Vec<u8> {
+        let mut bytes = MAGIC.as_bytes().to_vec();
+        bytes.extend_from_slice(&[CURRENT_VERSION_MINOR, CURRENT_VERSION_MAJOR, 0, 0]);
+        // constant pool: the entry name
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.push(CONSTANT_STRING);
+        bytes.extend_from_slice(&(ENTRY_NAME.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(ENTRY_NAME.as_bytes());
+        // no class, one function: name, params, max_locals, max_stack, code, attributes
+        bytes.extend_from_slice(&[0, 0, 1, 0]);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.push(OP_RETURN);
+        bytes.extend_from_slice(&[0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn test_load_unverified() {
+        let bytes = invalid_program();
+        let program = Loader::new(&bytes).load_unverified().unwrap();
+        assert_eq!(program.functions[ENTRY_NAME].code, [OP_RETURN]);
+        let e = Loader::new(&bytes).load().err().unwrap();
+        assert!(e.contains("`return` has no return value"), "{e}");
+    }
+}
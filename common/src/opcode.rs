@@ -1,3 +1,6 @@
+use crate::reader::LEReader;
+use crate::Result;
+
 pub const OP_CONST_NULL: u8 = 1;
 pub const OP_CONST_TRUE: u8 = 2;
 pub const OP_CONST_FALSE: u8 = 3;
@@ -53,4 +56,130 @@ pub const OP_GET_MEMBER: u8 = 35;
 
 pub const OP_DUP: u8 = 36;
 
-pub const OP_DEF_GLOBAL: u8 = 37;
\ No newline at end of file
+pub const OP_DEF_GLOBAL: u8 = 37;
+
+pub const OP_IS: u8 = 38;
+
+pub const OP_NEW_TUPLE: u8 = 39;
+
+pub const OP_UNPACK: u8 = 40;
+
+pub const OP_RANGE: u8 = 41;
+
+pub const OP_ITER_NEXT: u8 = 42;
+
+pub const OP_INDEX: u8 = 43;
+
+pub const OP_SLICE: u8 = 44;
+
+pub const OP_DEFER: u8 = 45;
+
+pub const OP_ASSERT: u8 = 46;
+
+// prefix, the operand of the next instruction is widened: u8 local slot to u16,
+// u16 constant index or jump target to u32, added in version 1.8
+pub const OP_WIDE: u8 = 47;
+
+// the name in `bytecode-format.txt`, for error messages
+pub fn opcode_name(opcode: u8) -> &'static str {
+    match opcode {
+        OP_CONST_NULL => "const_null",
+        OP_CONST_TRUE => "const_true",
+        OP_CONST_FALSE => "const_false",
+        OP_LCONST_M1 => "lconst_m1",
+        OP_LCONST_0 => "lconst_0",
+        OP_LCONST_1 => "lconst_1",
+        OP_LCONST_2 => "lconst_2",
+        OP_LCONST_3 => "lconst_3",
+        OP_LCONST_4 => "lconst_4",
+        OP_LCONST_5 => "lconst_5",
+        OP_LDC => "ldc",
+        OP_NEG => "neg",
+        OP_ADD => "add",
+        OP_SUB => "sub",
+        OP_MUL => "mul",
+        OP_DIV => "div",
+        OP_NOT => "not",
+        OP_CMP_EQ => "cmp_eq",
+        OP_CMP_BANGEQ => "cmp_bangeq",
+        OP_CMP_GT => "cmp_gt",
+        OP_CMP_LT => "cmp_lt",
+        OP_CMP_GTEQ => "cmp_gteq",
+        OP_CMP_LTEQ => "cmp_lteq",
+        OP_IF => "if",
+        OP_IF_NOT => "if_not",
+        OP_GOTO => "goto",
+        OP_INVOKE => "invoke",
+        OP_RETURN => "return",
+        OP_POP => "pop",
+        OP_SET_GLOBAL => "set_global",
+        OP_GET_GLOBAL => "get_global",
+        OP_SET_LOCAL => "set_local",
+        OP_GET_LOCAL => "get_local",
+        OP_SET_FIELD => "set_field",
+        OP_GET_MEMBER => "get_member",
+        OP_DUP => "dup",
+        OP_DEF_GLOBAL => "def_global",
+        OP_IS => "is",
+        OP_NEW_TUPLE => "new_tuple",
+        OP_UNPACK => "unpack",
+        OP_RANGE => "range",
+        OP_ITER_NEXT => "iter_next",
+        OP_INDEX => "index",
+        OP_SLICE => "slice",
+        OP_DEFER => "defer",
+        OP_ASSERT => "assert",
+        OP_WIDE => "wide",
+        _ => "unknown"
+    }
+}
+
+// length of the instruction at the start of `code`, including the opcode,
+// none if the opcode is unknown or the instruction is truncated
+pub fn instruction_len(code: &[u8]) -> Option<usize> {
+    if *code.first()? == OP_WIDE {
+        let len = match *code.get(1)? {
+            OP_SET_LOCAL | OP_GET_LOCAL => 4,
+            OP_LDC | OP_IF | OP_IF_NOT | OP_GOTO | OP_SET_GLOBAL | OP_GET_GLOBAL | OP_SET_FIELD | OP_GET_MEMBER
+            | OP_DEF_GLOBAL | OP_IS | OP_ITER_NEXT | OP_DEFER => 6,
+            // u32 description, u8 operand count, u32 name of each operand
+            OP_ASSERT => 7 + 4 * *code.get(6)? as usize,
+            _ => return None
+        };
+        return (len <= code.len()).then_some(len);
+    }
+
+    let len = match *code.first()? {
+        OP_CONST_NULL ..= OP_LCONST_5 | OP_NEG ..= OP_CMP_LTEQ | OP_RETURN | OP_POP | OP_DUP | OP_INDEX | OP_SLICE => 1,
+        OP_INVOKE | OP_SET_LOCAL | OP_GET_LOCAL | OP_NEW_TUPLE | OP_UNPACK | OP_RANGE => 2,
+        OP_LDC | OP_IF | OP_IF_NOT | OP_GOTO | OP_SET_GLOBAL | OP_GET_GLOBAL | OP_SET_FIELD | OP_GET_MEMBER
+        | OP_DEF_GLOBAL | OP_IS | OP_ITER_NEXT | OP_DEFER => 3,
+        // u16 description, u8 operand count, u16 name of each operand
+        OP_ASSERT => 4 + 2 * *code.get(3)? as usize,
+        _ => return None
+    };
+    (len <= code.len()).then_some(len)
+}
+
+// instructions whose u16 (u32 after `WIDE`) arg is an index in the code array
+pub fn is_jump(opcode: u8) -> bool {
+    matches!(opcode, OP_IF | OP_IF_NOT | OP_GOTO | OP_ITER_NEXT | OP_DEFER)
+}
+
+// constant index or jump target, u16 or u32 after `WIDE`
+pub fn next_index(reader: &mut LEReader, wide: bool) -> Result<usize> {
+    if wide {
+        reader.next_u32().map(|v| v as usize)
+    } else {
+        reader.next_u16().map(|v| v as usize)
+    }
+}
+
+// local variable slot, u8 or u16 after `WIDE`
+pub fn next_slot(reader: &mut LEReader, wide: bool) -> Result<usize> {
+    if wide {
+        reader.next_u16().map(|v| v as usize)
+    } else {
+        reader.next_u8().map(|v| v as usize)
+    }
+}
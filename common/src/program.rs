@@ -3,12 +3,20 @@ use crate::constant::ConstantItem;
 
 pub struct Class {
     pub name: String,
-    pub methods: HashMap<String, Method>
+    pub traits: Vec<String>,
+    pub methods: HashMap<String, Method>,
+    pub getters: HashMap<String, Method>,
+    pub setters: HashMap<String, Method>
 }
 
 impl Class {
-    pub fn new(name: String, methods: HashMap<String, Method>) -> Self {
-        Self { name, methods }
+    pub fn new(name: String
+               , traits: Vec<String>
+               , methods: HashMap<String, Method>
+               , getters: HashMap<String, Method>
+               , setters: HashMap<String, Method>
+    ) -> Self {
+        Self { name, traits, methods, getters, setters }
     }
 }
 
@@ -16,32 +24,83 @@ pub struct Method {
     pub class_name: String,
     pub name: String,
     pub params: u8, // not include 'this'
-    pub max_locals: u8,
-    pub code: Vec<u8>
+    pub max_locals: u16,
+    pub max_stack: u16,// depth of the operand stack above the locals
+    pub code: Vec<u8>,
+    pub debug_info: DebugInfo
 }
 
 impl Method {
-    pub fn new(class_name: String, name: String, params: u8, max_locals: u8, code: Vec<u8>) -> Self {
-        Self { class_name, name, params, max_locals, code }
+    pub fn new(class_name: String, name: String, params: u8, max_locals: u16, max_stack: u16, code: Vec<u8>, debug_info: DebugInfo) -> Self {
+        Self { class_name, name, params, max_locals, max_stack, code, debug_info }
     }
 }
 
 pub struct Function {
     pub name: String,
     pub params: u8,
-    pub max_locals: u8,
-    pub code: Vec<u8>
+    pub max_locals: u16,
+    pub max_stack: u16,// depth of the operand stack above the locals
+    pub code: Vec<u8>,
+    pub debug_info: DebugInfo
 }
 
 impl Function {
-    pub fn new(name: String, params: u8, max_locals: u8, code: Vec<u8>) -> Self {
-        Self { name, params, max_locals, code }
+    pub fn new(name: String, params: u8, max_locals: u16, max_stack: u16, code: Vec<u8>, debug_info: DebugInfo) -> Self {
+        Self { name, params, max_locals, max_stack, code, debug_info }
+    }
+}
+
+// code from `start_pc` to the next entry's is generated from the source at line:column
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineNumber {
+    pub start_pc: u32,
+    pub line: u32,
+    pub column: u32
+}
+
+// the local variable in `slot` is named `name` from `start_pc` (the instruction defining it) until `end_pc`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalVariable {
+    pub start_pc: u32,
+    pub end_pc: u32,
+    pub slot: u16,
+    pub name: String
+}
+
+// empty if the bytecode is compiled without debug info
+#[derive(Default)]
+pub struct DebugInfo {
+    pub line_numbers: Vec<LineNumber>,
+    pub local_variables: Vec<LocalVariable>
+}
+
+impl DebugInfo {
+    // the source position of the instruction at `pc`
+    pub fn line_number(&self, pc: usize) -> Option<(u32, u32)> {
+        let idx = self.line_numbers.partition_point(|v| v.start_pc as usize <= pc);
+        if idx == 0 {
+            return None;
+        }
+        let v = &self.line_numbers[idx - 1];
+        Some((v.line, v.column))
+    }
+
+    // the local variables live at `pc`
+    pub fn local_variables(&self, pc: usize) -> impl Iterator<Item = &LocalVariable> {
+        self.local_variables.iter().filter(move |v| v.start_pc as usize <= pc && pc < v.end_pc as usize)
+    }
+
+    // name of the local variable in `slot` at `pc`
+    pub fn local_variable_name(&self, slot: u16, pc: usize) -> Option<&str> {
+        self.local_variables(pc).find(|v| v.slot == slot).map(|v| v.name.as_str())
     }
 }
 
 pub struct Program {
     pub minor: u8,
     pub major: u8,
+    pub source_file: Option<String>,// none if the debug info is stripped
     pub constant_pool: Vec<ConstantItem>,
     pub classes: HashMap<String, Class>,
     pub functions: HashMap<String, Function>
@@ -50,10 +109,11 @@ pub struct Program {
 impl Program {
     pub fn new(minor: u8
                , major: u8
+               , source_file: Option<String>
                , constant_pool: Vec<ConstantItem>
                , classes: HashMap<String, Class>
                , functions: HashMap<String, Function>
     ) -> Self {
-        Self { minor, major, constant_pool, classes, functions }
+        Self { minor, major, source_file, constant_pool, classes, functions }
     }
 }
\ No newline at end of file
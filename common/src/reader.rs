@@ -69,6 +69,21 @@ impl LEReader<'_> {
         }
     }
     
+    pub fn skip(&mut self, len: usize) -> Result<()> {
+        if self.offset + len > self.data.len() {
+            Err(err_msg())
+        } else {
+            self.offset += len;
+            Ok(())
+        }
+    }
+
+    // bytes after the offset
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
     #[inline]
     pub fn offset(&self) -> usize {
         self.offset
@@ -0,0 +1,314 @@
+use crate::constant::ConstantItem;
+use crate::opcode::*;
+use crate::program::Program;
+use crate::reader::LEReader;
+use crate::Result;
+
+// checks the code of every function & method before it runs, so that the vm can trust it:
+// the opcodes are known and complete, jumps land on instructions, local slots are below `max_locals`,
+// constant indexes refer to the right type, and the operand stack never underflows.
+//
+// the depth of the operand stack is tracked along every path, it must be the same where paths meet.
+// a deferred block starts with an empty stack of its own, it runs on top of the stack of `return` or of an error,
+// so the deepest the stack can get is the depth of the body plus the depth of the deferred blocks
+
+pub fn verify(program: &Program) -> Result<()> {
+    let cp = &program.constant_pool;
+    for func in program.functions.values() {
+        let code = Code { code: &func.code, max_locals: func.max_locals, cp, wide: program.minor >= 8 };
+        code.verify(func.params as usize, func.max_stack)
+            .map_err(|e| format!("invalid bytecode in function: {}, {e}", func.name))?;
+    }
+    for class in program.classes.values() {
+        for method in class.methods.values().chain(class.getters.values()).chain(class.setters.values()) {
+            let code = Code { code: &method.code, max_locals: method.max_locals, cp, wide: program.minor >= 8 };
+            // 'this' is the local after the params
+            code.verify(method.params as usize + 1, method.max_stack)
+                .map_err(|e| format!("invalid bytecode in method: {}.{}, {e}", class.name, method.name))?;
+        }
+    }
+    Ok(())
+}
+
+// the deepest the operand stack of the code can get, the code isn't verified
+pub fn max_stack(code: &[u8]) -> Result<usize> {
+    Code { code, max_locals: u16::MAX, cp: &[], wide: true }.max_depth(false)
+}
+
+struct Code<'a> {
+    code: &'a [u8],
+    max_locals: u16,
+    cp: &'a [ConstantItem],
+    wide: bool// `WIDE` is allowed since version 1.8
+}
+
+// the operand of the instruction after the opcode: constant index, jump target, local slot or count
+struct Instruction {
+    opcode: u8,
+    arg: usize,
+    operands: usize,// values shown by `assert`
+    len: usize
+}
+
+// the depth of the operand stack before an instruction, and whether it's in a deferred block
+#[derive(Clone, Copy, PartialEq)]
+struct State {
+    depth: usize,
+    deferred: bool
+}
+
+impl Code<'_> {
+    fn verify(&self, locals: usize, max_stack: u16) -> Result<()> {
+        if locals > self.max_locals as usize {
+            return Err(format!("max locals: {} is less than the params", self.max_locals));
+        }
+        let depth = self.max_depth(true)?;
+        if depth > max_stack as usize {
+            return Err(format!("max stack: {max_stack} is less than the operand stack depth: {depth}"));
+        }
+        Ok(())
+    }
+
+    // the operands are checked if `check`
+    fn max_depth(&self, check: bool) -> Result<usize> {
+        // instruction boundaries
+        let mut starts = vec![false; self.code.len()];
+        let mut pc = 0;
+        while pc < self.code.len() {
+            let ins = self.decode(pc)?;
+            starts[pc] = true;
+            if check {
+                self.check_operands(pc, &ins)?;
+            }
+            pc += ins.len;
+        }
+
+        let mut flow = Flow { starts, states: vec![None; self.code.len()], pending: Vec::new() };
+        flow.enter(0, State { depth: 0, deferred: false }, 0)?;
+        let (mut max_body, mut max_deferred) = (0, 0);
+        while let Some(pc) = flow.pending.pop() {
+            let state = flow.states[pc].unwrap();
+            let ins = self.decode(pc)?;
+            let (pops, pushes) = stack_effect(&ins);
+            if state.depth < pops {
+                return Err(format!("pc {pc}: stack underflow, `{}` pops {pops} operands, the stack has {}", opcode_name(ins.opcode), state.depth));
+            }
+            let after = State { depth: state.depth - pops + pushes, ..state };
+            let max = if state.deferred { &mut max_deferred } else { &mut max_body };
+            *max = (*max).max(state.depth).max(after.depth);
+            let next = pc + ins.len;
+            match ins.opcode {
+                OP_GOTO => flow.enter(ins.arg, after, pc)?,
+                OP_IF | OP_IF_NOT => {
+                    flow.enter(next, after, pc)?;
+                    flow.enter(ins.arg, after, pc)?;
+                }
+                // the iterator stays, the next value is pushed unless it's exhausted
+                OP_ITER_NEXT => {
+                    flow.enter(next, State { depth: state.depth + 1, ..state }, pc)?;
+                    flow.enter(ins.arg, state, pc)?;
+                }
+                // the deferred block is right after the instruction
+                OP_DEFER => {
+                    flow.enter(ins.arg, after, pc)?;
+                    flow.enter(next, State { depth: 0, deferred: true }, pc)?;
+                }
+                // the return value is popped, deferred blocks leave the stack as it was
+                OP_RETURN => if state.depth == 0 && !state.deferred {
+                    return Err(format!("pc {pc}: stack underflow, `return` has no return value"));
+                }
+                // always raises an error
+                OP_ASSERT => (),
+                _ => flow.enter(next, after, pc)?
+            }
+        }
+        Ok(max_body + max_deferred)
+    }
+
+    fn decode(&self, pc: usize) -> Result<Instruction> {
+        let code = &self.code[pc..];
+        let Some(len) = instruction_len(code) else {
+            return match code[0] {
+                OP_WIDE => Err(format!("pc {pc}: `wide` before an opcode without index operand, or a truncated instruction")),
+                opcode => Err(format!("pc {pc}: unknown opcode: {opcode}, or a truncated instruction"))
+            };
+        };
+        let wide = code[0] == OP_WIDE;
+        if wide && !self.wide {
+            return Err(format!("pc {pc}: `wide` is not supported before version 1.8"));
+        }
+
+        let mut reader = LEReader::new(code);
+        reader.skip(if wide { 2 } else { 1 })?;
+        let opcode = code[usize::from(wide)];
+        let arg = match opcode {
+            OP_SET_LOCAL | OP_GET_LOCAL => next_slot(&mut reader, wide)?,
+            OP_INVOKE | OP_NEW_TUPLE | OP_UNPACK | OP_RANGE => reader.next_u8()? as usize,
+            _ if len > 1 + usize::from(wide) => next_index(&mut reader, wide)?,
+            _ => 0
+        };
+        let operands = if opcode == OP_ASSERT { reader.next_u8()? as usize } else { 0 };
+        Ok(Instruction { opcode, arg, operands, len })
+    }
+
+    fn check_operands(&self, pc: usize, ins: &Instruction) -> Result<()> {
+        match ins.opcode {
+            OP_SET_LOCAL | OP_GET_LOCAL if ins.arg >= self.max_locals as usize => {
+                Err(format!("pc {pc}: local slot: {} is out of max locals: {}", ins.arg, self.max_locals))
+            }
+            opcode if is_jump(opcode) && ins.arg >= self.code.len() => {
+                Err(format!("pc {pc}: jump target: {} is out of the code, length: {}", ins.arg, self.code.len()))
+            }
+            OP_LDC if ins.arg >= self.cp.len() => Err(format!("pc {pc}: constant index: {} is out of the constant pool", ins.arg)),
+            OP_DEF_GLOBAL | OP_SET_GLOBAL | OP_GET_GLOBAL | OP_SET_FIELD | OP_GET_MEMBER | OP_IS => self.check_string(pc, ins.arg),
+            OP_ASSERT => {
+                // the description, the operand count, and the names of the operands
+                self.check_string(pc, ins.arg)?;
+                let wide = self.code[pc] == OP_WIDE;
+                let mut reader = LEReader::new(&self.code[pc..pc + ins.len]);
+                reader.skip(if wide { 7 } else { 4 })?;
+                for _ in 0..ins.operands {
+                    self.check_string(pc, next_index(&mut reader, wide)?)?;
+                }
+                Ok(())
+            }
+            _ => Ok(())
+        }
+    }
+
+    fn check_string(&self, pc: usize, idx: usize) -> Result<()> {
+        match self.cp.get(idx) {
+            Some(ConstantItem::String(_)) => Ok(()),
+            Some(_) => Err(format!("pc {pc}: constant index: {idx} should refer to a string")),
+            None => Err(format!("pc {pc}: constant index: {idx} is out of the constant pool"))
+        }
+    }
+}
+
+struct Flow {
+    starts: Vec<bool>,// instruction boundaries
+    states: Vec<Option<State>>,
+    pending: Vec<usize>
+}
+
+impl Flow {
+    // `from` reaches `pc` with the state
+    fn enter(&mut self, pc: usize, state: State, from: usize) -> Result<()> {
+        if pc >= self.starts.len() {
+            // falling off the end returns, as `return`, jump targets are checked to be in the code
+            return match state.depth {
+                0 if !state.deferred => Err(format!("pc {from}: the code ends without a return value")),
+                _ => Ok(())
+            };
+        }
+        if !self.starts[pc] {
+            return Err(format!("pc {from}: jump target: {pc} is not the start of an instruction"));
+        }
+        match self.states[pc] {
+            None => {
+                self.states[pc] = Some(state);
+                self.pending.push(pc);
+                Ok(())
+            }
+            Some(prev) if prev == state => Ok(()),
+            Some(prev) => Err(format!("pc {pc}: the operand stack depth is {} or {} on different paths", prev.depth, state.depth))
+        }
+    }
+}
+
+// operands popped & pushed by the instruction, `invoke` pushes the return value
+fn stack_effect(ins: &Instruction) -> (usize, usize) {
+    match ins.opcode {
+        OP_CONST_NULL ..= OP_LDC | OP_GET_GLOBAL | OP_GET_LOCAL => (0, 1),
+        OP_NEG | OP_NOT | OP_GET_MEMBER | OP_IS => (1, 1),
+        OP_ADD ..= OP_DIV | OP_CMP_EQ ..= OP_CMP_LTEQ | OP_RANGE | OP_INDEX => (2, 1),
+        OP_SLICE => (3, 1),
+        OP_IF | OP_IF_NOT | OP_POP | OP_DEF_GLOBAL | OP_SET_GLOBAL | OP_SET_LOCAL => (1, 0),
+        OP_SET_FIELD => (2, 0),
+        OP_DUP => (1, 2),
+        OP_INVOKE => (ins.arg + 1, 1),
+        OP_NEW_TUPLE => (ins.arg, 1),
+        OP_UNPACK => (1, ins.arg),
+        OP_ITER_NEXT => (1, 1),
+        // the operands and the message
+        OP_ASSERT => (ins.operands + 1, 0),
+        _ => (0, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ahash::{HashMap, HashMapExt};
+    use crate::constant::{ConstantItem, ENTRY_NAME};
+    use crate::opcode::*;
+    use crate::program::{DebugInfo, Function, Program};
+    use crate::verifier::{max_stack, verify};
+
+    // the entry with the code and a max stack of 4, constant 0 is a string and 1 is a long
+    fn verify_entry(code: Vec<u8>, max_locals: u16) -> Result<(), String> {
+        let mut functions = HashMap::new();
+        functions.insert(ENTRY_NAME.to_owned(), Function::new(ENTRY_NAME.to_owned(), 0, max_locals, 4, code, DebugInfo::default()));
+        let cp = vec![ConstantItem::String("a".to_owned()), ConstantItem::Long(1)];
+        verify(&Program::new(9, 1, None, cp, HashMap::new(), functions))
+    }
+
+    fn assert_err(code: Vec<u8>, max_locals: u16, msg: &str) {
+        match verify_entry(code, max_locals) {
+            Ok(_) => panic!("expected error: {msg}"),
+            Err(e) => assert!(e.contains(msg), "{e}")
+        }
+    }
+
+    #[test]
+    fn test_ok() {
+        // var a = 1; for (i in 0..a) { if (i) { break; } } defer a;
+        assert_eq!(verify_entry(vec![
+            OP_LDC, 1, 0, OP_SET_LOCAL, 0,
+            OP_LCONST_0, OP_GET_LOCAL, 0, OP_RANGE, 0,
+            OP_ITER_NEXT, 26, 0, OP_SET_LOCAL, 1, OP_GET_LOCAL, 1, OP_IF_NOT, 23, 0, OP_GOTO, 26, 0,
+            OP_GOTO, 10, 0,
+            OP_POP,
+            OP_DEFER, 34, 0, OP_GET_LOCAL, 0, OP_POP, OP_RETURN,
+            OP_CONST_NULL, OP_RETURN
+        ], 2), Ok(()));
+        assert_eq!(verify_entry(vec![OP_WIDE, OP_LDC, 1, 0, 0, 0, OP_RETURN], 0), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_code() {
+        assert_err(vec![200, OP_RETURN], 0, "unknown opcode: 200");
+        assert_err(vec![OP_LDC, 1], 0, "truncated");
+        assert_err(vec![OP_WIDE, OP_POP], 0, "`wide`");
+        assert_err(vec![OP_GET_LOCAL, 1, OP_RETURN], 1, "local slot: 1 is out of max locals: 1");
+        assert_err(vec![OP_GET_GLOBAL, 1, 0, OP_RETURN], 0, "constant index: 1 should refer to a string");
+        assert_err(vec![OP_LDC, 2, 0, OP_RETURN], 0, "constant index: 2 is out of the constant pool");
+        // into the operand of `ldc`
+        assert_err(vec![OP_GOTO, 4, 0, OP_LDC, 1, 0, OP_RETURN], 0, "jump target: 4 is not the start of an instruction");
+        assert_err(vec![OP_GOTO, 9, 0, OP_CONST_NULL, OP_RETURN], 0, "jump target: 9 is out of the code");
+    }
+
+    #[test]
+    fn test_stack_depth() {
+        assert_err(vec![OP_LCONST_1, OP_ADD, OP_RETURN], 0, "pc 1: stack underflow, `add` pops 2 operands, the stack has 1");
+        assert_err(vec![OP_RETURN], 0, "`return` has no return value");
+        assert_err(vec![OP_LCONST_1, OP_POP], 0, "the code ends without a return value");
+        // `if` skips a push
+        assert_err(vec![OP_CONST_TRUE, OP_IF, 5, 0, OP_LCONST_1, OP_CONST_NULL, OP_RETURN], 0, "pc 5: the operand stack depth is");
+        // a deferred block can't pop the stack of `return`
+        assert_err(vec![OP_DEFER, 5, 0, OP_POP, OP_RETURN, OP_CONST_NULL, OP_RETURN], 0, "pc 3: stack underflow");
+    }
+
+    #[test]
+    fn test_max_stack() {
+        assert_eq!(max_stack(&[OP_CONST_NULL, OP_RETURN]), Ok(1));
+        // `1 + (1 + 1)`, the deepest after the third push
+        let code = [OP_LCONST_1, OP_LCONST_1, OP_LCONST_1, OP_ADD, OP_ADD, OP_RETURN];
+        assert_eq!(max_stack(&code), Ok(3));
+        // `defer { 1 + 1; }` runs on top of the return value
+        let code = [OP_DEFER, 8, 0, OP_LCONST_1, OP_LCONST_1, OP_ADD, OP_POP, OP_RETURN, OP_CONST_NULL, OP_RETURN];
+        assert_eq!(max_stack(&code), Ok(3));
+        // 5 operands are pushed before `new_tuple`
+        let code = [OP_LCONST_1, OP_LCONST_1, OP_LCONST_1, OP_LCONST_1, OP_LCONST_1, OP_NEW_TUPLE, 5, OP_RETURN];
+        assert_err(code.to_vec(), 0, "max stack: 4 is less than the operand stack depth: 5");
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt::Write;
+use common::json::Json;
+use common::line_column_info::LineColumnInfo;
+use compilerlib::ast::{ClassDecl, Expr, ExprKind, FuncDecl, Pattern, Program, Stmt, StmtKind, TraitDecl};
+use compilerlib::token::{Token, TokenKind};
+
+// `--emit=tokens` and `--emit=ast` of `charonc`, as readable text or one json value
+
+// line:column, the kind, and the value of identifiers & literals
+pub fn tokens_text(tokens: &[Token], source: &[u8]) -> String {
+    let line_column_info = LineColumnInfo::new(source);
+    let mut s = String::new();
+    for token in tokens {
+        let (line, column) = line_column_info.line_column_info(token.offset);
+        let _ = writeln!(s, "{line}:{column}  {:?}", token.kind);
+    }
+    s
+}
+
+pub fn tokens_json(tokens: &[Token], source: &[u8]) -> Json {
+    let line_column_info = LineColumnInfo::new(source);
+    Json::Array(tokens.iter().map(|token| {
+        let (line, column) = line_column_info.line_column_info(token.offset);
+        // name of the variant, without the value
+        let kind = format!("{:?}", token.kind);
+        let kind = kind.split('(').next().unwrap_or_default();
+        let value = match &token.kind {
+            TokenKind::Identifier(v) | TokenKind::String(v) => Json::string(v),
+            TokenKind::Long(v) => Json::Long(*v),
+            TokenKind::Double(v) => Json::Double(*v),
+            _ => Json::Null
+        };
+        Json::object([
+            ("kind", Json::string(kind)),
+            ("value", value),
+            ("offset", Json::Long(token.offset as i64)),
+            ("line", Json::Long(line as i64)),
+            ("column", Json::Long(column as i64))
+        ])
+    }).collect())
+}
+
+// nodes are objects with `kind` and `offset`, the children are named as the fields of the ast types
+pub fn ast_json(program: &Program) -> Json {
+    Json::object([
+        ("funcs", Json::Array(program.funcs.iter().map(func).collect())),
+        ("classes", Json::Array(program.classes.iter().map(class).collect())),
+        ("traits", Json::Array(program.traits.iter().map(trait_decl).collect()))
+    ])
+}
+
+fn func(func: &FuncDecl) -> Json {
+    Json::object([
+        ("name", Json::string(&func.name)),
+        ("params", Json::Array(func.params.iter().map(|v| Json::string(v)).collect())),
+        ("body", stmts(&func.body)),
+        ("offset", Json::Long(func.offset as i64))
+    ])
+}
+
+fn class(class: &ClassDecl) -> Json {
+    Json::object([
+        ("name", Json::string(&class.name)),
+        ("traits", Json::Array(class.traits.iter().map(|v| Json::string(v)).collect())),
+        ("methods", Json::Array(class.methods.iter().map(func).collect())),
+        ("getters", Json::Array(class.getters.iter().map(func).collect())),
+        ("setters", Json::Array(class.setters.iter().map(func).collect())),
+        ("offset", Json::Long(class.offset as i64))
+    ])
+}
+
+fn trait_decl(t: &TraitDecl) -> Json {
+    Json::object([
+        ("name", Json::string(&t.name)),
+        ("required", Json::Array(t.required.iter().map(func).collect())),
+        ("provided", Json::Array(t.provided.iter().map(func).collect())),
+        ("offset", Json::Long(t.offset as i64))
+    ])
+}
+
+fn stmts(stmts: &[Stmt]) -> Json {
+    Json::Array(stmts.iter().map(stmt).collect())
+}
+
+fn node<const N: usize>(kind: &str, offset: usize, fields: [(&str, Json); N]) -> Json {
+    let mut entries = vec![("kind".to_owned(), Json::string(kind)), ("offset".to_owned(), Json::Long(offset as i64))];
+    entries.extend(fields.into_iter().map(|(k, v)| (k.to_owned(), v)));
+    Json::Object(entries)
+}
+
+fn opt_expr(e: &Option<Box<Expr>>) -> Json {
+    e.as_deref().map_or(Json::Null, expr)
+}
+
+fn stmt(stmt: &Stmt) -> Json {
+    let offset = stmt.offset;
+    match &stmt.kind {
+        StmtKind::VarDef(vardef) => node("VarDef", offset, [("pattern", pattern(&vardef.pattern)), ("init", opt_expr(&vardef.init))]),
+        StmtKind::Expr(e) => node("Expr", offset, [("expr", expr(e))]),
+        StmtKind::SetVar(setvar) => node("SetVar", offset, [
+            ("to", pattern(&setvar.to)),
+            ("op", Json::string(&format!("{:?}", setvar.op))),
+            ("value", expr(&setvar.value))
+        ]),
+        StmtKind::Setter(setter) => node("Setter", offset, [
+            ("owner", expr(&setter.owner)),
+            ("field", Json::string(&setter.field)),
+            ("op", Json::string(&format!("{:?}", setter.op))),
+            ("value", expr(&setter.value))
+        ]),
+        StmtKind::If(ifstmt) => node("If", offset, [("cond", expr(&ifstmt.cond)), ("then", stmts(&ifstmt.then)), ("els", stmts(&ifstmt.els))]),
+        StmtKind::While(while_stmt) => node("While", offset, [("cond", expr(&while_stmt.cond)), ("body", stmts(&while_stmt.body))]),
+        StmtKind::ForIn(for_in) => node("ForIn", offset, [
+            ("pattern", pattern(&for_in.pattern)),
+            ("iterable", expr(&for_in.iterable)),
+            ("body", stmts(&for_in.body))
+        ]),
+        StmtKind::Break => node("Break", offset, []),
+        StmtKind::Continue => node("Continue", offset, []),
+        StmtKind::Return(e) => node("Return", offset, [("expr", opt_expr(e))]),
+        StmtKind::Defer(e) => node("Defer", offset, [("expr", expr(e))]),
+        StmtKind::Assert(assert) => node("Assert", offset, [
+            ("cond", expr(&assert.cond)),
+            ("msg", opt_expr(&assert.msg)),
+            ("text", Json::string(&assert.text))
+        ]),
+        StmtKind::Block(inner) => node("Block", offset, [("stmts", stmts(inner))])
+    }
+}
+
+fn pattern(pattern: &Pattern) -> Json {
+    match pattern {
+        Pattern::Var(name) => Json::object([("kind", Json::string("Var")), ("name", Json::string(name))]),
+        Pattern::Tuple(patterns) => Json::object([
+            ("kind", Json::string("Tuple")),
+            ("patterns", Json::Array(patterns.iter().map(self::pattern).collect()))
+        ])
+    }
+}
+
+fn expr(e: &Expr) -> Json {
+    let offset = e.offset;
+    match &e.kind {
+        ExprKind::True => node("True", offset, []),
+        ExprKind::False => node("False", offset, []),
+        ExprKind::Null => node("Null", offset, []),
+        ExprKind::This => node("This", offset, []),
+        ExprKind::Long(v) => node("Long", offset, [("value", Json::Long(*v))]),
+        ExprKind::Double(v) => node("Double", offset, [("value", Json::Double(*v))]),
+        ExprKind::String(v) => node("String", offset, [("value", Json::string(v))]),
+        ExprKind::Binary(binary) => node("Binary", offset, [
+            ("left", expr(&binary.left)),
+            ("op", Json::string(&format!("{:?}", binary.op))),
+            ("right", expr(&binary.right))
+        ]),
+        ExprKind::Logic(logic) => node("Logic", offset, [
+            ("left", expr(&logic.left)),
+            ("op", Json::string(&format!("{:?}", logic.op))),
+            ("right", expr(&logic.right))
+        ]),
+        ExprKind::Unary(unary) => node("Unary", offset, [("op", Json::string(&format!("{:?}", unary.op))), ("expr", expr(&unary.expr))]),
+        ExprKind::Call(call) => node("Call", offset, [
+            ("owner", expr(&call.owner)),
+            ("args", Json::Array(call.args.iter().map(expr).collect()))
+        ]),
+        ExprKind::GetVar(name) => node("GetVar", offset, [("name", Json::string(name))]),
+        ExprKind::Getter(getter) => node("Getter", offset, [("owner", expr(&getter.owner)), ("member", Json::string(&getter.member))]),
+        ExprKind::Is(is) => node("Is", offset, [("expr", expr(&is.expr)), ("name", Json::string(&is.name))]),
+        ExprKind::Tuple(elements) => node("Tuple", offset, [("elements", Json::Array(elements.iter().map(expr).collect()))]),
+        ExprKind::Range(range) => node("Range", offset, [
+            ("start", expr(&range.start)),
+            ("end", expr(&range.end)),
+            ("inclusive", Json::Bool(range.inclusive))
+        ]),
+        ExprKind::Index(index) => node("Index", offset, [("owner", expr(&index.owner)), ("index", expr(&index.index))]),
+        ExprKind::Slice(slice) => node("Slice", offset, [
+            ("owner", expr(&slice.owner)),
+            ("start", opt_expr(&slice.start)),
+            ("end", opt_expr(&slice.end))
+        ])
+    }
+}
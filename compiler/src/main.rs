@@ -1,53 +1,248 @@
 use std::{env, fs};
 use std::env::current_dir;
 use std::error::Error;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::exit;
-use common::err_println;
-use compilerlib::code_gen::check_and_gen;
-use compilerlib::{lex, parse};
+use common::diagnostic::{set_error_format, Diagnostic, ErrorFormat};
+use common::json::Json;
+use common::loader::Loader;
+use compilerlib::{compile_with_warnings, lex, parse, Diagnostics, OptLevel, Options};
+use crate::emit::{ast_json, tokens_json, tokens_text};
+
+mod emit;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Emit {
+    Tokens,
+    Ast,
+    Asm,
+    Bytecode
+}
+
+impl Emit {
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "tokens" => Some(Self::Tokens),
+            "ast" => Some(Self::Ast),
+            "asm" => Some(Self::Asm),
+            "bytecode" => Some(Self::Bytecode),
+            _ => None
+        }
+    }
+}
+
+struct Args {
+    options: Options,
+    inputs: Vec<String>,// `-` is stdin
+    output: Option<String>,// `-` is stdout
+    emit: Emit,
+    json: bool,// `--emit-format=json`, only for tokens & ast
+    check: bool// stop after the semantic checks, nothing is written
+}
 
 fn main() {
-    let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        err_println("usage: charonc [charon source file path]");
+    let args = parse_args();
+    if args.output.is_some() && args.inputs.len() > 1 {
+        Diagnostic::error("`-o` can't be used with multiple input files".to_owned()).emit();
         exit(1);
     }
-
-    let sourcecode_path = unsafe {args.get_unchecked(1)};
-    
-    if let Err(e) = run(sourcecode_path) {
-        err_println(&format!("{e}"));
+    if args.json && !matches!(args.emit, Emit::Tokens | Emit::Ast) {
+        Diagnostic::error("`--emit-format=json` can only be used with `--emit=tokens` or `--emit=ast`".to_owned()).emit();
         exit(1);
     }
+
+    // the exit code is the error count of all the inputs
+    let mut error_count = 0;
+    for input in &args.inputs {
+        match run(input, &args) {
+            Ok(count) => error_count += count,
+            Err(e) => {
+                Diagnostic::error(e.to_string()).emit();
+                error_count += 1;
+            }
+        }
+    }
+    if error_count > 0 {
+        exit(error_count.min(u8::MAX as usize) as i32);
+    }
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        options: Options::default(),
+        inputs: Vec::new(),
+        output: None,
+        emit: Emit::Bytecode,
+        json: false,
+        check: false
+    };
+    let options = &mut args.options;
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--strip-asserts" => options.strip_asserts = true,
+            "--strip-debug-info" => options.debug_info = false,
+            "-O" => options.opt_level = OptLevel::Basic,
+            "--deny-warnings" => options.deny_warnings = true,
+            "--check" => args.check = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                exit(0);
+            }
+            "-o" => args.output = Some(iter.next().unwrap_or_else(|| usage())),
+            // `-W`/`-A` with a lint name, code or `warnings` for all
+            "-W" | "-A" => {
+                let lint = iter.next().unwrap_or_else(|| usage());
+                let known = if arg == "-W" { options.lints.warn(&lint) } else { options.lints.allow(&lint) };
+                if !known {
+                    Diagnostic::error(format!("unknown lint: `{lint}`")).emit();
+                    exit(1);
+                }
+            }
+            _ if arg.starts_with("--emit=") => match Emit::from_arg(&arg["--emit=".len()..]) {
+                Some(emit) => args.emit = emit,
+                None => usage()
+            }
+            _ if arg.starts_with("--emit-format=") => match &arg["--emit-format=".len()..] {
+                "text" => args.json = false,
+                "json" => args.json = true,
+                _ => usage()
+            }
+            _ if arg.starts_with("--error-format=") => match ErrorFormat::from_arg(&arg["--error-format=".len()..]) {
+                Some(format) => set_error_format(format),
+                None => usage()
+            }
+            _ if arg == "-" || !arg.starts_with('-') => args.inputs.push(arg),
+            _ => usage()
+        }
+    }
+    if args.inputs.is_empty() {
+        usage();
+    }
+    args
+}
+
+const USAGE: &str = "usage: charonc [-O] [-o output path] [--emit=tokens|ast|asm|bytecode] [--emit-format=text|json] [--check] \
+    [-W lint] [-A lint] [--deny-warnings] [--strip-asserts] [--strip-debug-info] [--error-format=human|json] [-h|--help] \
+    [charon source file paths, `-` for stdin]";
+
+// for invalid arguments, `--help` prints it to stdout
+fn usage() -> ! {
+    Diagnostic::error(USAGE.to_owned()).emit();
+    exit(1);
 }
 
-fn run(sourcecode_path: &str) -> Result<(), Box<dyn Error>> {
-    let bytes = match fs::read(sourcecode_path) {
-        Ok(v) => v,
-        Err(e) => {
-            err_println(&format!("failed to read source code file: {sourcecode_path}, with error: {e}"));
-            exit(1);
+// returns the error count of the input
+fn run(input: &str, args: &Args) -> Result<usize, Box<dyn Error>> {
+    let (name, bytes) = if input == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        ("<stdin>", bytes)
+    } else {
+        match fs::read(input) {
+            Ok(v) => (input, v),
+            Err(e) => return Err(format!("failed to read source code file: {input}, with error: {e}").into())
         }
     };
-    
-    let output_path = output_path(sourcecode_path)?;
-    
-    let tokens = lex(&bytes)?;
-    let program = parse(tokens, &bytes)?;
-    drop(bytes);
-    let bytecode = check_and_gen(&program)?;
-    fs::write(output_path, bytecode)?;
-    Ok(())
-}
-
-fn output_path(sourcecode_path: &str) -> Result<PathBuf, Box<dyn Error>> {
-    let path = Path::new(sourcecode_path);
+
+    if args.check {
+        return Ok(compile(name, &bytes, args).err().unwrap_or(0));
+    }
+
+    let output = match args.emit {
+        Emit::Tokens => {
+            let tokens = match lex(&bytes) {
+                Ok(v) => v,
+                Err(e) => return Ok(report(e, name))
+            };
+            if args.json {
+                json_output(name, "tokens", tokens_json(&tokens, &bytes))
+            } else {
+                text_output(name, tokens_text(&tokens, &bytes), args)
+            }
+        }
+        Emit::Ast => {
+            let program = match lex(&bytes).and_then(|tokens| parse(tokens, &bytes)) {
+                Ok(v) => v,
+                Err(e) => return Ok(report(e, name))
+            };
+            if args.json {
+                json_output(name, "ast", ast_json(&program))
+            } else {
+                text_output(name, format!("{program:#?}\n"), args)
+            }
+        }
+        Emit::Asm => {
+            let bytecode = match compile(name, &bytes, args) {
+                Ok(v) => v,
+                Err(count) => return Ok(count)
+            };
+            let program = Loader::new(&bytecode).load()?;
+            text_output(name, charonp::disassemble(&program)?, args)
+        }
+        Emit::Bytecode => match compile(name, &bytes, args) {
+            Ok(v) => v,
+            Err(count) => return Ok(count)
+        }
+    };
+
+    match output_path(input, args)? {
+        Some(path) => fs::write(path, output)?,
+        None => io::stdout().write_all(&output)?
+    }
+    Ok(0)
+}
+
+// prints the warnings, the error is the error count
+fn compile(name: &str, bytes: &[u8], args: &Args) -> Result<Vec<u8>, usize> {
+    let mut options = args.options.clone();
+    options.source_file = if name == "<stdin>" {
+        None
+    } else {
+        Path::new(name).file_name().map(|v| v.to_string_lossy().into_owned())
+    };
+    let (result, warnings) = compile_with_warnings(bytes, &options);
+    warnings.print(name);
+    result.map_err(|e| report(e, name))
+}
+
+fn report(errors: Diagnostics, name: &str) -> usize {
+    let count = errors.items.len();
+    errors.print(name);
+    count
+}
+
+// one json object per input file, in one line
+fn json_output(name: &str, key: &str, value: Json) -> Vec<u8> {
+    format!("{}\n", Json::object([("file", Json::string(name)), (key, value)])).into_bytes()
+}
+
+// the listings of multiple inputs are separated by a header
+fn text_output(name: &str, text: String, args: &Args) -> Vec<u8> {
+    if args.inputs.len() > 1 {
+        format!("==> {name} <==\n{text}\n").into_bytes()
+    } else {
+        text.into_bytes()
+    }
+}
+
+// `None` is stdout: `-o -`, the text emits, and the bytecode of stdin without `-o`,
+// otherwise the bytecode is written to `<stem>.charonbc` in the current directory
+fn output_path(input: &str, args: &Args) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if let Some(output) = &args.output {
+        return Ok((output != "-").then(|| PathBuf::from(output)));
+    }
+    if args.emit != Emit::Bytecode || input == "-" {
+        return Ok(None);
+    }
+
+    let path = Path::new(input);
     let Some(name) = path.file_stem() else {
-        return Err(format!("failed to get {sourcecode_path}'s name").into());
+        return Err(format!("failed to get {input}'s name").into());
     };
     let mut name = name.to_os_string();
     name.push(".charonbc");
     let curr_dir = current_dir()?;
-    Ok(curr_dir.join(name))
-}
\ No newline at end of file
+    Ok(Some(curr_dir.join(name)))
+}
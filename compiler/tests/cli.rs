@@ -0,0 +1,163 @@
+// the arguments of `charonc`: the inputs, `-o`, stdin and `--check`
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use common::loader::Loader;
+
+const HELLO: &str = "__println(\"hello\");\n";
+const INVALID: &str = "__println(1 +);\nvar = 2;\n";
+
+// an empty directory for the test, used as the current directory of `charonc`
+fn dir(name: &str) -> PathBuf {
+    let path: PathBuf = [env!("CARGO_TARGET_TMPDIR"), "cli", name].iter().collect();
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn charonc(dir: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_charonc"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_help() {
+    let dir = dir("help");
+    for arg in ["-h", "--help"] {
+        let output = charonc(&dir, &[arg], "");
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stdout).unwrap().starts_with("usage: charonc"));
+        assert!(output.stderr.is_empty());
+    }
+
+    let output = charonc(&dir, &["--unknown"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("usage: charonc"));
+}
+
+#[test]
+fn test_output() {
+    let dir = dir("output");
+    fs::write(dir.join("hello.charon"), HELLO).unwrap();
+
+    // next to the current directory by default
+    let output = charonc(&dir, &["hello.charon"], "");
+    assert!(output.status.success());
+    assert!(Loader::new(&fs::read(dir.join("hello.charonbc")).unwrap()).load().is_ok());
+
+    let output = charonc(&dir, &["-o", "out.bc", "hello.charon"], "");
+    assert!(output.status.success());
+    assert_eq!(fs::read(dir.join("out.bc")).unwrap(), fs::read(dir.join("hello.charonbc")).unwrap());
+
+    // `-o -` is stdout
+    let output = charonc(&dir, &["-o", "-", "hello.charon"], "");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, fs::read(dir.join("hello.charonbc")).unwrap());
+    assert_eq!(files(&dir), ["hello.charon", "hello.charonbc", "out.bc"]);
+}
+
+#[test]
+fn test_stdin() {
+    let dir = dir("stdin");
+
+    // the bytecode of stdin is written to stdout, without the source file name
+    let output = charonc(&dir, &["-"], HELLO);
+    assert!(output.status.success());
+    let program = Loader::new(&output.stdout).load().unwrap();
+    assert_eq!(program.source_file, None);
+
+    let output = charonc(&dir, &["--emit=asm", "-"], HELLO);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().contains("hello"));
+
+    let output = charonc(&dir, &["-"], INVALID);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("<stdin>:1:"));
+}
+
+#[test]
+fn test_multiple_inputs() {
+    let dir = dir("multiple_inputs");
+    fs::write(dir.join("a.charon"), HELLO).unwrap();
+    fs::write(dir.join("b.charon"), HELLO).unwrap();
+    fs::write(dir.join("c.charon"), INVALID).unwrap();
+
+    let output = charonc(&dir, &["a.charon", "b.charon"], "");
+    assert!(output.status.success());
+    assert_eq!(files(&dir), ["a.charon", "a.charonbc", "b.charon", "b.charonbc", "c.charon"]);
+
+    // a header before the listing of each input
+    let output = charonc(&dir, &["--emit=asm", "a.charon", "-"], HELLO);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("==> a.charon <==\n"));
+    assert!(stdout.contains("\n==> <stdin> <==\n"));
+
+    // the exit code is the error count of all the inputs, the valid ones are still compiled
+    fs::remove_file(dir.join("a.charonbc")).unwrap();
+    let output = charonc(&dir, &["c.charon", "a.charon", "c.charon", "missing.charon"], "");
+    assert_eq!(output.status.code(), Some(5));
+    assert!(dir.join("a.charonbc").exists());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("missing.charon"));
+
+    let output = charonc(&dir, &["-o", "out.bc", "a.charon", "b.charon"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("`-o` can't be used with multiple input files"));
+    assert!(!dir.join("out.bc").exists());
+}
+
+#[test]
+fn test_check() {
+    let dir = dir("check");
+    fs::write(dir.join("hello.charon"), HELLO).unwrap();
+    fs::write(dir.join("invalid.charon"), INVALID).unwrap();
+
+    let output = charonc(&dir, &["--check", "hello.charon", "-o", "out.bc"], "");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let output = charonc(&dir, &["--check", "invalid.charon"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("invalid.charon:2:"));
+
+    // warnings don't fail the check unless they are denied
+    let unreachable = "func f() { return 1; __println(2); }\n__println(f());\n";
+    let output = charonc(&dir, &["--check", "-"], unreachable);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("unreachable statement"));
+    let output = charonc(&dir, &["--check", "--deny-warnings", "-"], unreachable);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(files(&dir), ["hello.charon", "invalid.charon"]);
+}
+
+#[test]
+fn test_emit_format() {
+    let dir = dir("emit_format");
+    let output = charonc(&dir, &["--emit=ast", "--emit-format=json", "-"], HELLO);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("{\"file\":\"<stdin>\",\"ast\":"));
+
+    for emit in ["--emit=asm", "--emit=bytecode"] {
+        let output = charonc(&dir, &[emit, "--emit-format=json", "-"], HELLO);
+        assert_eq!(output.status.code(), Some(1));
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8(output.stderr).unwrap().contains("`--emit-format=json` can only be used with `--emit=tokens` or `--emit=ast`"));
+    }
+}
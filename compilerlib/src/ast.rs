@@ -1,7 +1,19 @@
 #[derive(Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub offset: usize// offset of the operator token for compound expressions, otherwise the first token
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, offset: usize) -> Self {
+        Self { kind, offset }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
     True,
-    Flase,
+    False,
     Null,
     This,
     Long(i64),
@@ -12,7 +24,12 @@ pub enum Expr {
     Unary(UnaryExpr),
     Call(CallExpr),
     GetVar(String),
-    Getter(GetterExpr)
+    Getter(GetterExpr),
+    Is(IsExpr),
+    Tuple(Vec<Expr>),
+    Range(RangeExpr),
+    Index(IndexExpr),
+    Slice(SliceExpr)
 }
 
 #[derive(Debug)]
@@ -94,28 +111,99 @@ impl GetterExpr {
 }
 
 #[derive(Debug)]
-pub enum Stmt {
+pub struct IsExpr {
+    pub expr: Box<Expr>,
+    pub name: String// class or trait name
+}
+
+impl IsExpr {
+    pub fn new(expr: Box<Expr>, name: String) -> Self {
+        Self { expr, name }
+    }
+}
+
+#[derive(Debug)]
+pub struct RangeExpr {
+    pub start: Box<Expr>,
+    pub end: Box<Expr>,
+    pub inclusive: bool
+}
+
+impl RangeExpr {
+    pub fn new(start: Box<Expr>, end: Box<Expr>, inclusive: bool) -> Self {
+        Self { start, end, inclusive }
+    }
+}
+
+#[derive(Debug)]
+pub struct IndexExpr {
+    pub owner: Box<Expr>,
+    pub index: Box<Expr>
+}
+
+impl IndexExpr {
+    pub fn new(owner: Box<Expr>, index: Box<Expr>) -> Self {
+        Self { owner, index }
+    }
+}
+
+#[derive(Debug)]
+pub struct SliceExpr {
+    pub owner: Box<Expr>,
+    pub start: Option<Box<Expr>>,
+    pub end: Option<Box<Expr>>
+}
+
+impl SliceExpr {
+    pub fn new(owner: Box<Expr>, start: Option<Box<Expr>>, end: Option<Box<Expr>>) -> Self {
+        Self { owner, start, end }
+    }
+}
+
+#[derive(Debug)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub offset: usize// offset of the first token
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, offset: usize) -> Self {
+        Self { kind, offset }
+    }
+}
+
+#[derive(Debug)]
+pub enum StmtKind {
     VarDef(VarDefStmt),
     Expr(Box<Expr>),
     SetVar(SetVarStmt),
     Setter(SetterStmt),
     If(IfStmt),
     While(WhileStmt),
+    ForIn(ForInStmt),
     Break,
     Continue,
     Return(Option<Box<Expr>>),
+    Defer(Box<Expr>),// evaluated when the function exits
+    Assert(AssertStmt),
     Block(Vec<Stmt>)
 }
 
+#[derive(Debug)]
+pub enum Pattern {
+    Var(String),
+    Tuple(Vec<Pattern>)
+}
+
 #[derive(Debug)]
 pub struct VarDefStmt {
-    pub name: String,
+    pub pattern: Pattern,
     pub init: Option<Box<Expr>>
 }
 
 impl VarDefStmt {
-    pub fn new(name: String, init: Option<Box<Expr>>) -> Self {
-        Self { name, init }
+    pub fn new(pattern: Pattern, init: Option<Box<Expr>>) -> Self {
+        Self { pattern, init }
     }
 }
 
@@ -126,13 +214,13 @@ pub enum AssignOp {
 
 #[derive(Debug)]
 pub struct SetVarStmt {
-    pub to: String,
-    pub op: AssignOp,
+    pub to: Pattern,
+    pub op: AssignOp,// only `Assign` is allowed for tuple pattern
     pub value: Box<Expr>
 }
 
 impl SetVarStmt {
-    pub fn new(to: String, op: AssignOp, value: Box<Expr>) -> Self {
+    pub fn new(to: Pattern, op: AssignOp, value: Box<Expr>) -> Self {
         Self { to, op, value }
     }
 }
@@ -176,39 +264,93 @@ impl WhileStmt {
     }
 }
 
+#[derive(Debug)]
+pub struct ForInStmt {
+    pub pattern: Pattern,
+    pub iterable: Box<Expr>,
+    pub body: Vec<Stmt>
+}
+
+impl ForInStmt {
+    pub fn new(pattern: Pattern, iterable: Box<Expr>, body: Vec<Stmt>) -> Self {
+        Self { pattern, iterable, body }
+    }
+}
+
+#[derive(Debug)]
+pub struct AssertStmt {
+    pub cond: Box<Expr>,
+    pub msg: Option<Box<Expr>>,
+    pub text: String,// source text of `cond`
+    pub line: usize,
+    pub column: usize
+}
+
+impl AssertStmt {
+    pub fn new(cond: Box<Expr>, msg: Option<Box<Expr>>, text: String, line: usize, column: usize) -> Self {
+        Self { cond, msg, text, line, column }
+    }
+}
+
 #[derive(Debug)]
 pub struct FuncDecl {
     pub name: String,
     pub params: Vec<String>,
-    pub body: Vec<Stmt>
+    pub body: Vec<Stmt>,
+    pub offset: usize// offset of the name
 }
 
 impl FuncDecl {
-    pub fn new(name: String, params: Vec<String>, body: Vec<Stmt>) -> Self {
-        Self { name, params, body }
+    pub fn new(name: String, params: Vec<String>, body: Vec<Stmt>, offset: usize) -> Self {
+        Self { name, params, body, offset }
     }
 }
 
 #[derive(Debug)]
 pub struct ClassDecl {
     pub name: String,
-    pub methods: Vec<FuncDecl>
+    pub traits: Vec<String>,
+    pub methods: Vec<FuncDecl>,
+    pub getters: Vec<FuncDecl>,
+    pub setters: Vec<FuncDecl>,
+    pub offset: usize// offset of the name
 }
 
 impl ClassDecl {
-    pub fn new(name: String, methods: Vec<FuncDecl>) -> Self {
-        Self { name, methods }
+    pub fn new(name: String
+               , traits: Vec<String>
+               , methods: Vec<FuncDecl>
+               , getters: Vec<FuncDecl>
+               , setters: Vec<FuncDecl>
+               , offset: usize
+    ) -> Self {
+        Self { name, traits, methods, getters, setters, offset }
+    }
+}
+
+#[derive(Debug)]
+pub struct TraitDecl {
+    pub name: String,
+    pub required: Vec<FuncDecl>,// methods without body, only name & params are used
+    pub provided: Vec<FuncDecl>,// methods with default body
+    pub offset: usize// offset of the name
+}
+
+impl TraitDecl {
+    pub fn new(name: String, required: Vec<FuncDecl>, provided: Vec<FuncDecl>, offset: usize) -> Self {
+        Self { name, required, provided, offset }
     }
 }
 
 #[derive(Debug)]
 pub struct Program {
     pub funcs: Vec<FuncDecl>,
-    pub classes: Vec<ClassDecl>
+    pub classes: Vec<ClassDecl>,
+    pub traits: Vec<TraitDecl>
 }
 
 impl Program {
-    pub fn new(funcs: Vec<FuncDecl>, classes: Vec<ClassDecl>) -> Self {
-        Self { funcs, classes }
+    pub fn new(funcs: Vec<FuncDecl>, classes: Vec<ClassDecl>, traits: Vec<TraitDecl>) -> Self {
+        Self { funcs, classes, traits }
     }
 }
\ No newline at end of file
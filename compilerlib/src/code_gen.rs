@@ -1,13 +1,21 @@
+use std::mem;
 use ahash::{HashMap, HashMapExt, HashSet, HashSetExt};
-use crate::ast::{AssignOp, BinaryOp, ClassDecl, Expr, FuncDecl, LogicOp, Program, Stmt, UnaryOp};
+use crate::ast::{AssertStmt, AssignOp, BinaryOp, ClassDecl, Expr, ExprKind, FuncDecl, LogicOp, Pattern, Program, Stmt, StmtKind, TraitDecl, UnaryOp};
+use crate::err::{Error, Result};
+use crate::optimizer;
+use crate::visit::{walk_pattern, Visitor};
+use crate::{OptLevel, Options};
 use common::constant::*;
+use common::line_column_info::LineColumnInfo;
 use common::opcode::*;
+use common::program::{LineNumber, LocalVariable};
+use common::verifier;
 
 struct ConstantPool {
-    long: HashMap<i64, u16>,
-    double: HashMap<u64, u16>, // key is double's int bits
-    string: HashMap<String, u16>,
-    count: u16,
+    long: HashMap<i64, u32>,
+    double: HashMap<u64, u32>, // key is double's int bits
+    string: HashMap<String, u32>,
+    count: u32,
     code: Vec<u8>
 }
 
@@ -22,7 +30,7 @@ impl ConstantPool {
         }
     }
 
-    fn const_long(&mut self, v: i64) -> u16 {
+    fn const_long(&mut self, v: i64) -> u32 {
         *self.long.entry(v).or_insert_with(|| {
             self.code.push(CONSTANT_LONG);
             self.code.extend_from_slice(&v.to_le_bytes());
@@ -33,7 +41,7 @@ impl ConstantPool {
         })
     }
 
-    fn const_double(&mut self, v: f64) -> u16 {
+    fn const_double(&mut self, v: f64) -> u32 {
         *self.double.entry(v.to_bits()).or_insert_with(|| {
             self.code.push(CONSTANT_DOUBLE);
             self.code.extend_from_slice(&v.to_bits().to_le_bytes());
@@ -44,13 +52,13 @@ impl ConstantPool {
         })
     }
 
-    fn const_string(&mut self, v: &str) -> u16 {
+    fn const_string(&mut self, v: &str) -> u32 {
         if let Some(idx) = self.string.get(v) {
             return *idx;
         }
 
         self.code.push(CONSTANT_STRING);
-        self.code.extend_from_slice(&(v.len() as u16).to_le_bytes());
+        self.code.extend_from_slice(&(v.len() as u32).to_le_bytes());
         self.code.extend_from_slice(v.as_bytes());
 
         let idx = self.count;
@@ -60,31 +68,103 @@ impl ConstantPool {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 enum CallableType {
     Method(u8),// param count(not include 'this')
     Func,
     None
 }
 
-struct Context {
-    local_vars: Vec<HashMap<String, u8>>,
-    count: u8,
+// top-level declarations, collected before generating any code
+struct Declarations<'a> {
+    funcs: HashMap<&'a str, &'a FuncDecl>,
+    classes: HashMap<&'a str, &'a ClassDecl>,
+    traits: HashMap<&'a str, &'a TraitDecl>,
+    // names defined or assigned by `var`, assignment or `for` anywhere, functions & classes are globals which can be replaced
+    assigned: HashSet<&'a str>
+}
+
+impl<'a> Declarations<'a> {
+    fn new(program: &'a Program) -> Result<Self> {
+        let funcs = program.funcs.iter()
+            .filter(|func| func.name != ENTRY_NAME)
+            .map(|func| (func.name.as_str(), func))
+            .collect();
+
+        let mut classes = HashMap::with_capacity(program.classes.len());
+        for class in &program.classes {
+            if classes.insert(class.name.as_str(), class).is_some() {
+                return Err(Error::new(format!("multi class with name: {} found", class.name), class.offset));
+            }
+        }
+
+        let mut traits = HashMap::with_capacity(program.traits.len());
+        for t in &program.traits {
+            if classes.contains_key(t.name.as_str()) {
+                return Err(Error::new(format!("trait: {} has the same name with class", t.name), t.offset));
+            }
+            if traits.insert(t.name.as_str(), t).is_some() {
+                return Err(Error::new(format!("multi trait with name: {} found", t.name), t.offset));
+            }
+        }
+
+        let mut assigned = Assigned(HashSet::new());
+        assigned.visit_program(program);
+        Ok(Self { funcs, classes, traits, assigned: assigned.0 })
+    }
+}
+
+struct Assigned<'a>(HashSet<&'a str>);
+
+impl<'a> Visitor<'a> for Assigned<'a> {
+    fn visit_pattern(&mut self, pattern: &'a Pattern) {
+        if let Pattern::Var(name) = pattern {
+            self.0.insert(name);
+        }
+        walk_pattern(self, pattern);
+    }
+}
+
+struct Context<'a> {
+    decls: &'a Declarations<'a>,
+    options: &'a Options,
+    line_column_info: &'a LineColumnInfo<'a>,
+
+    local_vars: Vec<HashMap<String, u16>>,
+    count: usize,// may be over the limit, checked after the function is generated
+    local_var_table: Vec<LocalVariable>,// index is the slot
 
     callable_type: CallableType,
+    // jump targets are u32 after `WIDE` if the code is longer than u16
+    wide_jumps: bool,
+
+    loop_start_pos: Option<usize>,
+    loop_out_patch_pos: Vec<usize>,
 
-    loop_start_pos: Option<u16>,
-    loop_out_patch_pos: Vec<u16>
+    // source offsets of the stmts & exprs being generated, the innermost is the last
+    nodes: Vec<usize>,
+    line_numbers: Vec<LineNumber>,
+
+    // operands of `assert` whose values are also stored to the local slot when they are generated
+    captures: Vec<(*const Expr, u16)>
 }
 
-impl Context {
-    fn new(callable_type: CallableType) -> Self {
+impl<'a> Context<'a> {
+    fn new(callable_type: CallableType, decls: &'a Declarations<'a>, options: &'a Options, line_column_info: &'a LineColumnInfo<'a>) -> Self {
         Self {
+            decls,
+            options,
+            line_column_info,
             local_vars: vec![HashMap::new()],
             count: 0,
+            local_var_table: Vec::new(),
             callable_type,
+            wide_jumps: false,
             loop_start_pos: None,
-            loop_out_patch_pos: Vec::new()
+            loop_out_patch_pos: Vec::new(),
+            nodes: Vec::new(),
+            line_numbers: Vec::new(),
+            captures: Vec::new()
         }
     }
 
@@ -92,23 +172,29 @@ impl Context {
         self.local_vars.push(HashMap::new());
     }
     
-    fn pop_scope(&mut self) {
-        self.local_vars.pop();
+    // the variables of the scope are live until `pc`
+    fn pop_scope(&mut self, pc: usize) {
+        if let Some(scope) = self.local_vars.pop() {
+            for idx in scope.values() {
+                self.local_var_table[*idx as usize].end_pc = pc as u32;
+            }
+        }
     }
     
-    // we allow redefine variable
-    fn define_local_var(&mut self, var: &str) -> u8 {
+    // we allow redefine variable, `pc` is the instruction defining it
+    fn define_local_var(&mut self, var: &str, pc: usize) -> u16 {
         if let Some(idx) = self.local_vars.last().unwrap().get(var) {
             return *idx;
         }
 
-        let idx = self.count;
+        let idx = self.count as u16;
         self.count += 1;
         self.local_vars.last_mut().unwrap().insert(var.to_owned(), idx);
+        self.local_var_table.push(LocalVariable { start_pc: pc as u32, end_pc: pc as u32, slot: idx, name: var.to_owned() });
         idx
     }
 
-    fn get_local_var(&mut self, var: &str) -> Option<u8> {
+    fn get_local_var(&mut self, var: &str) -> Option<u16> {
         for scope in self.local_vars.iter().rev() {
             if let Some(idx) = scope.get(var) {
                 return Some(*idx);
@@ -117,43 +203,136 @@ impl Context {
         None
     }
     
+    // returns the outer loop's info, which should be restored by `exit_loop`
+    fn enter_loop(&mut self, loop_start_pos: usize) -> (Option<usize>, Vec<usize>) {
+        (self.loop_start_pos.replace(loop_start_pos), mem::take(&mut self.loop_out_patch_pos))
+    }
+
+    // returns the positions need to be patched to the loop's end
+    fn exit_loop(&mut self, outer: (Option<usize>, Vec<usize>)) -> Vec<usize> {
+        self.loop_start_pos = outer.0;
+        mem::replace(&mut self.loop_out_patch_pos, outer.1)
+    }
+
+    // only top-level declarations are globals, variables in blocks of the entry are locals
     fn is_global_scope(&self) -> bool {
-        self.callable_type == CallableType::None
+        self.callable_type == CallableType::None && self.local_vars.len() == 1
+    }
+
+    // code from `pc` is generated from the node at source `offset`
+    fn enter_node(&mut self, offset: usize, pc: usize) {
+        self.nodes.push(offset);
+        self.add_line_number(offset, pc);
+    }
+
+    // code from `pc` belongs to the enclosing node again
+    fn exit_node(&mut self, pc: usize) {
+        self.nodes.pop();
+        if let Some(offset) = self.nodes.last() {
+            self.add_line_number(*offset, pc);
+        }
+    }
+
+    // emit a jump whose target is patched later, returns the position to patch
+    fn emit_jump(&self, code: &mut Vec<u8>, opcode: u8) -> usize {
+        if self.wide_jumps {
+            code.push(OP_WIDE);
+            code.push(opcode);
+            code.extend_from_slice(&[0; 4]);
+            code.len() - 4
+        } else {
+            code.push(opcode);
+            code.extend_from_slice(&[0; 2]);
+            code.len() - 2
+        }
+    }
+
+    fn emit_jump_to(&self, code: &mut Vec<u8>, opcode: u8, target: usize) {
+        let pos = self.emit_jump(code, opcode);
+        self.patch(code, pos, target);
+    }
+
+    // targets over u16 are truncated without `WIDE`, such code is generated again with wide jumps
+    fn patch(&self, code: &mut [u8], pos: usize, target: usize) {
+        if self.wide_jumps {
+            code[pos..pos + 4].copy_from_slice(&(target as u32).to_le_bytes());
+        } else {
+            code[pos..pos + 2].copy_from_slice(&(target as u16).to_le_bytes());
+        }
+    }
+
+    fn add_line_number(&mut self, offset: usize, pc: usize) {
+        if !self.options.debug_info {
+            return;
+        }
+
+        let (line, column) = self.line_column_info.line_column_info(offset);
+        let v = LineNumber { start_pc: pc as u32, line: line as u32, column: column as u32 };
+        // no code generated for the last node
+        if self.line_numbers.last().is_some_and(|last| last.start_pc == v.start_pc) {
+            self.line_numbers.pop();
+        }
+        if !self.line_numbers.last().is_some_and(|last| last.line == v.line && last.column == v.column) {
+            self.line_numbers.push(v);
+        }
     }
 }
 
-type Result<T> = std::result::Result<T, String>;// use string as error type, ignore line, column info
+// each class, method & function is checked even if the others have errors, all the errors are returned.
+// the errors of the declarations stop before any code is generated
+pub fn check_and_gen(program: &Program, source: &[u8], options: &Options) -> std::result::Result<Vec<u8>, Vec<Error>> {
+    let decls = Declarations::new(program).map_err(|e| vec![e])?;
+    let mut errors = Vec::new();
+    for t in &program.traits {
+        if let Err(e) = check_trait(t) {
+            errors.push(e);
+        }
+    }
 
-pub fn check_and_gen(program: &Program) -> Result<Vec<u8>> {
-    let mut global = HashSet::new();
+    let line_column_info = LineColumnInfo::new(source);
     let mut cp = ConstantPool::new();
 
     // contains classes & functions info (not include header & constant pool info)
     let mut code = Vec::new();
+    if let Some(class) = program.classes.get(u16::MAX as usize) {
+        return Err(vec![Error::new(format!("too many classes, the limit is {}", u16::MAX), class.offset)]);
+    }
+    if let Some(func) = program.funcs.get(u16::MAX as usize) {
+        return Err(vec![Error::new(format!("too many functions, the limit is {}", u16::MAX), func.offset)]);
+    }
     code.extend_from_slice(&(program.classes.len() as u16).to_le_bytes());
     for class in &program.classes {
-        if global.contains(&class.name) {
-            return Err(format!("multi class with name: {} found", class.name));
+        if let Err(e) = gen_class(class, &decls, options, &line_column_info, &mut cp, &mut code, &mut errors) {
+            errors.push(e);
         }
-        global.insert(class.name.clone());
-        gen_class(class, &mut cp, &mut code)?;
     }
 
     code.extend_from_slice(&(program.funcs.len() as u16).to_le_bytes());
 
     for func in &program.funcs {
         let mut context = if func.name == ENTRY_NAME {
-            Context::new(CallableType::None)
+            Context::new(CallableType::None, &decls, options, &line_column_info)
         } else {
-            Context::new(CallableType::Func)
+            Context::new(CallableType::Func, &decls, options, &line_column_info)
         };
-        gen_func(func, &mut context, &mut cp, &mut code)?;
+        if let Err(e) = gen_func(func, &mut context, &mut cp, &mut code) {
+            errors.push(e);
+        }
     }
-    
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| e.offset);
+        // a default method is generated in each class which copies it from the trait
+        errors.dedup();
+        return Err(errors);
+    }
+
     let mut bytes = Vec::with_capacity(20 + cp.code.len() + code.len());
     bytes.extend_from_slice(MAGIC.as_bytes());
     bytes.push(CURRENT_VERSION_MINOR);
     bytes.push(CURRENT_VERSION_MAJOR);
+    let source_file = options.source_file.as_deref().filter(|_| options.debug_info).unwrap_or_default();
+    bytes.extend_from_slice(&(source_file.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(source_file.as_bytes());
     bytes.extend_from_slice(&cp.count.to_le_bytes());
     bytes.extend_from_slice(&cp.code);
     bytes.extend_from_slice(&code);
@@ -161,42 +340,197 @@ pub fn check_and_gen(program: &Program) -> Result<Vec<u8>> {
     Ok(bytes)
 }
 
+fn check_trait(t: &TraitDecl) -> Result<()> {
+    let mut method_names = HashSet::with_capacity(t.required.len() + t.provided.len());
+    for method in t.required.iter().chain(&t.provided) {
+        if !method_names.insert(&method.name) {
+            return Err(Error::new(format!("multi method with name: {} in trait: {}", method.name, t.name), method.offset));
+        }
+    }
+    Ok(())
+}
 
-fn gen_class(class: &ClassDecl, cp: &mut ConstantPool, code: &mut Vec<u8>) -> Result<()> {
-    // class name's index
-    code.extend_from_slice(&cp.const_string(&class.name).to_le_bytes());
-
-    // method count
-    code.extend_from_slice(&(class.methods.len() as u16).to_le_bytes());
+// the errors of the class itself are returned, the errors of its methods are pushed to `errors`
+fn gen_class(class: &ClassDecl, decls: &Declarations, options: &Options, line_column_info: &LineColumnInfo, cp: &mut ConstantPool, code: &mut Vec<u8>
+             , errors: &mut Vec<Error>) -> Result<()> {
+    let mut traits = Vec::with_capacity(class.traits.len());
+    for trait_name in &class.traits {
+        let Some(t) = decls.traits.get(trait_name.as_str()) else {
+            return Err(Error::new(format!("class: {} implements unknown trait: {trait_name}", class.name), class.offset)
+                .with_help(&format!("declare it with `trait {trait_name} {{ ... }}`")));
+        };
+        if traits.iter().any(|other: &&TraitDecl| other.name == t.name) {
+            return Err(Error::new(format!("trait: {trait_name} is implemented more than once by class: {}", class.name), class.offset));
+        }
+        traits.push(*t);
+    }
 
     let mut method_names = HashSet::with_capacity(class.methods.len());
     for method in &class.methods {
         if method_names.contains(&method.name) {
-            return Err(format!("multi method with name: {} in class: {}", method.name, class.name));
+            return Err(Error::new(format!("multi method with name: {} in class: {}", method.name, class.name), method.offset));
+        }
+        method_names.insert(method.name.clone());
+    }
+
+    // default methods which are not defined in class are copied from traits
+    let mut defaults: Vec<(&TraitDecl, &FuncDecl)> = Vec::new();
+    for t in &traits {
+        for method in &t.provided {
+            if let Some(own) = class.methods.iter().find(|m| m.name == method.name) {
+                check_trait_method(class, own, t, method)?;
+                continue;
+            }
+            if let Some((other, _)) = defaults.iter().find(|(_, m)| m.name == method.name) {
+                return Err(Error::new(format!("method: {} is provided by both trait: {} and trait: {}, class: {} must define it"
+                                   , method.name, other.name, t.name, class.name), class.offset));
+            }
+            defaults.push((t, method));
         }
+    }
+    for (_, method) in &defaults {
         method_names.insert(method.name.clone());
+    }
+
+    for t in &traits {
+        for method in &t.required {
+            let own = class.methods.iter()
+                .find(|m| m.name == method.name)
+                .or_else(|| defaults.iter().find(|(_, m)| m.name == method.name).map(|(_, m)| *m));
+            let Some(own) = own else {
+                return Err(Error::new(format!("class: {} doesn't implement method: {} of trait: {}", class.name, method.name, t.name), class.offset));
+            };
+            check_trait_method(class, own, t, method)?;
+        }
+    }
+
+    let mut getter_names = HashSet::with_capacity(class.getters.len());
+    for getter in &class.getters {
+        if method_names.contains(&getter.name) {
+            return Err(Error::new(format!("getter: {} conflicts with method in class: {}", getter.name, class.name), getter.offset));
+        }
+        if getter_names.contains(&getter.name) {
+            return Err(Error::new(format!("multi getter with name: {} in class: {}", getter.name, class.name), getter.offset));
+        }
+        getter_names.insert(getter.name.clone());
+    }
+
+    let mut setter_names = HashSet::with_capacity(class.setters.len());
+    for setter in &class.setters {
+        if method_names.contains(&setter.name) {
+            return Err(Error::new(format!("setter: {} conflicts with method in class: {}", setter.name, class.name), setter.offset));
+        }
+        if setter_names.contains(&setter.name) {
+            return Err(Error::new(format!("multi setter with name: {} in class: {}", setter.name, class.name), setter.offset));
+        }
+        setter_names.insert(setter.name.clone());
+    }
+
+    // class name's index
+    code.extend_from_slice(&cp.const_string(&class.name).to_le_bytes());
+
+    // implemented traits
+    code.extend_from_slice(&(traits.len() as u16).to_le_bytes());
+    for t in &traits {
+        code.extend_from_slice(&cp.const_string(&t.name).to_le_bytes());
+    }
+
+    // method count
+    let method_count = class.methods.len() + defaults.len() + class.getters.len() + class.setters.len();
+    if method_count > u16::MAX as usize {
+        return Err(Error::new(format!("too many methods in class: {}, the limit is {}", class.name, u16::MAX), class.offset));
+    }
+    code.extend_from_slice(&(method_count as u16).to_le_bytes());
 
-        let mut context = Context::new(CallableType::Method(method.params.len() as u8));
-        gen_func(method, &mut context, cp, code)?;
+    let methods = class.methods.iter().map(|m| (m, METHOD_KIND_NORMAL))
+        .chain(defaults.iter().map(|(_, m)| (*m, METHOD_KIND_NORMAL)))
+        .chain(class.getters.iter().map(|m| (m, METHOD_KIND_GETTER)))
+        .chain(class.setters.iter().map(|m| (m, METHOD_KIND_SETTER)));
+    for (method, kind) in methods {
+        if let Err(e) = gen_method(method, kind, decls, options, line_column_info, cp, code) {
+            errors.push(e);
+        }
     }
 
     Ok(())
 }
 
+fn check_trait_method(class: &ClassDecl, method: &FuncDecl, t: &TraitDecl, trait_method: &FuncDecl) -> Result<()> {
+    if method.params.len() != trait_method.params.len() {
+        return Err(Error::new(format!("method: {} of class: {} has {} params, but trait: {} requires {}"
+                           , method.name, class.name, method.params.len(), t.name, trait_method.params.len()), method.offset));
+    }
+    Ok(())
+}
+
+fn gen_method(method: &FuncDecl, kind: u8, decls: &Declarations, options: &Options, line_column_info: &LineColumnInfo, cp: &mut ConstantPool, code: &mut Vec<u8>) -> Result<()> {
+    // name index
+    code.extend_from_slice(&cp.const_string(&method.name).to_le_bytes());
+
+    code.push(kind);
+
+    let mut context = Context::new(CallableType::Method(method.params.len() as u8), decls, options, line_column_info);
+    gen_callable(method, &mut context, cp, code)
+}
+
 fn gen_func(func: &FuncDecl, context: &mut Context, cp: &mut ConstantPool, code: &mut Vec<u8>) -> Result<()> {
     // name index
     code.extend_from_slice(&cp.const_string(&func.name).to_le_bytes());
 
+    gen_callable(func, context, cp, code)
+}
+
+fn gen_callable(func: &FuncDecl, context: &mut Context, cp: &mut ConstantPool, code: &mut Vec<u8>) -> Result<()> {
     // param count
     code.push(func.params.len() as u8);
 
+    let mut body = gen_body(func, context, cp)?;
+    if body.len() > u16::MAX as usize {
+        // u16 jump targets can't reach the end of the code
+        *context = Context {
+            wide_jumps: true,
+            ..Context::new(context.callable_type, context.decls, context.options, context.line_column_info)
+        };
+        body = gen_body(func, context, cp)?;
+    }
+    // `this` is included
+    if context.count > u16::MAX as usize {
+        return Err(Error::new(format!("function: {} has {} local variables, the limit is {}", func.name, context.count, u16::MAX), func.offset));
+    }
+    if body.len() > u32::MAX as usize {
+        return Err(Error::new(format!("the code of function: {} is longer than {} bytes", func.name, u32::MAX), func.offset));
+    }
+
+    if context.options.opt_level != OptLevel::None {
+        optimizer::optimize_code(&mut body, &mut context.line_numbers, &mut context.local_var_table);
+    }
+
+    // the deepest of the operand stack, the vm checks there is room for it and the locals once per call
+    let max_stack = verifier::max_stack(&body)
+        .map_err(|e| Error::new(format!("failed to compute the max stack of function: {}, {e}", func.name), func.offset))?;
+    if max_stack > u16::MAX as usize {
+        return Err(Error::new(format!("function: {} needs an operand stack of {max_stack}, the limit is {}", func.name, u16::MAX), func.offset));
+    }
+
+    // max locals
+    code.extend_from_slice(&(context.count as u16).to_le_bytes());
+    code.extend_from_slice(&(max_stack as u16).to_le_bytes());
+    
+    code.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    code.extend_from_slice(&body);
+
+    gen_attributes(context, cp, code);
+    Ok(())
+}
+
+fn gen_body(func: &FuncDecl, context: &mut Context, cp: &mut ConstantPool) -> Result<Vec<u8>> {
     for arg in &func.params {
-        context.define_local_var(arg);
+        context.define_local_var(arg, 0);
     }
 
     // define 'this' as the last arg
     if let CallableType::Method(_) = context.callable_type {
-        context.define_local_var("this");
+        context.define_local_var("this", 0);
     }
 
     let mut body = Vec::new();
@@ -205,42 +539,99 @@ fn gen_func(func: &FuncDecl, context: &mut Context, cp: &mut ConstantPool, code:
     }
 
     // default return null
-    if body.is_empty() || unsafe { *body.get_unchecked(body.len() - 1) != OP_RETURN } {
+    if !matches!(func.body.last(), Some(Stmt {kind: StmtKind::Return(_), ..})) {
         body.push(OP_CONST_NULL);
         body.push(OP_RETURN);
     }
+    context.pop_scope(body.len());
+    Ok(body)
+}
 
-    // max locals
-    code.push(context.count);
-    
-    code.extend_from_slice(&(body.len() as u16).to_le_bytes());
-    code.extend_from_slice(&body);
+fn gen_attributes(context: &Context, cp: &mut ConstantPool, code: &mut Vec<u8>) {
+    if !context.options.debug_info {
+        code.extend_from_slice(&0u16.to_le_bytes());
+        return;
+    }
+
+    // attribute count
+    code.extend_from_slice(&2u16.to_le_bytes());
+
+    code.push(ATTRIBUTE_LINE_NUMBER_TABLE);
+    let line_numbers = &context.line_numbers;
+    code.extend_from_slice(&(4 + line_numbers.len() as u32 * 12).to_le_bytes());
+    code.extend_from_slice(&(line_numbers.len() as u32).to_le_bytes());
+    for v in line_numbers {
+        code.extend_from_slice(&v.start_pc.to_le_bytes());
+        code.extend_from_slice(&v.line.to_le_bytes());
+        code.extend_from_slice(&v.column.to_le_bytes());
+    }
+
+    code.push(ATTRIBUTE_LOCAL_VARIABLE_TABLE);
+    let local_vars = &context.local_var_table;
+    code.extend_from_slice(&(4 + local_vars.len() as u32 * 14).to_le_bytes());
+    code.extend_from_slice(&(local_vars.len() as u32).to_le_bytes());
+    for v in local_vars {
+        code.extend_from_slice(&v.start_pc.to_le_bytes());
+        code.extend_from_slice(&v.end_pc.to_le_bytes());
+        code.extend_from_slice(&v.slot.to_le_bytes());
+        code.extend_from_slice(&cp.const_string(&v.name).to_le_bytes());
+    }
+}
+
+// the param count of calls to top-level functions, ffi functions & classes are known at compile time,
+// unless the name is assigned somewhere and may refer to something else when called
+fn check_arity(owner: &Expr, args: usize, context: &mut Context) -> Result<()> {
+    let ExprKind::GetVar(name) = &owner.kind else {
+        return Ok(());
+    };
+    if !context.is_global_scope() && context.get_local_var(name).is_some() {
+        return Ok(());
+    }
+    if context.decls.assigned.contains(name.as_str()) {
+        return Ok(());
+    }
+
+    if let Some(func) = context.decls.funcs.get(name.as_str()) {
+        if func.params.len() != args {
+            let (line, column) = context.line_column_info.line_column_info(func.offset);
+            return Err(Error::new(format!("function: {name}'s param count: {}, but got: {args}", func.params.len()), owner.offset)
+                .with_help(&format!("function: {name}({}) is defined at {line}:{column}", func.params.join(", "))));
+        }
+    } else if let Some((_, params)) = FFI_FUNCTIONS.iter().find(|(ffi, _)| ffi == name) {
+        if *params as usize != args {
+            return Err(Error::new(format!("foreign function: {name}'s param count: {params}, but got: {args}"), owner.offset));
+        }
+    } else if context.decls.classes.contains_key(name.as_str()) && args != 0 {
+        // classes have no constructor, instances are created without arguments
+        return Err(Error::new(format!("class: {name} has no constructor, but got: {args} arguments"), owner.offset)
+            .with_help(&format!("create it with `{name}()` and set the fields after")));
+    }
     Ok(())
 }
 
 fn gen_stmt(stmt: &Stmt, context: &mut Context, cp: &mut ConstantPool, code: &mut Vec<u8>) -> Result<()> {
-    match stmt {
-        Stmt::VarDef(vardef) => {
+    context.enter_node(stmt.offset, code.len());
+    let res = gen_stmt_kind(stmt, context, cp, code);
+    context.exit_node(code.len());
+    res
+}
+
+fn gen_stmt_kind(stmt: &Stmt, context: &mut Context, cp: &mut ConstantPool, code: &mut Vec<u8>) -> Result<()> {
+    match &stmt.kind {
+        StmtKind::VarDef(vardef) => {
             if let Some(init) = &vardef.init {
-                gen_expr(init, context, cp, code)?;
+                gen_destructure(&vardef.pattern, init, true, context, cp, code)?;
             } else {
                 code.push(OP_CONST_NULL);
-            }
-            
-            if context.is_global_scope() {
-                code.push(OP_DEF_GLOBAL);
-                code.extend_from_slice(&cp.const_string(&vardef.name).to_le_bytes());
-            } else {
-                code.push(OP_SET_LOCAL);
-                code.push(context.define_local_var(&vardef.name));
+                gen_store_pattern(&vardef.pattern, true, context, cp, code);
             }
         }
-        Stmt::Expr(e) => {
-            match e.as_ref() {
-                Expr::True | Expr::Flase | Expr::Null |
-                Expr::Long(_) | Expr::Double(_) | Expr::String(_) => (),
-                Expr::This => if !matches!(context.callable_type, CallableType::Method(_)) {
-                    return Err("`this` can only used in methods".to_owned());
+        StmtKind::Expr(e) => {
+            match &e.kind {
+                ExprKind::True | ExprKind::False | ExprKind::Null |
+                ExprKind::Long(_) | ExprKind::Double(_) | ExprKind::String(_) => (),
+                ExprKind::This => if !matches!(context.callable_type, CallableType::Method(_)) {
+                    return Err(Error::new("`this` can only used in methods".to_owned(), e.offset));
                 }
                 _ => {
                     gen_expr(e, context, cp, code)?;
@@ -248,56 +639,40 @@ fn gen_stmt(stmt: &Stmt, context: &mut Context, cp: &mut ConstantPool, code: &mu
                 }
             }
         }
-        Stmt::SetVar(setvar) => {
-            let opcode;
-            let idx: u16;
-            if !context.is_global_scope() && let Some(local) = context.get_local_var(&setvar.to) {
-                opcode = OP_SET_LOCAL;
-                idx = local as u16;
-            } else {
-                opcode = OP_SET_GLOBAL;
-                idx = cp.const_string(&setvar.to);
-            }
-
+        StmtKind::SetVar(setvar) => {
+            let Pattern::Var(to) = &setvar.to else {
+                return gen_destructure(&setvar.to, &setvar.value, false, context, cp, code);
+            };
             if setvar.op == AssignOp::Assign {
-                gen_expr(&setvar.value, context, cp, code)?;
-            } else {
-                if opcode == OP_SET_LOCAL {
-                    code.push(OP_GET_LOCAL);
-                    code.push(idx as u8);
-                } else {
-                    code.push(OP_GET_GLOBAL);
-                    code.extend_from_slice(&idx.to_le_bytes());
-                }
-
-                gen_expr(&setvar.value, context, cp, code)?;
-
-                let op = match setvar.op {
-                    AssignOp::AddAssign => OP_ADD,
-                    AssignOp::SubAssign => OP_SUB,
-                    AssignOp::MultiplyAssign => OP_MUL,
-                    AssignOp::DivideAssign => OP_DIV,
-                    AssignOp::Assign => unreachable!(),
-                };
-                code.push(op);
+                return gen_destructure(&setvar.to, &setvar.value, false, context, cp, code);
             }
 
-            code.push(opcode);
-            if opcode == OP_SET_LOCAL {
-                code.push(idx as u8);
+            if !context.is_global_scope() && let Some(local) = context.get_local_var(to) {
+                emit_slot(code, OP_GET_LOCAL, local);
             } else {
-                code.extend_from_slice(&idx.to_le_bytes());
+                emit_index(code, OP_GET_GLOBAL, cp.const_string(to));
             }
+
+            gen_expr(&setvar.value, context, cp, code)?;
+
+            let op = match setvar.op {
+                AssignOp::AddAssign => OP_ADD,
+                AssignOp::SubAssign => OP_SUB,
+                AssignOp::MultiplyAssign => OP_MUL,
+                AssignOp::DivideAssign => OP_DIV,
+                AssignOp::Assign => unreachable!(),
+            };
+            code.push(op);
+            gen_store_pattern(&setvar.to, false, context, cp, code);
         }
-        Stmt::Setter(setter) => {
+        StmtKind::Setter(setter) => {
             gen_expr(&setter.owner, context, cp, code)?;
             let idx = cp.const_string(&setter.field);
             if setter.op == AssignOp::Assign {
                 gen_expr(&setter.value, context, cp, code)?;
             } else {
                 code.push(OP_DUP);
-                code.push(OP_GET_MEMBER);
-                code.extend_from_slice(&idx.to_le_bytes());
+                emit_index(code, OP_GET_MEMBER, idx);
                 gen_expr(&setter.value, context, cp, code)?;
                 let op = match setter.op {
                     AssignOp::AddAssign => OP_ADD,
@@ -308,84 +683,99 @@ fn gen_stmt(stmt: &Stmt, context: &mut Context, cp: &mut ConstantPool, code: &mu
                 };
                 code.push(op);
             }
-            code.push(OP_SET_FIELD);
-            code.extend_from_slice(&idx.to_le_bytes());
+            emit_index(code, OP_SET_FIELD, idx);
         }
-        Stmt::If(ifstmt) => {
+        StmtKind::If(ifstmt) => {
             gen_expr(&ifstmt.cond, context, cp, code)?;
-            code.push(OP_IF_NOT);
-            let off = code.len() as u16;
-            code.push(0);code.push(0);
+            let off = context.emit_jump(code, OP_IF_NOT);
 
             context.push_scope();
             for stmt in &ifstmt.then {
                 gen_stmt(stmt, context, cp, code)?;
             }
-            context.pop_scope();
+            context.pop_scope(code.len());
 
             let target;
             if ifstmt.els.is_empty() {
-                target = code.len() as u16;
+                target = code.len();
             } else {
-                code.push(OP_GOTO);
-                let off2 = code.len() as u16;
-                code.push(0);code.push(0);
+                let off2 = context.emit_jump(code, OP_GOTO);
 
-                target = code.len() as u16;
+                target = code.len();
                 context.push_scope();
                 for stmt in &ifstmt.els {
                     gen_stmt(stmt, context, cp, code)?;
                 }
-                context.pop_scope();
-                patch(code, off2, code.len() as u16);
+                context.pop_scope(code.len());
+                let end = code.len();
+                context.patch(code, off2, end);
             }
-            patch(code, off, target);
+            context.patch(code, off, target);
         }
-        Stmt::While(while_stmt) => {
-            let loop_back = code.len() as u16;
+        StmtKind::While(while_stmt) => {
+            let loop_back = code.len();
             gen_expr(&while_stmt.cond, context, cp, code)?;
-            code.push(OP_IF_NOT);
-            let off = code.len() as u16;
-            code.push(0);code.push(0);
+            let off = context.emit_jump(code, OP_IF_NOT);
 
-            context.loop_start_pos = Some(loop_back);
+            let outer_loop = context.enter_loop(loop_back);
             context.push_scope();
             for stmt in &while_stmt.body {
                 gen_stmt(stmt, context, cp, code)?;
             }
-            context.pop_scope();
-            context.loop_start_pos = None;
+            context.pop_scope(code.len());
+            let loop_out_patch_pos = context.exit_loop(outer_loop);
 
             // jump back
-            code.push(OP_GOTO);
-            code.extend_from_slice(&loop_back.to_le_bytes());
+            context.emit_jump_to(code, OP_GOTO, loop_back);
+
+            let while_end = code.len();
+            context.patch(code, off, while_end);
+
+            for patch_pos in &loop_out_patch_pos {
+                context.patch(code, *patch_pos, while_end);
+            }
+        }
+        StmtKind::ForIn(for_in) => {
+            // the iterator stays on the top of the operand stack during the loop
+            gen_expr(&for_in.iterable, context, cp, code)?;
+            let loop_back = code.len();
+            let off = context.emit_jump(code, OP_ITER_NEXT);
+
+            let outer_loop = context.enter_loop(loop_back);
+            context.push_scope();
+            gen_store_pattern(&for_in.pattern, true, context, cp, code);
+            for stmt in &for_in.body {
+                gen_stmt(stmt, context, cp, code)?;
+            }
+            context.pop_scope(code.len());
+            let loop_out_patch_pos = context.exit_loop(outer_loop);
 
-            let while_end = code.len() as u16;
-            patch(code, off, while_end);
+            // jump back
+            context.emit_jump_to(code, OP_GOTO, loop_back);
 
-            for patch_pos in &context.loop_out_patch_pos {
-                patch(code, *patch_pos, while_end);
+            let for_end = code.len();
+            context.patch(code, off, for_end);
+            for patch_pos in &loop_out_patch_pos {
+                context.patch(code, *patch_pos, for_end);
             }
-            context.loop_out_patch_pos.clear();
+            code.push(OP_POP);// the iterator
         }
-        Stmt::Break => {
+        StmtKind::Break => {
             if context.loop_start_pos.is_none() {
-                return Err("`break` can only used in while loop".to_owned());
+                return Err(Error::new("`break` can only used in loop".to_owned(), stmt.offset));
             }
-            code.push(OP_GOTO);
-            context.loop_out_patch_pos.push(code.len() as u16);
-            code.push(0);code.push(0);
+            let off = context.emit_jump(code, OP_GOTO);
+            context.loop_out_patch_pos.push(off);
         }
-        Stmt::Continue => {
+        StmtKind::Continue => {
             let Some(loop_back) = context.loop_start_pos else {
-                return Err("`continue` can only used in while loop".to_owned());
+                return Err(Error::new("`continue` can only used in loop".to_owned(), stmt.offset));
             };
-            code.push(OP_GOTO);
-            code.extend_from_slice(&loop_back.to_le_bytes());
+            context.emit_jump_to(code, OP_GOTO, loop_back);
         }
-        Stmt::Return(ret) => {
+        StmtKind::Return(ret) => {
             if context.callable_type == CallableType::None {
-                return Err("`return` can only used in function or method".to_owned());
+                return Err(Error::new("`return` can only used in function or method".to_owned(), stmt.offset));
             }
             match ret {
                 None => code.push(OP_CONST_NULL),
@@ -393,34 +783,178 @@ fn gen_stmt(stmt: &Stmt, context: &mut Context, cp: &mut ConstantPool, code: &mu
             }
             code.push(OP_RETURN);
         }
-        Stmt::Block(block) => {
-            if context.callable_type == CallableType::None {
-                return Err("don't allow block statement in global scope".to_owned());
-            }
+        StmtKind::Defer(e) => {
+            let off = context.emit_jump(code, OP_DEFER);
+
+            // the deferred block, `return` runs the next deferred block or returns to the caller
+            gen_expr(e, context, cp, code)?;
+            code.push(OP_POP);
+            code.push(OP_RETURN);
+            let end = code.len();
+            context.patch(code, off, end);
+        }
+        StmtKind::Assert(assert) => if !context.options.strip_asserts {
+            gen_assert(assert, context, cp, code)?;
+        }
+        StmtKind::Block(block) => {
             context.push_scope();
             for stmt in block {
                 gen_stmt(stmt, context, cp, code)?;
             }
-            context.pop_scope();
+            context.pop_scope(code.len());
         }
     }
 
     Ok(())
 }
 
+// cond, if cond jump over the failure code,
+// otherwise load values of the simple operands and the message, then raise the error by `assert`
+fn gen_assert(assert: &AssertStmt, context: &mut Context, cp: &mut ConstantPool, code: &mut Vec<u8>) -> Result<()> {
+    let operands: Vec<_> = match &assert.cond.kind {
+        ExprKind::Binary(binary) => vec![binary.left.as_ref(), binary.right.as_ref()],
+        ExprKind::Unary(unary) => vec![unary.expr.as_ref()],
+        _ => vec![assert.cond.as_ref()]
+    }.into_iter().filter_map(|e| simple_operand_text(e).map(|text| (text, e))).collect();
+
+    // getters run user code, the values they return for the condition are kept in temporaries named by the operands
+    let getters = operands.iter().any(|(_, e)| matches!(e.kind, ExprKind::Getter(_)));
+    if getters {
+        context.push_scope();
+        for (text, e) in &operands {
+            if matches!(e.kind, ExprKind::Getter(_)) {
+                // null until the condition evaluates the getter, the condition may fail before it
+                code.push(OP_CONST_NULL);
+                let slot = context.define_local_var(text, code.len());
+                emit_slot(code, OP_SET_LOCAL, slot);
+                context.captures.push((*e as *const Expr, slot));
+            }
+        }
+    }
+    let res = gen_expr(&assert.cond, context, cp, code);
+    let captures = mem::take(&mut context.captures);
+    res?;
+    let off = context.emit_jump(code, OP_IF);
+
+    for (_, e) in &operands {
+        match captures.iter().find(|(captured, _)| std::ptr::eq(*captured, *e)) {
+            Some((_, slot)) => emit_slot(code, OP_GET_LOCAL, *slot),
+            None => gen_expr(e, context, cp, code)?
+        }
+    }
+    match &assert.msg {
+        Some(msg) => gen_expr(msg, context, cp, code)?,
+        None => code.push(OP_CONST_NULL)
+    }
+
+    let desc = format!("`{}` failed at {}:{}", assert.text, assert.line, assert.column);
+    let desc = cp.const_string(&desc);
+    let names: Vec<_> = operands.iter().map(|(text, _)| cp.const_string(text)).collect();
+    // all the indexes are u32 after `WIDE`
+    let wide = names.iter().chain([&desc]).any(|idx| *idx > u16::MAX as u32);
+    if wide {
+        code.push(OP_WIDE);
+    }
+    code.push(OP_ASSERT);
+    for (i, idx) in [desc].iter().chain(&names).enumerate() {
+        if wide {
+            code.extend_from_slice(&idx.to_le_bytes());
+        } else {
+            code.extend_from_slice(&(*idx as u16).to_le_bytes());
+        }
+        // operand count after the description
+        if i == 0 {
+            code.push(names.len() as u8);
+        }
+    }
+
+    let end = code.len();
+    context.patch(code, off, end);
+    if getters {
+        context.pop_scope(end);
+    }
+    Ok(())
+}
+
+// variables, fields and getters are shown, variables are evaluated again, the values of getters are captured
+fn simple_operand_text(e: &Expr) -> Option<String> {
+    match &e.kind {
+        ExprKind::GetVar(var) => Some(var.clone()),
+        ExprKind::This => Some("this".to_owned()),
+        ExprKind::Getter(getter) => simple_operand_text(&getter.owner).map(|owner| format!("{owner}.{}", getter.member)),
+        _ => None
+    }
+}
+
+// evaluate `value` and store it to `pattern`, define new variables if `define` is true
+fn gen_destructure(pattern: &Pattern, value: &Expr, define: bool, context: &mut Context, cp: &mut ConstantPool, code: &mut Vec<u8>) -> Result<()> {
+    match (pattern, &value.kind) {
+        // `(a, b) = (b, a)`, no need to create a tuple
+        (Pattern::Tuple(patterns), ExprKind::Tuple(elements)) if patterns.len() == elements.len() => {
+            for e in elements {
+                gen_expr(e, context, cp, code)?;
+            }
+            for p in patterns.iter().rev() {
+                gen_store_pattern(p, define, context, cp, code);
+            }
+        }
+        _ => {
+            gen_expr(value, context, cp, code)?;
+            gen_store_pattern(pattern, define, context, cp, code);
+        }
+    }
+    Ok(())
+}
+
+// pop the top data of the operand stack and store it to `pattern`
+fn gen_store_pattern(pattern: &Pattern, define: bool, context: &mut Context, cp: &mut ConstantPool, code: &mut Vec<u8>) {
+    match pattern {
+        Pattern::Var(var) => if define {
+            if context.is_global_scope() {
+                emit_index(code, OP_DEF_GLOBAL, cp.const_string(var));
+            } else {
+                let idx = context.define_local_var(var, code.len());
+                emit_slot(code, OP_SET_LOCAL, idx);
+            }
+        } else if !context.is_global_scope() && let Some(local) = context.get_local_var(var) {
+            emit_slot(code, OP_SET_LOCAL, local);
+        } else {
+            emit_index(code, OP_SET_GLOBAL, cp.const_string(var));
+        }
+        Pattern::Tuple(patterns) => {
+            code.push(OP_UNPACK);
+            code.push(patterns.len() as u8);
+            // the last element is on the top of the operand stack
+            for p in patterns.iter().rev() {
+                gen_store_pattern(p, define, context, cp, code);
+            }
+        }
+    }
+}
+
 fn gen_expr(expr: &Expr, context: &mut Context, cp: &mut ConstantPool, code: &mut Vec<u8>) -> Result<()> {
-    match expr {
-        Expr::True => code.push(OP_CONST_TRUE),
-        Expr::Flase => code.push(OP_CONST_FALSE),
-        Expr::Null => code.push(OP_CONST_NULL),
-        Expr::This => {
+    context.enter_node(expr.offset, code.len());
+    let res = gen_expr_kind(expr, context, cp, code);
+    if let Some((_, slot)) = context.captures.iter().find(|(captured, _)| std::ptr::eq(*captured, expr)) {
+        code.push(OP_DUP);
+        emit_slot(code, OP_SET_LOCAL, *slot);
+    }
+    context.exit_node(code.len());
+    res
+}
+
+fn gen_expr_kind(expr: &Expr, context: &mut Context, cp: &mut ConstantPool, code: &mut Vec<u8>) -> Result<()> {
+    match &expr.kind {
+        ExprKind::True => code.push(OP_CONST_TRUE),
+        ExprKind::False => code.push(OP_CONST_FALSE),
+        ExprKind::Null => code.push(OP_CONST_NULL),
+        ExprKind::This => {
             let CallableType::Method(count) = context.callable_type else {
-                return Err("`this` can only used in methods".to_owned());
+                return Err(Error::new("`this` can only used in methods".to_owned(), expr.offset));
             };
-            code.push(OP_GET_LOCAL);
-            code.push(count);
+            emit_slot(code, OP_GET_LOCAL, count as u16);
         }
-        Expr::Long(v) => {
+        ExprKind::Long(v) => {
             let opcode = match *v {
                 -1 => OP_LCONST_M1,
                 0  => OP_LCONST_0,
@@ -430,22 +964,15 @@ fn gen_expr(expr: &Expr, context: &mut Context, cp: &mut ConstantPool, code: &mu
                 4  => OP_LCONST_4,
                 5  => OP_LCONST_5,
                 _  => {
-                    code.push(OP_LDC);
-                    code.extend_from_slice(&cp.const_long(*v).to_le_bytes());
+                    emit_index(code, OP_LDC, cp.const_long(*v));
                     return Ok(());
                 }
             };
             code.push(opcode);
         }
-        Expr::Double(v) => {
-            code.push(OP_LDC);
-            code.extend_from_slice(&cp.const_double(*v).to_le_bytes());
-        }
-        Expr::String(s) => {
-            code.push(OP_LDC);
-            code.extend_from_slice(&cp.const_string(s).to_le_bytes());
-        }
-        Expr::Binary(binary) => {
+        ExprKind::Double(v) => emit_index(code, OP_LDC, cp.const_double(*v)),
+        ExprKind::String(s) => emit_index(code, OP_LDC, cp.const_string(s)),
+        ExprKind::Binary(binary) => {
             gen_expr(&binary.left, context, cp, code)?;
             gen_expr(&binary.right, context, cp, code)?;
             let opcode = match binary.op {
@@ -462,49 +989,39 @@ fn gen_expr(expr: &Expr, context: &mut Context, cp: &mut ConstantPool, code: &mu
             };
             code.push(opcode);
         }
-        Expr::Logic(logic) => {
+        ExprKind::Logic(logic) => {
             match logic.op {
                 LogicOp::And => {
                     gen_expr(&logic.left, context, cp, code)?;
-                    code.push(OP_IF_NOT);
-                    let off = code.len() as u16;
-                    code.push(0);code.push(0);
+                    let off = context.emit_jump(code, OP_IF_NOT);
                     gen_expr(&logic.right, context, cp, code)?;
-                    code.push(OP_IF_NOT);
-                    let off2 = code.len() as u16;
-                    code.push(0);code.push(0);
+                    let off2 = context.emit_jump(code, OP_IF_NOT);
                     code.push(OP_CONST_TRUE);
-                    code.push(OP_GOTO);
-                    let off3 = code.len() as u16;
-                    code.push(0);code.push(0);
-                    let target = code.len() as u16;
+                    let off3 = context.emit_jump(code, OP_GOTO);
+                    let target = code.len();
                     code.push(OP_CONST_FALSE);
-                    patch(code, off, target);
-                    patch(code, off2, target);
-                    patch(code, off3, code.len() as u16);
+                    context.patch(code, off, target);
+                    context.patch(code, off2, target);
+                    let end = code.len();
+                    context.patch(code, off3, end);
                 }
                 LogicOp::Or => {
                     gen_expr(&logic.left, context, cp, code)?;
-                    code.push(OP_IF);
-                    let off = code.len() as u16;
-                    code.push(0);code.push(0);
+                    let off = context.emit_jump(code, OP_IF);
                     gen_expr(&logic.right, context, cp, code)?;
-                    code.push(OP_IF);
-                    let off2 = code.len() as u16;
-                    code.push(0);code.push(0);
+                    let off2 = context.emit_jump(code, OP_IF);
                     code.push(OP_CONST_FALSE);
-                    code.push(OP_GOTO);
-                    let off3 = code.len() as u16;
-                    code.push(0);code.push(0);
-                    let target = code.len() as u16;
+                    let off3 = context.emit_jump(code, OP_GOTO);
+                    let target = code.len();
                     code.push(OP_CONST_TRUE);
-                    patch(code, off, target);
-                    patch(code, off2, target);
-                    patch(code, off3, code.len() as u16);
+                    context.patch(code, off, target);
+                    context.patch(code, off2, target);
+                    let end = code.len();
+                    context.patch(code, off3, end);
                 }
             }
         }
-        Expr::Unary(unary) => {
+        ExprKind::Unary(unary) => {
             gen_expr(&unary.expr, context, cp, code)?;
             let opcode = match unary.op {
                 UnaryOp::Bang => OP_NOT,
@@ -512,7 +1029,11 @@ fn gen_expr(expr: &Expr, context: &mut Context, cp: &mut ConstantPool, code: &mu
             };
             code.push(opcode);
         }
-        Expr::Call(call) => {
+        ExprKind::Call(call) => {
+            check_arity(&call.owner, call.args.len(), context)?;
+            if call.args.len() > u8::MAX as usize {
+                return Err(Error::new(format!("call has {} arguments, the limit is {}", call.args.len(), u8::MAX), expr.offset));
+            }
             gen_expr(&call.owner, context, cp, code)?;
             for arg in &call.args {
                 gen_expr(arg, context, cp, code)?;
@@ -520,28 +1041,84 @@ fn gen_expr(expr: &Expr, context: &mut Context, cp: &mut ConstantPool, code: &mu
             code.push(OP_INVOKE);
             code.push(call.args.len() as u8);
         }
-        Expr::GetVar(getvar) => {
+        ExprKind::GetVar(getvar) => {
             if !context.is_global_scope() && let Some(idx) = context.get_local_var(getvar) {
-                code.push(OP_GET_LOCAL);
-                code.push(idx);
+                emit_slot(code, OP_GET_LOCAL, idx);
             } else {
-                code.push(OP_GET_GLOBAL);
-                code.extend_from_slice(&cp.const_string(getvar).to_le_bytes());
+                emit_index(code, OP_GET_GLOBAL, cp.const_string(getvar));
             }
         }
-        Expr::Getter(getter) => {
+        ExprKind::Getter(getter) => {
             gen_expr(&getter.owner, context, cp, code)?;
-            code.push(OP_GET_MEMBER);
-            code.extend_from_slice(&cp.const_string(&getter.member).to_le_bytes());
+            emit_index(code, OP_GET_MEMBER, cp.const_string(&getter.member));
+        }
+        ExprKind::Is(is) => {
+            if !context.decls.classes.contains_key(is.name.as_str())
+                && !context.decls.traits.contains_key(is.name.as_str()) {
+                return Err(Error::new(format!("unknown class or trait: {} after `is`", is.name), expr.offset));
+            }
+            gen_expr(&is.expr, context, cp, code)?;
+            emit_index(code, OP_IS, cp.const_string(&is.name));
+        }
+        ExprKind::Tuple(elements) => {
+            for e in elements {
+                gen_expr(e, context, cp, code)?;
+            }
+            code.push(OP_NEW_TUPLE);
+            code.push(elements.len() as u8);
+        }
+        ExprKind::Range(range) => {
+            gen_expr(&range.start, context, cp, code)?;
+            gen_expr(&range.end, context, cp, code)?;
+            code.push(OP_RANGE);
+            code.push(range.inclusive as u8);
+        }
+        ExprKind::Index(index) => {
+            gen_expr(&index.owner, context, cp, code)?;
+            gen_expr(&index.index, context, cp, code)?;
+            code.push(OP_INDEX);
+        }
+        ExprKind::Slice(slice) => {
+            gen_expr(&slice.owner, context, cp, code)?;
+            // `null` means no bound
+            for bound in [&slice.start, &slice.end] {
+                match bound {
+                    Some(e) => gen_expr(e, context, cp, code)?,
+                    None => code.push(OP_CONST_NULL)
+                }
+            }
+            code.push(OP_SLICE);
         }
     }
     Ok(())
 }
 
-fn patch(code: &mut Vec<u8>, to: u16, value: u16) {
-    let bytes = value.to_le_bytes();
-    unsafe {
-        code.as_mut_ptr().offset(to as isize).write(*bytes.get_unchecked(0));
-        code.as_mut_ptr().offset(to as isize + 1).write(*bytes.get_unchecked(1));
+// `WIDE` prefix if the constant index doesn't fit in u16
+fn emit_index(code: &mut Vec<u8>, opcode: u8, idx: u32) {
+    match u16::try_from(idx) {
+        Ok(idx) => {
+            code.push(opcode);
+            code.extend_from_slice(&idx.to_le_bytes());
+        }
+        Err(_) => {
+            code.push(OP_WIDE);
+            code.push(opcode);
+            code.extend_from_slice(&idx.to_le_bytes());
+        }
     }
-}
\ No newline at end of file
+}
+
+// `WIDE` prefix if the local variable slot doesn't fit in u8
+fn emit_slot(code: &mut Vec<u8>, opcode: u8, slot: u16) {
+    match u8::try_from(slot) {
+        Ok(slot) => {
+            code.push(opcode);
+            code.push(slot);
+        }
+        Err(_) => {
+            code.push(OP_WIDE);
+            code.push(opcode);
+            code.extend_from_slice(&slot.to_le_bytes());
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::mem;
+use crate::ast::{ClassDecl, Expr, ExprKind, FuncDecl, IfStmt, Program, Stmt, StmtKind, TraitDecl};
+use crate::lint::{Lint, Warning};
+
+// control-flow analysis over the ast: statements after `return`, `break`, `continue` or an endless loop,
+// the branch of `if` which is never taken, loops never run, and literal statements are removed with warnings
+
+pub fn remove_dead_code(program: &mut Program) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let Program { funcs, classes, traits } = program;
+    for func in funcs {
+        block(&mut func.body, &mut warnings);
+    }
+    for ClassDecl { methods, getters, setters, .. } in classes {
+        for method in methods.iter_mut().chain(getters).chain(setters) {
+            block(&mut method.body, &mut warnings);
+        }
+    }
+    for TraitDecl { provided, .. } in traits {
+        for FuncDecl { body, .. } in provided {
+            block(body, &mut warnings);
+        }
+    }
+    warnings
+}
+
+// returns whether the block never completes normally, so the statements after it are unreachable
+fn block(stmts: &mut Vec<Stmt>, warnings: &mut Vec<Warning>) -> bool {
+    let mut i = 0;
+    while i < stmts.len() {
+        let terminated = stmt(&mut stmts[i], warnings);
+        match &stmts[i].kind {
+            StmtKind::Block(inner) if inner.is_empty() => {
+                stmts.remove(i);
+            }
+            _ => i += 1
+        }
+
+        if terminated {
+            if let Some(next) = stmts.get(i) {
+                warnings.push(Warning::new(Lint::UnreachableCode, "unreachable statement".to_owned(), next.offset));
+                stmts.truncate(i);
+            }
+            return true;
+        }
+    }
+    false
+}
+
+// returns whether the statement never completes normally, dead parts are replaced by an empty block
+fn stmt(stmt: &mut Stmt, warnings: &mut Vec<Warning>) -> bool {
+    match &mut stmt.kind {
+        StmtKind::Return(_) | StmtKind::Break | StmtKind::Continue => true,
+        StmtKind::Expr(e) if is_literal(e) => {
+            warnings.push(Warning::new(Lint::UnusedExpression, "literal statement has no effect".to_owned(), e.offset));
+            stmt.kind = StmtKind::Block(Vec::new());
+            false
+        }
+        StmtKind::If(ifstmt) => match truthiness(&ifstmt.cond) {
+            Some(cond) => {
+                let IfStmt { then, els, .. } = ifstmt;
+                let (live, dead) = if cond { (then, els) } else { (els, then) };
+                if cond {
+                    if !dead.is_empty() {
+                        warnings.push(Warning::new(Lint::DeadBranch, "condition is always true, the `else` branch is never executed".to_owned(), ifstmt.cond.offset));
+                    }
+                } else {
+                    warnings.push(Warning::new(Lint::DeadBranch, "condition is always false, the branch is never executed".to_owned(), ifstmt.cond.offset));
+                }
+                // keeps the scope of the branch
+                let mut live = mem::take(live);
+                let terminated = block(&mut live, warnings);
+                stmt.kind = StmtKind::Block(live);
+                terminated
+            }
+            None => {
+                let then = block(&mut ifstmt.then, warnings);
+                let els = block(&mut ifstmt.els, warnings);
+                then && els
+            }
+        }
+        StmtKind::While(while_stmt) => match truthiness(&while_stmt.cond) {
+            Some(false) => {
+                warnings.push(Warning::new(Lint::LoopNeverRuns, "condition is always false, the loop never runs".to_owned(), while_stmt.cond.offset));
+                stmt.kind = StmtKind::Block(Vec::new());
+                false
+            }
+            // endless loop if nothing breaks out of it
+            Some(true) => {
+                block(&mut while_stmt.body, warnings);
+                !has_break(&while_stmt.body)
+            }
+            None => {
+                block(&mut while_stmt.body, warnings);
+                false
+            }
+        }
+        StmtKind::ForIn(for_in) => {
+            if let ExprKind::Range(range) = &for_in.iterable.kind
+                && let (ExprKind::Long(start), ExprKind::Long(end)) = (&range.start.kind, &range.end.kind)
+                && (if range.inclusive { start > end } else { start >= end }) {
+                warnings.push(Warning::new(Lint::LoopNeverRuns, "the range is empty, the loop never runs".to_owned(), for_in.iterable.offset));
+                stmt.kind = StmtKind::Block(Vec::new());
+                return false;
+            }
+            block(&mut for_in.body, warnings);
+            false
+        }
+        StmtKind::Block(inner) => block(inner, warnings),
+        StmtKind::VarDef(_) | StmtKind::Expr(_) | StmtKind::SetVar(_) | StmtKind::Setter(_)
+        | StmtKind::Defer(_) | StmtKind::Assert(_) => false
+    }
+}
+
+fn is_literal(e: &Expr) -> bool {
+    matches!(e.kind, ExprKind::True | ExprKind::False | ExprKind::Null | ExprKind::Long(_) | ExprKind::Double(_) | ExprKind::String(_))
+}
+
+// whether a literal condition is true, same as the vm converts values to boolean
+fn truthiness(e: &Expr) -> Option<bool> {
+    match &e.kind {
+        ExprKind::True => Some(true),
+        ExprKind::False | ExprKind::Null => Some(false),
+        ExprKind::Long(v) => Some(*v != 0),
+        ExprKind::Double(v) => Some(*v != 0f64),
+        ExprKind::String(s) => Some(!s.is_empty()),
+        _ => None
+    }
+}
+
+// `break` of this loop, not the nested loops
+fn has_break(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Break => true,
+        StmtKind::If(ifstmt) => has_break(&ifstmt.then) || has_break(&ifstmt.els),
+        StmtKind::Block(inner) => has_break(inner),
+        _ => false
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Program, StmtKind};
+    use crate::control_flow::{has_break, remove_dead_code};
+    use crate::lexer::Lexer;
+    use crate::lint::{Lint, Warning};
+    use crate::parser::Parser;
+
+    fn parse(s: &str) -> Program {
+        let tokens = Lexer::new(s.as_bytes()).lex().unwrap();
+        let (program, errors) = Parser::new(tokens, s.as_bytes()).parse();
+        assert!(errors.is_empty());
+        program
+    }
+
+    // the warnings, and the statements left in the body of `f`
+    fn remove(s: &str) -> (Vec<Warning>, usize) {
+        let mut program = parse(s);
+        let warnings = remove_dead_code(&mut program);
+        let f = program.funcs.iter().find(|f| f.name == "f").unwrap();
+        (warnings, f.body.len())
+    }
+
+    fn lints(warnings: &[Warning]) -> Vec<(Lint, &str)> {
+        warnings.iter().map(|w| (w.lint, w.msg.as_str())).collect()
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let source = "func f(a) { return a; __println(a); __println(a); }";
+        let (warnings, len) = remove(source);
+
+        assert_eq!(lints(&warnings), vec![(Lint::UnreachableCode, "unreachable statement")]);
+        assert_eq!(warnings[0].offset, source.find("__println").unwrap());
+        assert_eq!(len, 1);
+
+        // both branches return
+        let (warnings, len) = remove("func f(a) { if (a) { return 1; } else { return 2; } return 3; }");
+        assert_eq!(lints(&warnings), vec![(Lint::UnreachableCode, "unreachable statement")]);
+        assert_eq!(len, 1);
+
+        let (warnings, len) = remove("func f(a) { if (a) { return 1; } return 3; }");
+        assert!(warnings.is_empty());
+        assert_eq!(len, 2);
+    }
+
+    #[test]
+    fn test_dead_branch() {
+        let source = "func f(a) { if (true) { __println(a); } else { return a; } return a; }";
+        let (warnings, len) = remove(source);
+
+        assert_eq!(lints(&warnings), vec![(Lint::DeadBranch, "condition is always true, the `else` branch is never executed")]);
+        assert_eq!(warnings[0].offset, source.find("true").unwrap());
+        assert_eq!(len, 2);
+
+        // the `else` branch which is taken may terminate
+        let (warnings, len) = remove("func f(a) { if (0) { __println(a); } else { return a; } return 1; }");
+        assert_eq!(lints(&warnings), vec![
+            (Lint::DeadBranch, "condition is always false, the branch is never executed"),
+            (Lint::UnreachableCode, "unreachable statement")
+        ]);
+        assert_eq!(len, 1);
+
+        // nothing to report without `else`
+        let (warnings, _) = remove("func f(a) { if (\"s\") { __println(a); } }");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_loop_never_runs() {
+        let source = "func f(a) { while (null) { __println(a); } for (i in 3..3) { __println(i); } for (i in 3..=3) { __println(i); } }";
+        let (warnings, len) = remove(source);
+
+        assert_eq!(lints(&warnings), vec![
+            (Lint::LoopNeverRuns, "condition is always false, the loop never runs"),
+            (Lint::LoopNeverRuns, "the range is empty, the loop never runs")
+        ]);
+        assert_eq!(warnings[0].offset, source.find("null").unwrap());
+        assert_eq!(warnings[1].offset, source.find("..3)").unwrap());
+        assert_eq!(len, 1);
+    }
+
+    #[test]
+    fn test_endless_loop() {
+        let (warnings, len) = remove("func f(a) { while (1) { __println(a); } return a; }");
+        assert_eq!(lints(&warnings), vec![(Lint::UnreachableCode, "unreachable statement")]);
+        assert_eq!(len, 1);
+
+        let (warnings, len) = remove("func f(a) { while (true) { if (a) { break; } } return a; }");
+        assert!(warnings.is_empty());
+        assert_eq!(len, 2);
+
+        // `break` of the nested loop doesn't leave the outer one
+        let (warnings, len) = remove("func f(a) { while (true) { while (a) { break; } } return a; }");
+        assert_eq!(lints(&warnings), vec![(Lint::UnreachableCode, "unreachable statement")]);
+        assert_eq!(len, 1);
+    }
+
+    #[test]
+    fn test_has_break() {
+        let program = parse("func f(a) { { if (a) {} else { break; } } while (a) { break; } for (i in a) { break; } continue; }");
+        let body = &program.funcs.iter().find(|f| f.name == "f").unwrap().body;
+
+        assert!(has_break(&body[..1]));
+        assert!(!has_break(&body[1..]));
+        for stmt in &body[1..3] {
+            let (StmtKind::While(_) | StmtKind::ForIn(_)) = &stmt.kind else { panic!("{stmt:?}") };
+        }
+    }
+
+    #[test]
+    fn test_literal_statement() {
+        let source = "func f() { 1; \"s\"; null; }";
+        let (warnings, len) = remove(source);
+
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings.iter().all(|w| w.lint == Lint::UnusedExpression && w.msg == "literal statement has no effect"));
+        assert_eq!(warnings[1].offset, source.find('"').unwrap());
+        assert_eq!(len, 0);
+    }
+}
@@ -1,15 +1,24 @@
+#[derive(Debug, PartialEq)]
 pub struct Error {
     pub msg: String,
-    pub offset: usize
+    pub offset: usize,
+    pub help: Option<String>
 }
 
 impl Error {
     pub fn new(msg: String, offset: usize) -> Self {
         Self {
             msg,
-            offset
+            offset,
+            help: None
         }
     }
+
+    // a hint on how to fix it, printed below the source line
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_owned());
+        self
+    }
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
\ No newline at end of file
+pub type Result<T> = std::result::Result<T, Error>;
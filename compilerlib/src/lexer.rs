@@ -1,5 +1,6 @@
 use std::str::FromStr;
-use crate::token::{Token, TokenKind};
+use unicode_xid::UnicodeXID;
+use crate::token::{Comment, Token, TokenKind};
 use crate::err::{Result, Error};
 
 fn identifier_or_keyword(s: String) -> TokenKind {
@@ -10,13 +11,20 @@ fn identifier_or_keyword(s: String) -> TokenKind {
         "if" => TokenKind::If,
         "else" => TokenKind::Else,
         "while" => TokenKind::While,
+        "for" => TokenKind::For,
+        "in" => TokenKind::In,
         "break" => TokenKind::Break,
         "continue" => TokenKind::Continue,
         "return" => TokenKind::Return,
+        "defer" => TokenKind::Defer,
+        "assert" => TokenKind::Assert,
         "func" => TokenKind::Func,
         "class" => TokenKind::Class,
         "this" => TokenKind::This,
         "null" => TokenKind::Null,
+        "trait" => TokenKind::Trait,
+        "impl" => TokenKind::Impl,
+        "is" => TokenKind::Is,
         _ => TokenKind::Identifier(s)
     }
 }
@@ -24,7 +32,8 @@ fn identifier_or_keyword(s: String) -> TokenKind {
 pub struct Lexer<'a> {
     data: &'a [u8],
     offset: usize,
-    buf: String
+    buf: String,
+    comments: Vec<Comment>
 }
 
 impl Lexer<'_> {
@@ -32,12 +41,45 @@ impl Lexer<'_> {
         Lexer {
             data,
             offset: 0,
-            buf: String::new()
+            buf: String::new(),
+            comments: Vec::new()
         }
     }
 
     pub fn lex(mut self) -> Result<Vec<Token>> {
         let mut tokens = Vec::new();
+        while let Some(token) = self.next_token()? {
+            tokens.push(token);
+        }
+
+        Ok(tokens)
+    }
+
+    // the comments are kept as trivia for tools such as the formatter, the parser only takes the tokens
+    pub fn lex_with_comments(mut self) -> Result<(Vec<Token>, Vec<Comment>)> {
+        let mut tokens = Vec::new();
+        while let Some(token) = self.next_token()? {
+            tokens.push(token);
+        }
+
+        Ok((tokens, self.comments))
+    }
+
+    // byte length of the token at `off`, used to underline it in diagnostics, 1 if no token starts there
+    pub fn token_len(data: &[u8], off: usize) -> usize {
+        let mut lexer = Lexer {
+            data,
+            offset: off,
+            buf: String::new(),
+            comments: Vec::new()
+        };
+        match lexer.next_token() {
+            Ok(Some(token)) if token.offset == off => lexer.offset - off,
+            _ => 1
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>> {
         while let Some(c) = self.peek() {
             let off = self.offset;
             self.advance();
@@ -52,7 +94,16 @@ impl Lexer<'_> {
                 ']' => TokenKind::RBracket,
                 ';' => TokenKind::Semi,
                 ',' => TokenKind::Comma,
-                '.' => TokenKind::Dot,
+                ':' => TokenKind::Colon,
+                '.' => if self.consume('.') {
+                    if self.consume('=') {
+                        TokenKind::DotDotEq
+                    } else {
+                        TokenKind::DotDot
+                    }
+                } else {
+                    TokenKind::Dot
+                }
                 '=' => if self.consume('=') {
                     TokenKind::EqEq
                 } else {
@@ -99,19 +150,11 @@ impl Lexer<'_> {
                     TokenKind::Star
                 }
                 '#' => {
-                    while let Some(v) = self.next() {
-                        if v == b'\n' {
-                            break;
-                        }
-                    }
+                    self.skip_comment(off);
                     continue;
                 }
                 '/' => if self.consume('/') {
-                    while let Some(v) = self.next() {
-                        if v == b'\n' {
-                            break;
-                        }
-                    }
+                    self.skip_comment(off);
                     continue;
                 } else if self.consume('=') {
                     TokenKind::SlashEq
@@ -119,97 +162,226 @@ impl Lexer<'_> {
                     TokenKind::Slash
                 }
                 ' ' | '\t' | '\n' | '\r' => continue,
-                _ => if c == '"' {
-                    self.parse_string_literal()?
+                '"' => {
+                    let triple = self.consume_triple_quote();
+                    self.parse_string_literal(off, false, triple)?
+                }
+                _ => if c == 'r' && self.consume('"') {
+                    let triple = self.consume_triple_quote();
+                    self.parse_string_literal(off, true, triple)?
                 } else if c.is_ascii_digit() {
-                    match self.parse_long_double(c) {
-                        Ok(t) => t,
-                        Err(e) => return Err(Error::new(e, off))
+                    self.parse_number(c, off)?
+                } else {
+                    let Some(c) = self.char_at(off) else {
+                        return Err(Error::new("invalid utf-8".to_owned(), off));
+                    };
+                    if c != '_' && !c.is_xid_start() {
+                        return Err(Error::new(format!("unsupport char: {c}"), off));
                     }
-                } else if matches!(c, 'a'..='z' | 'A'..='Z' | '_') {
+                    self.offset = off + c.len_utf8();
                     self.parse_identifier_keyword(c)
-                } else {
-                    return Err(Error::new(format!("unsupport char: {c}"), off));
                 }
             };
-            tokens.push(Token::new(tok, off));
+            return Ok(Some(Token::new(tok, off)));
         }
 
-        Ok(tokens)
+        Ok(None)
     }
 
-    fn parse_string_literal(&mut self) -> Result<TokenKind> {
-        let mut s = String::new();
+    // `#` or `//` at `start` is consumed, the comment ends at the line break
+    fn skip_comment(&mut self, start: usize) {
+        while let Some(v) = self.next() {
+            if v == b'\n' {
+                break;
+            }
+        }
+        let text = String::from_utf8_lossy(&self.data[start..self.offset]);
+        self.comments.push(Comment::new(text.trim_end().to_owned(), start));
+    }
+
+    // the opening `"` is consumed, returns whether it's a triple-quoted string
+    fn consume_triple_quote(&mut self) -> bool {
+        if self.data[self.offset..].starts_with(b"\"\"") {
+            self.offset += 2;
+            true
+        } else {
+            false
+        }
+    }
+
+    // `start` is the offset of the literal, raw string takes no escapes,
+    // triple-quoted string can contain `"`, and the newline right after the opening quotes is skipped
+    fn parse_string_literal(&mut self, start: usize, raw: bool, triple: bool) -> Result<TokenKind> {
+        let mut bytes = Vec::new();
+
+        if triple && !self.consume('\n') && self.data[self.offset..].starts_with(b"\r\n") {
+            self.offset += 2;
+        }
 
-        let off = self.offset;
         while let Some(c) = self.next() {
-            let c = c as char;
             match c {
-                '\\' => if let Some(v) = self.next() {
-                    let v = v as char;
-                    let t = match v {
-                        '\\' => '\\',
-                        'r' => '\r',
-                        'n' => '\n',
-                        '"' => '"',
-                        't' => '\t',
-                        _ => return Err(Error::new(format!("unsupport escape sequence: \\{v}"), self.offset - 2))
-                    };
-                    s.push(t);
+                b'\\' if !raw => {
+                    let v = self.parse_escape(self.offset - 1)?;
+                    bytes.extend_from_slice(v.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                b'"' => if !triple || self.consume_triple_quote() {
+                    return Self::string_token(bytes, start);
                 } else {
-                    return Err(Error::new("escape sequence: no char found after: \\".to_owned(), self.offset - 1));
+                    bytes.push(c);
                 }
-                '"' => {
-                    if s.len() >= u16::MAX as usize {
-                        return Err(Error::new(format!("constant string too long, length: {}", s.len()), off));
-                    }
-                    return Ok(TokenKind::String(s))
-                },
-                _ => s.push(c)
+                _ => bytes.push(c)
             }
         }
 
-        Err(Error::new("unclosed string literal".to_owned(), off - 1))
+        let help = if triple { "add `\"\"\"` to close the string" } else { "add `\"` to close the string" };
+        Err(Error::new("unclosed string literal".to_owned(), start).with_help(help))
+    }
+
+    fn string_token(bytes: Vec<u8>, start: usize) -> Result<TokenKind> {
+        if bytes.len() >= u16::MAX as usize {
+            return Err(Error::new(format!("constant string too long, length: {}", bytes.len()), start));
+        }
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(TokenKind::String(s)),
+            Err(_) => Err(Error::new("invalid utf-8 in string literal".to_owned(), start))
+        }
+    }
+
+    // `off` is the offset of `\`
+    fn parse_escape(&mut self, off: usize) -> Result<char> {
+        let Some(v) = self.next() else {
+            return Err(Error::new("escape sequence: no char found after: \\".to_owned(), off));
+        };
+
+        let c = match v {
+            b'\\' => '\\',
+            b'r' => '\r',
+            b'n' => '\n',
+            b'"' => '"',
+            b't' => '\t',
+            // `\x41`, exactly 2 hex digits, at most 0x7F
+            b'x' => {
+                // `from_str_radix` also accepts a leading `+`
+                let digits = self.data.get(self.offset..self.offset + 2).filter(|s| s.iter().all(u8::is_ascii_hexdigit));
+                let Some(v) = digits.and_then(|s| u8::from_str_radix(std::str::from_utf8(s).ok()?, 16).ok()) else {
+                    return Err(Error::new("invalid escape sequence: \\x must be followed by 2 hex digits".to_owned(), off));
+                };
+                if v > 0x7F {
+                    return Err(Error::new(format!("out of range escape sequence: \\x{:02X}, must be at most \\x7F", v), off));
+                }
+                self.offset += 2;
+                v as char
+            }
+            // `\u{1F600}`, 1 to 6 hex digits
+            b'u' => {
+                let rest = &self.data[self.offset..];
+                let end = rest.iter().position(|v| *v == b'}');
+                let Some(end) = end.filter(|end| rest[0] == b'{' && (2..=7).contains(end)) else {
+                    return Err(Error::new("invalid escape sequence: \\u must be followed by {1 to 6 hex digits}".to_owned(), off));
+                };
+                let digits = Some(&rest[1..end]).filter(|s| s.iter().all(u8::is_ascii_hexdigit));
+                let Some(v) = digits.and_then(|s| u32::from_str_radix(std::str::from_utf8(s).ok()?, 16).ok()) else {
+                    return Err(Error::new("invalid escape sequence: \\u must be followed by {1 to 6 hex digits}".to_owned(), off));
+                };
+                let Some(c) = char::from_u32(v) else {
+                    return Err(Error::new(format!("invalid unicode scalar value: \\u{{{:X}}}", v), off));
+                };
+                self.offset += end + 1;
+                c
+            }
+            _ => return Err(Error::new(format!("unsupport escape sequence: \\{}", v as char), off))
+        };
+        Ok(c)
     }
 
-    fn parse_long_double(&mut self, first: char) -> std::result::Result<TokenKind, String> {
+    // `0xFF`, `0b1010`, `0o17`, `1_000_000`, `1.5`, `1.5e-3`
+    fn parse_number(&mut self, first: char, start: usize) -> Result<TokenKind> {
         self.buf.clear();
+
+        let radix = match (first, self.peek()) {
+            ('0', Some(b'x' | b'X')) => Some((16, "hexadecimal")),
+            ('0', Some(b'b' | b'B')) => Some((2, "binary")),
+            ('0', Some(b'o' | b'O')) => Some((8, "octal")),
+            _ => None
+        };
+        if let Some((radix, name)) = radix {
+            self.advance();
+            self.read_digits(radix)?;
+            if self.buf.is_empty() {
+                return Err(Error::new(format!("missing digits in {name} literal"), start));
+            }
+            if let Some(c) = self.peek() && c.is_ascii_alphanumeric() {
+                return Err(Error::new(format!("invalid digit for {name} literal: {}", c as char), self.offset));
+            }
+            return match i64::from_str_radix(&self.buf, radix) {
+                Ok(v) => Ok(TokenKind::Long(v)),
+                Err(e) => Err(Error::new(e.to_string(), start))
+            };
+        }
+
         self.buf.push(first);
+        self.read_digits(10)?;
 
-        let mut has_dot = false;
-        while let Some(c) = self.peek() {
-            if c.is_ascii_digit() {
-                self.advance();
-                self.buf.push(c as char);
-            } else if !has_dot && c == b'.' {
+        let mut is_double = false;
+        if self.peek() == Some(b'.') && self.peek_next().is_some_and(|v| v.is_ascii_digit()) {// `0..n` is a range
+            self.advance();
+            self.buf.push('.');
+            self.read_digits(10)?;
+            is_double = true;
+        }
+        if let Some(c @ (b'e' | b'E')) = self.peek() {
+            let off = self.offset;
+            self.advance();
+            self.buf.push(c as char);
+            if let Some(c @ (b'+' | b'-')) = self.peek() {
                 self.advance();
                 self.buf.push(c as char);
-                has_dot = true;
-            } else {
-                break;
             }
+            if !self.peek().is_some_and(|v| v.is_ascii_digit()) {
+                return Err(Error::new("missing digits in exponent".to_owned(), off));
+            }
+            self.read_digits(10)?;
+            is_double = true;
         }
 
-        if has_dot {
+        if is_double {
             match f64::from_str(&self.buf) {
                 Ok(v) => Ok(TokenKind::Double(v)),
-                Err(e) => Err(e.to_string())
+                Err(e) => Err(Error::new(e.to_string(), start))
             }
         } else {
             match i64::from_str(&self.buf) {
                 Ok(v) => Ok(TokenKind::Long(v)),
-                Err(e) => Err(e.to_string())
+                Err(e) => Err(Error::new(e.to_string(), start))
             }
         }
     }
 
+    // digits are pushed into `buf`, `_` separators are skipped
+    fn read_digits(&mut self, radix: u32) -> Result<()> {
+        while let Some(c) = self.peek() {
+            if (c as char).is_digit(radix) {
+                self.advance();
+                self.buf.push(c as char);
+            } else if c == b'_' {
+                if !self.peek_next().is_some_and(|v| (v as char).is_digit(radix)) {
+                    return Err(Error::new("`_` must be followed by a digit".to_owned(), self.offset));
+                }
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     fn parse_identifier_keyword(&mut self, first: char) -> TokenKind {
         let mut s = String::new();
         s.push(first);
 
-        while let Some(c) = self.peek() && (c.is_ascii_alphanumeric() || c == b'_') {
-            self.advance();
-            s.push(c as char);
+        while let Some(c) = self.char_at(self.offset) && c.is_xid_continue() {
+            self.offset += c.len_utf8();
+            s.push(c);
         }
 
         identifier_or_keyword(s)
@@ -222,10 +394,24 @@ impl Lexer<'_> {
         })
     }
 
+    // decode the char at `off`, `None` if it's not valid utf-8
+    fn char_at(&self, off: usize) -> Option<char> {
+        let bytes = self.data.get(off..(off + 4).min(self.data.len()))?;
+        let s = match std::str::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(e) => std::str::from_utf8(&bytes[..e.valid_up_to()]).ok()?
+        };
+        s.chars().next()
+    }
+
     fn peek(&self) -> Option<u8> {
         self.data.get(self.offset).copied()
     }
 
+    fn peek_next(&self) -> Option<u8> {
+        self.data.get(self.offset + 1).copied()
+    }
+
     fn advance(&mut self) {
         self.offset += 1;
     }
@@ -244,7 +430,7 @@ impl Lexer<'_> {
 #[cfg(test)]
 mod tests {
     use crate::lexer::{Error, Lexer};
-    use crate::token::{Token, TokenKind};
+    use crate::token::{Comment, Token, TokenKind};
 
     fn parse(s: &str) -> Result<Vec<Token>, Error> {
         Lexer::new(s.as_bytes()).lex()
@@ -285,13 +471,72 @@ f();
             Token::new(TokenKind::RParen, 30),
             Token::new(TokenKind::Semi, 31),
         ]);
+
+        let toks = parse("var r = 0..=10;");
+        assert!(toks.is_ok());
+        assert_eq!(toks.unwrap(), vec![
+            Token::new(TokenKind::Var, 0),
+            Token::new(TokenKind::Identifier("r".to_owned()), 4),
+            Token::new(TokenKind::Eq, 6),
+            Token::new(TokenKind::Long(0), 8),
+            Token::new(TokenKind::DotDotEq, 9),
+            Token::new(TokenKind::Long(10), 12),
+            Token::new(TokenKind::Semi, 14),
+        ]);
+    }
+
+    #[test]
+    fn test_literal() {
+        let toks = parse("0xFF 0b1010 0o17 1_000_000 1.5e-3 2E10 1_0.2_5");
+        assert!(toks.is_ok());
+        assert_eq!(toks.unwrap(), vec![
+            Token::new(TokenKind::Long(255), 0),
+            Token::new(TokenKind::Long(10), 5),
+            Token::new(TokenKind::Long(15), 12),
+            Token::new(TokenKind::Long(1_000_000), 17),
+            Token::new(TokenKind::Double(1.5e-3), 27),
+            Token::new(TokenKind::Double(2E10), 34),
+            Token::new(TokenKind::Double(10.25), 39),
+        ]);
+
+        let toks = parse(r#""\u{1F600}\x41\u{e9}" r"C:\path\n" "é""#);
+        assert!(toks.is_ok());
+        assert_eq!(toks.unwrap(), vec![
+            Token::new(TokenKind::String("\u{1F600}A\u{e9}".to_owned()), 0),
+            Token::new(TokenKind::String("C:\\path\\n".to_owned()), 22),
+            Token::new(TokenKind::String("é".to_owned()), 35),
+        ]);
+
+        let toks = parse("\"\"\"\nsay \"hi\"\n  \\tend\"\"\" r\"\"\"\\n\"\"\" \"\"");
+        assert!(toks.is_ok());
+        assert_eq!(toks.unwrap(), vec![
+            Token::new(TokenKind::String("say \"hi\"\n  \tend".to_owned()), 0),
+            Token::new(TokenKind::String("\\n".to_owned()), 24),
+            Token::new(TokenKind::String("".to_owned()), 34),
+        ]);
+    }
+
+    #[test]
+    fn test_unicode_identifier() {
+        let toks = parse("var 名字 = café_1;");
+        assert!(toks.is_ok());
+        assert_eq!(toks.unwrap(), vec![
+            Token::new(TokenKind::Var, 0),
+            Token::new(TokenKind::Identifier("名字".to_owned()), 4),
+            Token::new(TokenKind::Eq, 11),
+            Token::new(TokenKind::Identifier("café_1".to_owned()), 13),
+            Token::new(TokenKind::Semi, 20),
+        ]);
+
+        let toks = parse("var a = 1 € 2;");
+        assert_eq!(toks.err().unwrap(), Error::new("unsupport char: €".to_owned(), 10));
     }
 
     #[test]
     fn test_err() {
         let toks = parse(r#""abcdef"#);
         assert!(toks.is_err());
-        assert_eq!(toks.err().unwrap(), Error::new("unclosed string literal".to_owned(), 0));
+        assert_eq!(toks.err().unwrap(), Error::new("unclosed string literal".to_owned(), 0).with_help("add `\"` to close the string"));
 
         let toks = parse("var a &= 1;");
         assert!(toks.is_err());
@@ -304,5 +549,70 @@ f();
         let toks = parse(r#""abcdef\d"#);
         assert!(toks.is_err());
         assert_eq!(toks.err().unwrap(), Error::new("unsupport escape sequence: \\d".to_owned(), 7));
+
+        let toks = parse("var a = 0x;");
+        assert_eq!(toks.err().unwrap(), Error::new("missing digits in hexadecimal literal".to_owned(), 8));
+
+        let toks = parse("var a = 0b102;");
+        assert_eq!(toks.err().unwrap(), Error::new("invalid digit for binary literal: 2".to_owned(), 12));
+
+        let toks = parse("var a = 1_;");
+        assert_eq!(toks.err().unwrap(), Error::new("`_` must be followed by a digit".to_owned(), 9));
+
+        let toks = parse("var a = 1.5e+;");
+        assert_eq!(toks.err().unwrap(), Error::new("missing digits in exponent".to_owned(), 11));
+
+        let toks = parse("var a = 0x8000000000000000;");
+        assert_eq!(toks.err().unwrap(), Error::new("number too large to fit in target type".to_owned(), 8));
+
+        let toks = parse(r#"var a = "ab\x4";"#);
+        assert_eq!(toks.err().unwrap(), Error::new("invalid escape sequence: \\x must be followed by 2 hex digits".to_owned(), 11));
+
+        let toks = parse(r#"var a = "\x+F";"#);
+        assert_eq!(toks.err().unwrap(), Error::new("invalid escape sequence: \\x must be followed by 2 hex digits".to_owned(), 9));
+
+        let toks = parse(r#"var a = "\xFF";"#);
+        assert_eq!(toks.err().unwrap(), Error::new("out of range escape sequence: \\xFF, must be at most \\x7F".to_owned(), 9));
+
+        let toks = parse(r#"var a = "\u{1234567}";"#);
+        assert_eq!(toks.err().unwrap(), Error::new("invalid escape sequence: \\u must be followed by {1 to 6 hex digits}".to_owned(), 9));
+
+        let toks = parse(r#"var a = "\u{+41}";"#);
+        assert_eq!(toks.err().unwrap(), Error::new("invalid escape sequence: \\u must be followed by {1 to 6 hex digits}".to_owned(), 9));
+
+        let toks = parse(r#"var a = "a\u{D800}";"#);
+        assert_eq!(toks.err().unwrap(), Error::new("invalid unicode scalar value: \\u{D800}".to_owned(), 10));
+
+        let toks = parse(r#"var a = r"abc;"#);
+        assert_eq!(toks.err().unwrap(), Error::new("unclosed string literal".to_owned(), 8).with_help("add `\"` to close the string"));
+
+        let toks = parse("var a = \"\"\"abc\"\";");
+        assert_eq!(toks.err().unwrap(), Error::new("unclosed string literal".to_owned(), 8).with_help("add `\"\"\"` to close the string"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_token_len() {
+        let source = "var name = \"abc\" + 0x1F; // note".as_bytes();
+        assert_eq!(Lexer::token_len(source, 0), 3);
+        assert_eq!(Lexer::token_len(source, 4), 4);
+        assert_eq!(Lexer::token_len(source, 11), 5);
+        assert_eq!(Lexer::token_len(source, 19), 4);
+        // whitespace, comment and end of file
+        assert_eq!(Lexer::token_len(source, 3), 1);
+        assert_eq!(Lexer::token_len(source, 25), 1);
+        assert_eq!(Lexer::token_len(source, usize::MAX), 1);
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "#!/usr/bin/env charon\nvar a = 1; // one \r\n# two\n// three";
+        let (toks, comments) = Lexer::new(source.as_bytes()).lex_with_comments().unwrap();
+        assert_eq!(toks.len(), 5);
+        assert_eq!(comments, vec![
+            Comment::new("#!/usr/bin/env charon".to_owned(), 0),
+            Comment::new("// one".to_owned(), 33),
+            Comment::new("# two".to_owned(), 42),
+            Comment::new("// three".to_owned(), 48)
+        ]);
+    }
+}
@@ -1,29 +1,224 @@
 #![feature(let_chains)]
 
 use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::process::exit;
+use common::diagnostic::{Diagnostic, Level, Span};
 use common::line_column_info::LineColumnInfo;
 use crate::ast::Program;
 use crate::lexer::Lexer;
+use crate::lint::{LintLevels, Warning};
 use crate::parser::Parser;
-use crate::token::Token;
+use crate::token::{Comment, Token};
 
-mod token;
+pub mod token;
 mod lexer;
 mod parser;
-mod ast;
+pub mod ast;
+pub mod visit;
 mod err;
-pub mod code_gen;
+mod optimizer;
+mod control_flow;
+mod resolver;
+mod code_gen;
+pub mod lint;
 
-pub fn lex(bytes: &[u8]) -> Result<Vec<Token>, Box<dyn Error>> {
-    Lexer::new(bytes).lex().map_err(|e| { map_err(e, bytes).into() })
+pub use crate::visit::{Visitor, VisitorMut};
+pub use crate::resolver::{Definition, Reference};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OptLevel {
+    #[default]
+    None,
+    Basic// `-O`: constant folding & peephole optimizations
+}
+
+// options of `compile`, the default is the same as `charonc` without flags.
+// more options may be added, create it with `Options::default()` and set the fields
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Options {
+    pub opt_level: OptLevel,
+    pub debug_info: bool,// the source file name, line number & local variable tables, shown in runtime errors
+    pub strip_asserts: bool,// don't generate code for `assert`, for release bytecode
+    pub source_file: Option<String>,// file name shown in runtime errors
+    pub lints: LintLevels,// warnings which are reported
+    pub deny_warnings: bool// the reported warnings fail the compilation as errors
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            opt_level: OptLevel::None,
+            debug_info: true,
+            strip_asserts: false,
+            source_file: None,
+            lints: LintLevels::default(),
+            deny_warnings: false
+        }
+    }
+}
+
+// all the errors or warnings of a compile run
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    pub items: Vec<Diagnostic>
+}
+
+impl Diagnostics {
+    fn new(errors: Vec<err::Error>, bytes: &[u8]) -> Self {
+        let line_column_info = LineColumnInfo::new(bytes);
+        let items = errors.into_iter().map(|e| {
+            let mut item = diagnostic(Level::Error, e.msg, e.offset, bytes, &line_column_info);
+            item.help.extend(e.help);
+            item
+        }).collect();
+        Self { items }
+    }
+
+    fn warnings(warnings: Vec<Warning>, options: &Options, bytes: &[u8]) -> Self {
+        let line_column_info = LineColumnInfo::new(bytes);
+        let items = warnings.into_iter().filter(|w| !options.lints.is_allowed(w.lint)).map(|w| {
+            let mut item = diagnostic(Level::Warning, w.msg, w.offset, bytes, &line_column_info);
+            item.code = Some(w.lint.code().to_owned());
+            item.notes.push(format!("`{}` is on by default, `-A {}` to allow it", w.lint.name(), w.lint.name()));
+            item.help.extend(w.help);
+            item
+        }).collect();
+        Self { items }
+    }
+
+    // `--deny-warnings`, the warnings are reported as errors
+    fn deny(&mut self) {
+        for item in &mut self.items {
+            item.level = Level::Error;
+            item.notes.push("`--deny-warnings` is set".to_owned());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    // print the warnings with the source file path
+    pub fn print(mut self, file: &str) {
+        for item in &mut self.items {
+            item.file = Some(file.to_owned());
+            item.emit();
+        }
+    }
+
+    // print all the errors with the source file path, the exit code is the error count
+    pub fn print_and_exit(mut self, file: &str) -> ! {
+        for item in &mut self.items {
+            item.file = Some(file.to_owned());
+            item.emit();
+        }
+        exit(self.items.len().min(u8::MAX as usize) as i32);
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                writeln!(f, "\n")?;
+            }
+            write!(f, "{item}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Diagnostics {}
+
+fn diagnostic(level: Level, msg: String, offset: usize, bytes: &[u8], line_column_info: &LineColumnInfo) -> Diagnostic {
+    let (line, column) = line_column_info.line_column_info(offset);
+    let len = line_column_info.char_len(offset, Lexer::token_len(bytes, offset));
+    let mut item = Diagnostic::new(level, msg);
+    item.span = Some(Span { line, column, len, source_line: line_column_info.line_text(line) });
+    item
 }
 
-pub fn parse(tokens: Vec<Token>, bytes: &[u8]) -> Result<Program, Box<dyn Error>> {
-    Parser::new(tokens).parse().map_err(|e| { map_err(e, bytes).into() })
+pub fn lex(bytes: &[u8]) -> Result<Vec<Token>, Diagnostics> {
+    Lexer::new(bytes).lex().map_err(|e| Diagnostics::new(vec![e], bytes))
 }
 
-fn map_err(e: err::Error, bytes: &[u8]) -> String {
-    let line_column_info = LineColumnInfo::new(&bytes);
-    let (line, column) = line_column_info.line_column_info(e.offset);
-    format!("{}, ({line}: {column})", e.msg)
-}
\ No newline at end of file
+// the tokens with the comments, which are dropped by `lex`
+pub fn lex_with_comments(bytes: &[u8]) -> Result<(Vec<Token>, Vec<Comment>), Diagnostics> {
+    Lexer::new(bytes).lex_with_comments().map_err(|e| Diagnostics::new(vec![e], bytes))
+}
+
+// byte length of the token at `offset`, 1 if no token starts there
+pub fn token_len(bytes: &[u8], offset: usize) -> usize {
+    Lexer::token_len(bytes, offset)
+}
+
+// fails if there is any syntax error
+pub fn parse(tokens: Vec<Token>, bytes: &[u8]) -> Result<Program, Diagnostics> {
+    let (program, errors) = Parser::new(tokens, bytes).parse();
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(Diagnostics::new(errors, bytes))
+    }
+}
+
+// the program with the parts that parsed, and all the syntax errors, for tools working on code being edited
+pub fn parse_partial(tokens: Vec<Token>, bytes: &[u8]) -> (Program, Diagnostics) {
+    let (program, errors) = Parser::new(tokens, bytes).parse();
+    (program, Diagnostics::new(errors, bytes))
+}
+
+// the names resolved in the same scopes as the code generation, see `Reference`
+pub fn references(program: &Program, bytes: &[u8]) -> Vec<Reference> {
+    resolver::references(program, bytes)
+}
+
+// reports all the syntax errors, and the semantic errors of each class, method & function that parsed.
+// the warnings are dropped unless `options.deny_warnings` is set
+pub fn compile(bytes: &[u8], options: &Options) -> Result<Vec<u8>, Diagnostics> {
+    compile_with_warnings(bytes, options).0
+}
+
+// the warnings are returned even if there are errors, lints allowed by `options.lints` are not reported.
+// with `options.deny_warnings`, the warnings are moved into the errors
+pub fn compile_with_warnings(bytes: &[u8], options: &Options) -> (Result<Vec<u8>, Diagnostics>, Diagnostics) {
+    let (result, mut warnings) = compile_program(bytes, options);
+    if !options.deny_warnings || warnings.is_empty() {
+        return (result, warnings);
+    }
+    warnings.deny();
+    if let Err(e) = result {
+        warnings.items.extend(e.items);
+    }
+    (Err(warnings), Diagnostics::default())
+}
+
+fn compile_program(bytes: &[u8], options: &Options) -> (Result<Vec<u8>, Diagnostics>, Diagnostics) {
+    let tokens = match lex(bytes) {
+        Ok(v) => v,
+        Err(e) => return (Err(e), Diagnostics::default())
+    };
+    let (mut program, mut errors) = Parser::new(tokens, bytes).parse();
+    let mut warnings = resolver::resolve(&program, bytes);
+    // the whole program is checked, the semantic errors of dead code are reported too
+    let checked = code_gen::check_and_gen(&program, bytes, options);
+    // dead code is never generated
+    warnings.extend(control_flow::remove_dead_code(&mut program));
+    warnings.sort_by_key(|w| w.offset);
+    let warnings = Diagnostics::warnings(warnings, options, bytes);
+    if options.opt_level != OptLevel::None {
+        optimizer::fold_constants(&mut program);
+    }
+    match checked.and_then(|_| code_gen::check_and_gen(&program, bytes, options)) {
+        Ok(code) if errors.is_empty() => return (Ok(code), warnings),
+        Ok(_) => (),
+        Err(e) => {
+            errors.extend(e);
+            errors.sort_by_key(|e| e.offset);
+        }
+    }
+    (Err(Diagnostics::new(errors, bytes)), warnings)
+}
@@ -0,0 +1,110 @@
+// warnings of the compiler, each lint has a code and a name, both can be used in `-W`/`-A` of `charonc`
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnreachableCode,
+    DeadBranch,
+    LoopNeverRuns,
+    UnusedExpression,
+    UnknownIdentifier,
+    UnusedVariable,
+    ShadowedVariable,
+    RedefinedVariable
+}
+
+impl Lint {
+    pub const ALL: [Lint; 8] = [
+        Lint::UnreachableCode, Lint::DeadBranch, Lint::LoopNeverRuns, Lint::UnusedExpression,
+        Lint::UnknownIdentifier, Lint::UnusedVariable, Lint::ShadowedVariable, Lint::RedefinedVariable
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lint::UnreachableCode => "W0001",
+            Lint::DeadBranch => "W0002",
+            Lint::LoopNeverRuns => "W0003",
+            Lint::UnusedExpression => "W0004",
+            Lint::UnknownIdentifier => "W0005",
+            Lint::UnusedVariable => "W0006",
+            Lint::ShadowedVariable => "W0007",
+            Lint::RedefinedVariable => "W0008"
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::DeadBranch => "dead-branch",
+            Lint::LoopNeverRuns => "loop-never-runs",
+            Lint::UnusedExpression => "unused-expression",
+            Lint::UnknownIdentifier => "unknown-identifier",
+            Lint::UnusedVariable => "unused-variable",
+            Lint::ShadowedVariable => "shadowed-variable",
+            Lint::RedefinedVariable => "redefined-variable"
+        }
+    }
+
+    // by name or code
+    pub fn from_arg(arg: &str) -> Option<Lint> {
+        Self::ALL.into_iter().find(|lint| lint.name() == arg || lint.code().eq_ignore_ascii_case(arg))
+    }
+}
+
+// all lints warn by default
+#[derive(Debug, Default, Clone)]
+pub struct LintLevels {
+    allowed: Vec<Lint>
+}
+
+impl LintLevels {
+    // `-A name`, `-A warnings` allows all
+    pub fn allow(&mut self, arg: &str) -> bool {
+        if arg == "warnings" {
+            self.allowed = Lint::ALL.to_vec();
+            return true;
+        }
+        let Some(lint) = Lint::from_arg(arg) else {
+            return false;
+        };
+        if !self.allowed.contains(&lint) {
+            self.allowed.push(lint);
+        }
+        true
+    }
+
+    // `-W name`, `-W warnings` warns all
+    pub fn warn(&mut self, arg: &str) -> bool {
+        if arg == "warnings" {
+            self.allowed.clear();
+            return true;
+        }
+        let Some(lint) = Lint::from_arg(arg) else {
+            return false;
+        };
+        self.allowed.retain(|v| *v != lint);
+        true
+    }
+
+    pub fn is_allowed(&self, lint: Lint) -> bool {
+        self.allowed.contains(&lint)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub msg: String,
+    pub offset: usize,
+    pub help: Option<String>
+}
+
+impl Warning {
+    pub fn new(lint: Lint, msg: String, offset: usize) -> Self {
+        Self { lint, msg, offset, help: None }
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_owned());
+        self
+    }
+}
@@ -0,0 +1,315 @@
+use common::opcode::*;
+use common::program::{LineNumber, LocalVariable};
+use crate::ast::{BinaryOp, Expr, ExprKind, Program, Stmt, StmtKind, UnaryOp};
+use crate::visit::{walk_expr_mut, walk_stmt_mut, VisitorMut};
+
+// `-O`: constant folding on the ast before generating code, then peephole optimizations on the code of each function:
+// jump threading, removing unreachable code and `goto` to the next instruction.
+// the result of the optimized code is identical to the unoptimized code, including the runtime errors
+
+pub fn fold_constants(program: &mut Program) {
+    FoldConstants.visit_program_mut(program);
+}
+
+struct FoldConstants;
+
+impl VisitorMut for FoldConstants {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            // the operands of the condition are reported by name, keep them
+            StmtKind::Assert(assert) => if let Some(msg) = &mut assert.msg {
+                self.visit_expr_mut(msg);
+            }
+            _ => walk_stmt_mut(self, stmt)
+        }
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+        if let Some(kind) = folded(expr) {
+            expr.kind = kind;
+        }
+    }
+}
+
+// the constant value of `expr` whose operands are folded, same as the vm computes.
+// comparisons are not folded, the vm's boolean result differs from the literal `true` & `false`
+fn folded(expr: &Expr) -> Option<ExprKind> {
+    match &expr.kind {
+        ExprKind::Unary(unary) => match (&unary.op, &unary.expr.kind) {
+            (UnaryOp::Neg, ExprKind::Long(v)) => v.checked_neg().map(ExprKind::Long),
+            (UnaryOp::Neg, ExprKind::Double(v)) => Some(ExprKind::Double(-v)),
+            _ => None
+        }
+        ExprKind::Binary(binary) => {
+            let (l, r) = (&binary.left.kind, &binary.right.kind);
+            match (l, r) {
+                (ExprKind::Long(l), ExprKind::Long(r)) => match binary.op {
+                    BinaryOp::Add => l.checked_add(*r),
+                    BinaryOp::Sub => l.checked_sub(*r),
+                    BinaryOp::Multiply => l.checked_mul(*r),
+                    BinaryOp::Divide => l.checked_div(*r),
+                    _ => None
+                }.map(ExprKind::Long),
+                (ExprKind::Long(_) | ExprKind::Double(_), ExprKind::Long(_) | ExprKind::Double(_)) => {
+                    let (l, r) = (as_double(l)?, as_double(r)?);
+                    match binary.op {
+                        BinaryOp::Add => Some(l + r),
+                        BinaryOp::Sub => Some(l - r),
+                        BinaryOp::Multiply => Some(l * r),
+                        BinaryOp::Divide => Some(l / r),
+                        _ => None
+                    }.map(ExprKind::Double)
+                }
+                (ExprKind::String(l), _) if matches!(binary.op, BinaryOp::Add) => {
+                    let r = match r {
+                        ExprKind::String(r) => r.clone(),
+                        ExprKind::Long(r) => r.to_string(),
+                        ExprKind::Double(r) => r.to_string(),
+                        ExprKind::True => "true".to_owned(),
+                        ExprKind::False => "false".to_owned(),
+                        ExprKind::Null => "null".to_owned(),
+                        _ => return None
+                    };
+                    // must fit in a constant string
+                    (l.len() + r.len() < u16::MAX as usize).then(|| ExprKind::String(format!("{l}{r}")))
+                }
+                _ => None
+            }
+        }
+        _ => None
+    }
+}
+
+fn as_double(kind: &ExprKind) -> Option<f64> {
+    match kind {
+        ExprKind::Long(v) => Some(*v as f64),
+        ExprKind::Double(v) => Some(*v),
+        _ => None
+    }
+}
+
+struct Inst {
+    pc: usize,// offset in the unoptimized code
+    bytes: Vec<u8>,
+    target: Option<usize>// jump target, offset in the unoptimized code
+}
+
+impl Inst {
+    // the opcode after the `WIDE` prefix
+    fn opcode(&self) -> u8 {
+        if self.bytes[0] == OP_WIDE {
+            self.bytes[1]
+        } else {
+            self.bytes[0]
+        }
+    }
+}
+
+// peephole optimizations, the offsets in the line number & local variable tables are updated
+pub fn optimize_code(code: &mut Vec<u8>, line_numbers: &mut Vec<LineNumber>, local_vars: &mut [LocalVariable]) {
+    let Some(mut insts) = decode(code) else {
+        return;
+    };
+    let end = code.len();
+
+    loop {
+        let mut changed = thread_jumps(&mut insts, end);
+        changed |= remove_unreachable(&mut insts, end);
+        changed |= remove_goto_next(&mut insts, end);
+        if !changed {
+            break;
+        }
+    }
+
+    // offset in the unoptimized code -> offset in the optimized code, removed instructions map to the next one
+    let mut new_pcs = Vec::with_capacity(insts.len());
+    let mut len = 0;
+    for inst in &insts {
+        new_pcs.push(len);
+        len += inst.bytes.len();
+    }
+    let new_pc = |pc: usize| {
+        let idx = insts.partition_point(|inst| inst.pc < pc);
+        new_pcs.get(idx).copied().unwrap_or(len)
+    };
+
+    let mut optimized = Vec::with_capacity(len);
+    for inst in &insts {
+        optimized.extend_from_slice(&inst.bytes);
+        // the target is the last operand, u32 after `WIDE`
+        if let Some(target) = inst.target {
+            if inst.bytes[0] == OP_WIDE {
+                let at = optimized.len() - 4;
+                optimized[at..].copy_from_slice(&(new_pc(target) as u32).to_le_bytes());
+            } else {
+                let at = optimized.len() - 2;
+                optimized[at..].copy_from_slice(&(new_pc(target) as u16).to_le_bytes());
+            }
+        }
+    }
+
+    let mut relocated: Vec<LineNumber> = Vec::with_capacity(line_numbers.len());
+    for v in line_numbers.iter() {
+        let start_pc = new_pc(v.start_pc as usize);
+        if start_pc == len {
+            break;
+        }
+        // the later one belongs to the instruction at `start_pc`
+        if relocated.last().is_some_and(|last| last.start_pc as usize == start_pc) {
+            relocated.pop();
+        }
+        if !relocated.last().is_some_and(|last| last.line == v.line && last.column == v.column) {
+            relocated.push(LineNumber { start_pc: start_pc as u32, ..*v });
+        }
+    }
+    *line_numbers = relocated;
+
+    for v in local_vars {
+        v.start_pc = new_pc(v.start_pc as usize) as u32;
+        v.end_pc = new_pc(v.end_pc as usize) as u32;
+    }
+
+    *code = optimized;
+}
+
+fn decode(code: &[u8]) -> Option<Vec<Inst>> {
+    let mut insts = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let len = instruction_len(&code[pc..])?;
+        let bytes = code[pc..pc + len].to_vec();
+        let inst = Inst { pc, bytes, target: None };
+        let target = is_jump(inst.opcode()).then(|| match inst.bytes[..] {
+            [OP_WIDE, _, a, b, c, d] => u32::from_le_bytes([a, b, c, d]) as usize,
+            _ => u16::from_le_bytes([inst.bytes[1], inst.bytes[2]]) as usize
+        });
+        insts.push(Inst { target, ..inst });
+        pc += len;
+    }
+    Some(insts)
+}
+
+// index of the instruction at `pc`, or the next one if it's removed
+fn index_of(insts: &[Inst], pc: usize) -> usize {
+    insts.partition_point(|inst| inst.pc < pc)
+}
+
+// where the code continues after reaching the instruction at `idx`, following `goto` and constant conditions:
+// `goto L` continues at L, `const_true; if L` continues at L, `const_true; if_not L` continues after the `if_not`
+fn jump_destination(insts: &[Inst], idx: usize, end: usize) -> Option<usize> {
+    let inst = insts.get(idx)?;
+    match inst.opcode() {
+        OP_GOTO => inst.target,
+        OP_CONST_TRUE | OP_CONST_FALSE => {
+            let next = insts.get(idx + 1)?;
+            let jumps = match next.opcode() {
+                OP_IF => inst.opcode() == OP_CONST_TRUE,
+                OP_IF_NOT => inst.opcode() == OP_CONST_FALSE,
+                _ => return None
+            };
+            if jumps {
+                next.target
+            } else {
+                Some(insts.get(idx + 2).map_or(end, |v| v.pc))
+            }
+        }
+        _ => None
+    }
+}
+
+fn thread_jumps(insts: &mut [Inst], end: usize) -> bool {
+    let mut changed = false;
+    for i in 0 .. insts.len() {
+        let Some(mut target) = insts[i].target else {
+            continue;
+        };
+        let original = target;
+        let mut visited = vec![target];
+        while let Some(next) = jump_destination(insts, index_of(insts, target), end) {
+            if visited.contains(&next) {
+                // jumps form an infinite loop, keep it as it is
+                target = original;
+                break;
+            }
+            visited.push(next);
+            target = next;
+        }
+        if insts[i].target != Some(target) {
+            insts[i].target = Some(target);
+            changed = true;
+        }
+    }
+
+    // `const_true; goto L` where L is `if`/`if_not`, or `const_true; if_not L`: the constant becomes a `goto`
+    for i in 0 .. insts.len() {
+        if !matches!(insts[i].opcode(), OP_CONST_TRUE | OP_CONST_FALSE) {
+            continue;
+        }
+        let Some(next) = insts.get(i + 1) else {
+            continue;
+        };
+        let cond = if next.opcode() == OP_GOTO && let Some(target) = next.target {
+            index_of(insts, target)
+        } else {
+            i + 1
+        };
+        let Some(cond) = insts.get(cond) else {
+            continue;
+        };
+        let jumps = match cond.opcode() {
+            OP_IF => insts[i].opcode() == OP_CONST_TRUE,
+            OP_IF_NOT => insts[i].opcode() == OP_CONST_FALSE,
+            _ => continue
+        };
+        let target = if jumps {
+            cond.target
+        } else {
+            Some(insts.get(index_of(insts, cond.pc) + 1).map_or(end, |v| v.pc))
+        };
+        // code longer than u16 is generated with wide jumps
+        insts[i].bytes = if end > u16::MAX as usize { vec![OP_WIDE, OP_GOTO, 0, 0, 0, 0] } else { vec![OP_GOTO, 0, 0] };
+        insts[i].target = target;
+        changed = true;
+    }
+    changed
+}
+
+fn remove_unreachable(insts: &mut Vec<Inst>, end: usize) -> bool {
+    let mut reachable = vec![false; insts.len()];
+    let mut pending = vec![0];
+    while let Some(idx) = pending.pop() {
+        if idx >= insts.len() || reachable[idx] {
+            continue;
+        }
+        reachable[idx] = true;
+
+        let inst = &insts[idx];
+        if let Some(target) = inst.target && target < end {
+            pending.push(index_of(insts, target));
+        }
+        // `defer` registers the block right after it
+        if !matches!(inst.opcode(), OP_GOTO | OP_RETURN) {
+            pending.push(idx + 1);
+        }
+    }
+
+    let len = insts.len();
+    let mut reachable = reachable.into_iter();
+    insts.retain(|_| reachable.next().unwrap());
+    insts.len() != len
+}
+
+fn remove_goto_next(insts: &mut Vec<Inst>, end: usize) -> bool {
+    let len = insts.len();
+    let mut i = 0;
+    while i < insts.len() {
+        let next = insts.get(i + 1).map_or(end, |v| v.pc);
+        if insts[i].opcode() == OP_GOTO && insts[i].target.is_some_and(|target| index_of(insts, target) == index_of(insts, next)) {
+            insts.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    insts.len() != len
+}
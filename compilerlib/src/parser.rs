@@ -1,47 +1,99 @@
 use std::mem;
 use crate::ast::*;
 use common::constant::ENTRY_NAME;
+use common::line_column_info::LineColumnInfo;
 use crate::token::{Token, TokenKind};
 use crate::err::{Result, Error};
 
-pub struct Parser {
+pub struct Parser<'a> {
     tokens: Vec<Token>,
-    offset: usize
+    offset: usize,
+    source: &'a [u8],// for the source text of `assert`
+    line_column_info: Option<LineColumnInfo<'a>>,// of `source`, built at the first `assert`
+    errors: Vec<Error>
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<Token>, source: &'a [u8]) -> Self {
         Self {
             tokens,
-            offset: 0
+            offset: 0,
+            source,
+            line_column_info: None,
+            errors: Vec::new()
         }
     }
 
-    pub fn parse(mut self) -> Result<Program> {
+    // returns the program with all the errors, the parts that failed to parse are skipped
+    pub fn parse(mut self) -> (Program, Vec<Error>) {
         let mut funcs = Vec::new();
         let mut classes = Vec::new();
+        let mut traits = Vec::new();
         let mut stmts = Vec::new();
         
         while let Some(tok) = self.peek() {
-            match tok.kind {
+            let res = match tok.kind {
                 TokenKind::Func => {
                     self.advance();
-                    funcs.push(self.parse_function()?);
+                    self.parse_function().map(|func| funcs.push(func))
                 }
                 TokenKind::Class => {
                     self.advance();
-                    classes.push(self.parse_class()?);
+                    self.parse_class().map(|class| classes.push(class))
+                }
+                TokenKind::Trait => {
+                    self.advance();
+                    self.parse_trait().map(|t| traits.push(t))
+                }
+                _ => self.parse_stmt().map(|stmt| stmts.push(stmt))
+            };
+            if let Err(e) = res {
+                self.errors.push(e);
+                self.synchronize();
+                // unmatched `}` in global scope
+                if self.peek().is_some_and(|t| t.kind == TokenKind::RBrace) {
+                    self.advance();
                 }
-                _ => stmts.push(self.parse_stmt()?)
             }
         }
         
-        let entry = FuncDecl::new(ENTRY_NAME.to_owned(), Vec::new(), stmts);
+        let entry = FuncDecl::new(ENTRY_NAME.to_owned(), Vec::new(), stmts, 0);
         funcs.push(entry);
-        Ok(Program::new(funcs, classes))
+        (Program::new(funcs, classes, traits), self.errors)
+    }
+
+    // panic-mode recovery, skip tokens until the end of the statement: after `;`, or before `}`, `func`, `class`
+    fn synchronize(&mut self) {
+        // the token which caused the error may already be consumed
+        match self.offset.checked_sub(1).and_then(|i| self.tokens.get(i)).map(|t| &t.kind) {
+            Some(TokenKind::Semi) => return,
+            Some(TokenKind::RBrace) => {
+                self.offset -= 1;
+                return;
+            }
+            _ => ()
+        }
+
+        while let Some(tok) = self.peek() {
+            match tok.kind {
+                TokenKind::Semi => {
+                    self.advance();
+                    return;
+                }
+                TokenKind::RBrace | TokenKind::Func | TokenKind::Class => return,
+                _ => self.advance()
+            }
+        }
     }
     
     fn parse_function(&mut self) -> Result<FuncDecl> {
+        let (name, params, off) = self.parse_function_head()?;
+        self.consume_or_err(&TokenKind::LBrace)?;
+        Ok(FuncDecl::new(name, params, self.parse_block()?, off))
+    }
+
+    // function's name & params & name's offset
+    fn parse_function_head(&mut self) -> Result<(String, Vec<String>, usize)> {
         let tok = self.next_or_err()?;
         let Token {kind: TokenKind::Identifier(name), offset: off } = tok else {
             return Err(Error::new("function name not found after keyword `func`".to_owned(), tok.offset));
@@ -68,77 +120,174 @@ impl Parser {
         
         self.consume_or_err(&TokenKind::RParen)?;
         
-        self.consume_or_err(&TokenKind::LBrace)?;
-        
         if params.len() > u8::MAX as usize {
             return Err(Error::new(format!("function: {name} has too many params"), func_off));
         }
         
-        Ok(FuncDecl::new(name, params, self.parse_block()?))
+        Ok((name, params, func_off))
     }
 
     fn parse_class(&mut self) -> Result<ClassDecl> {
         let tok = self.next_or_err()?;
-        let Token {kind: TokenKind::Identifier(name), offset: _ } = tok else {
+        let Token {kind: TokenKind::Identifier(name), offset: class_off } = tok else {
             return Err(Error::new("class name not found after keyword `class`".to_owned(), tok.offset));
         };
+        let class_off = *class_off;
         let name = name.to_owned();
+
+        let mut traits = Vec::new();
+        if self.consume(&TokenKind::Impl) {
+            loop {
+                let tok = self.next_or_err()?;
+                let Token {kind: TokenKind::Identifier(trait_name), offset: _} = tok else {
+                    return Err(Error::new("expected trait name after keyword `impl`".to_owned(), tok.offset));
+                };
+                traits.push(trait_name.to_owned());
+                if !self.consume(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
         self.consume_or_err(&TokenKind::LBrace)?;
         
         let mut methods = Vec::new();
+        let mut getters = Vec::new();
+        let mut setters = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token {kind: TokenKind::Func, offset: off}) => {
+                    let off = *off;
+                    self.advance();
+                    let func = self.parse_function()?;
+                    if func.params.len() >= u8::MAX as usize {// method has 'this' as arg
+                        return Err(Error::new(format!("method: {name} has too many params"), off));
+                    }
+                    methods.push(func);
+                }
+                // `get` and `set` are only keywords inside class body
+                Some(Token {kind: TokenKind::Identifier(accessor), offset: off}) if accessor == "get" || accessor == "set" => {
+                    let off = *off;
+                    let is_getter = accessor == "get";
+                    self.advance();
+                    let func = self.parse_function()?;
+                    if is_getter {
+                        if !func.params.is_empty() {
+                            return Err(Error::new(format!("getter: {} can't have params", func.name), off));
+                        }
+                        getters.push(func);
+                    } else {
+                        if func.params.len() != 1 {
+                            return Err(Error::new(format!("setter: {} must have exactly one param", func.name), off));
+                        }
+                        setters.push(func);
+                    }
+                }
+                _ => break
+            }
+        }
+        self.consume_or_err(&TokenKind::RBrace)?;
+        Ok(ClassDecl::new(name, traits, methods, getters, setters, class_off))
+    }
+
+    fn parse_trait(&mut self) -> Result<TraitDecl> {
+        let tok = self.next_or_err()?;
+        let Token {kind: TokenKind::Identifier(name), offset: trait_off } = tok else {
+            return Err(Error::new("trait name not found after keyword `trait`".to_owned(), tok.offset));
+        };
+        let trait_off = *trait_off;
+        let name = name.to_owned();
+        self.consume_or_err(&TokenKind::LBrace)?;
+
+        let mut required = Vec::new();
+        let mut provided = Vec::new();
         while let Some(Token {kind: TokenKind::Func, offset: off}) = self.peek() {
             let off = *off;
             self.advance();
-            let func = self.parse_function()?;
-            if func.params.len() >= u8::MAX as usize {// method has 'this' as arg
-                return Err(Error::new(format!("method: {name} has too many params"), off));
+            let (method_name, params, method_off) = self.parse_function_head()?;
+            if params.len() >= u8::MAX as usize {// method has 'this' as arg
+                return Err(Error::new(format!("method: {method_name} has too many params"), off));
+            }
+            if self.consume(&TokenKind::Semi) {
+                required.push(FuncDecl::new(method_name, params, Vec::new(), method_off));
+            } else {
+                self.consume_or_err(&TokenKind::LBrace)?;
+                provided.push(FuncDecl::new(method_name, params, self.parse_block()?, method_off));
             }
-            methods.push(func);
         }
         self.consume_or_err(&TokenKind::RBrace)?;
-        Ok(ClassDecl::new(name, methods))
+        Ok(TraitDecl::new(name, required, provided, trait_off))
     }
     
     fn parse_stmt(&mut self) -> Result<Stmt> {
         let tok = self.next_or_err()?;
+        let off = tok.offset;
         
-        let stmt = match &tok.kind {
-            TokenKind::Var => Stmt::VarDef(self.parse_var_def()?),
-            TokenKind::If => Stmt::If(self.parse_if()?),
-            TokenKind::While => Stmt::While(self.parse_while()?),
+        let kind = match &tok.kind {
+            TokenKind::Var => StmtKind::VarDef(self.parse_var_def()?),
+            TokenKind::If => StmtKind::If(self.parse_if()?),
+            TokenKind::While => StmtKind::While(self.parse_while()?),
+            TokenKind::For => StmtKind::ForIn(self.parse_for_in()?),
             TokenKind::Break => {
                 self.consume_or_err(&TokenKind::Semi)?;
-                Stmt::Break
+                StmtKind::Break
             }
             TokenKind::Continue => {
                 self.consume_or_err(&TokenKind::Semi)?;
-                Stmt::Continue
+                StmtKind::Continue
+            }
+            TokenKind::Return => StmtKind::Return(self.parse_return()?),
+            TokenKind::Defer => {
+                let e = self.parse_expr()?;
+                self.consume_or_err(&TokenKind::Semi)?;
+                StmtKind::Defer(Box::new(e))
             }
-            TokenKind::Return => Stmt::Return(self.parse_return()?),
-            TokenKind::LBrace => Stmt::Block(self.parse_block()?),
+            TokenKind::Assert => StmtKind::Assert(self.parse_assert()?),
+            TokenKind::LBrace => StmtKind::Block(self.parse_block()?),
             _ => {
                 self.offset -= 1;
                 self.parse_assign_or_expr_stmt()?
             }
         };
-        Ok(stmt)
+        Ok(Stmt::new(kind, off))
     }
     
     fn parse_var_def(&mut self) -> Result<VarDefStmt> {
-        let tok = self.next_or_err()?;
-        let Token {kind: TokenKind::Identifier(name), offset: _} = tok else {
-            return Err(Error::new("expected variable name after keyword `var`".to_owned(), tok.offset));
-        };
-        let name = name.to_owned();
+        let off = self.peek().map(|t| t.offset).unwrap_or(usize::MAX);
+        let pattern = self.parse_pattern()?;
         
         let stmt = if self.consume(&TokenKind::Eq) {
-            VarDefStmt::new(name, Some(Box::new(self.parse_expr()?)))
+            VarDefStmt::new(pattern, Some(Box::new(self.parse_expr()?)))
+        } else if let Pattern::Tuple(_) = pattern {
+            return Err(Error::new("destructuring declaration must have an initializer".to_owned(), off)
+                .with_help("add `= <tuple>` after the pattern"));
         } else {
-            VarDefStmt::new(name, None)
+            VarDefStmt::new(pattern, None)
         };
         self.consume_or_err(&TokenKind::Semi)?;
         Ok(stmt)
     }
+
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        let tok = self.next_or_err()?;
+        match &tok.kind {
+            TokenKind::Identifier(name) => Ok(Pattern::Var(name.to_owned())),
+            TokenKind::LParen => {
+                let off = tok.offset;
+                let mut patterns = Vec::new();
+                loop {
+                    patterns.push(self.parse_pattern()?);
+                    if !self.consume(&TokenKind::Comma) {
+                        break;
+                    }
+                }
+                self.consume_or_err(&TokenKind::RParen)?;
+                check_tuple_len(patterns.len(), off)?;
+                Ok(Pattern::Tuple(patterns))
+            }
+            _ => Err(Error::new("expected variable name or `(` in pattern".to_owned(), tok.offset))
+        }
+    }
     
     fn parse_if(&mut self) -> Result<IfStmt> {
         self.consume_or_err(&TokenKind::LParen)?;
@@ -147,7 +296,8 @@ impl Parser {
         let then = self.parse_block_with_lbrace()?;
         let stmt = if self.consume(&TokenKind::Else) {
             let els = if self.consume(&TokenKind::If) {
-                vec![Stmt::If(self.parse_if()?)]
+                let off = self.prev_offset();
+                vec![Stmt::new(StmtKind::If(self.parse_if()?), off)]
             } else {
                 self.parse_block_with_lbrace()?
             };
@@ -166,6 +316,16 @@ impl Parser {
         Ok(WhileStmt::new(cond, body))
     }
     
+    fn parse_for_in(&mut self) -> Result<ForInStmt> {
+        self.consume_or_err(&TokenKind::LParen)?;
+        let pattern = self.parse_pattern()?;
+        self.consume_or_err(&TokenKind::In)?;
+        let iterable = Box::new(self.parse_expr()?);
+        self.consume_or_err(&TokenKind::RParen)?;
+        let body = self.parse_block_with_lbrace()?;
+        Ok(ForInStmt::new(pattern, iterable, body))
+    }
+    
     fn parse_return(&mut self) -> Result<Option<Box<Expr>>> {
         if self.consume(&TokenKind::Semi) {
             return Ok(None);
@@ -176,12 +336,30 @@ impl Parser {
         Ok(Some(Box::new(res)))
     }
     
-    fn parse_assign_or_expr_stmt(&mut self) -> Result<Stmt> {
+    // `assert cond, "message";`, the message is optional
+    fn parse_assert(&mut self) -> Result<AssertStmt> {
+        let start = self.peek_or_err()?.offset;
+        let cond = self.parse_expr()?;
+        let end = self.peek_or_err()?.offset;
+        let text = String::from_utf8_lossy(&self.source[start..end]).trim_end().to_owned();
+        let source = self.source;
+        let (line, column) = self.line_column_info.get_or_insert_with(|| LineColumnInfo::new(source)).line_column_info(start);
+
+        let msg = if self.consume(&TokenKind::Comma) {
+            Some(Box::new(self.parse_expr()?))
+        } else {
+            None
+        };
+        self.consume_or_err(&TokenKind::Semi)?;
+        Ok(AssertStmt::new(Box::new(cond), msg, text, line, column))
+    }
+
+    fn parse_assign_or_expr_stmt(&mut self) -> Result<StmtKind> {
         let left = self.parse_expr()?;
         let tok = self.next_or_err()?;
         
         let op = match &tok.kind {
-            TokenKind::Semi => return Ok(Stmt::Expr(Box::new(left))),
+            TokenKind::Semi => return Ok(StmtKind::Expr(Box::new(left))),
             TokenKind::Eq => AssignOp::Assign,
             TokenKind::PlusEq => AssignOp::AddAssign,
             TokenKind::SubEq => AssignOp::SubAssign,
@@ -190,15 +368,23 @@ impl Parser {
             _ => return Err(Error::new(format!("unexpected token: {tok:?}"), tok.offset))
         };
         
-        if !matches!(left, Expr::Getter(_) | Expr::GetVar(_)) {
+        if !matches!(left.kind, ExprKind::Getter(_) | ExprKind::GetVar(_) | ExprKind::Tuple(_)) {
             return Err(Error::new("invalid assign target".to_owned(), tok.offset));
         }
+        if matches!(left.kind, ExprKind::Tuple(_)) && op != AssignOp::Assign {
+            return Err(Error::new("destructuring assignment only support `=`".to_owned(), tok.offset));
+        }
+        let off = tok.offset;
         
         let value = Box::new(self.parse_expr()?);
-        let stmt = match left {
-            Expr::GetVar(var) => Stmt::SetVar(SetVarStmt::new(var, op, value)),
-            Expr::Getter(getter) => Stmt::Setter(SetterStmt::new(getter.owner, getter.member, op, value)),
-            _ => unreachable!()
+        let stmt = match left.kind {
+            ExprKind::Getter(getter) => StmtKind::Setter(SetterStmt::new(getter.owner, getter.member, op, value)),
+            _ => {
+                let Some(to) = expr_to_pattern(left) else {
+                    return Err(Error::new("invalid destructuring assign target, only variables are allowed".to_owned(), off));
+                };
+                StmtKind::SetVar(SetVarStmt::new(to, op, value))
+            }
         };
         self.consume_or_err(&TokenKind::Semi)?;
         Ok(stmt)
@@ -211,8 +397,9 @@ impl Parser {
     fn logic_or(&mut self) -> Result<Expr> {
         let mut left = self.logic_and()?;
         while self.consume(&TokenKind::BarBar) {
+            let off = self.prev_offset();
             let right = self.logic_and()?;
-            left = Expr::Logic(LogicExpr::new(Box::new(left), LogicOp::Or, Box::new(right)));
+            left = Expr::new(ExprKind::Logic(LogicExpr::new(Box::new(left), LogicOp::Or, Box::new(right))), off);
         }
         Ok(left)
     }
@@ -220,8 +407,9 @@ impl Parser {
     fn logic_and(&mut self) -> Result<Expr> {
         let mut left = self.equal()?;
         while self.consume(&TokenKind::AmpAmp) {
+            let off = self.prev_offset();
             let right = self.equal()?;
-            left = Expr::Logic(LogicExpr::new(Box::new(left), LogicOp::And, Box::new(right)));
+            left = Expr::new(ExprKind::Logic(LogicExpr::new(Box::new(left), LogicOp::And, Box::new(right))), off);
         }
         Ok(left)
     }
@@ -229,20 +417,30 @@ impl Parser {
     fn equal(&mut self) -> Result<Expr> {
         let left = self.compare()?;
         if self.consume(&TokenKind::EqEq) {
-            Ok(Expr::Binary(BinaryExpr::new(Box::new(left), BinaryOp::EqEq, Box::new(self.compare()?))))
+            let off = self.prev_offset();
+            Ok(Expr::new(ExprKind::Binary(BinaryExpr::new(Box::new(left), BinaryOp::EqEq, Box::new(self.compare()?))), off))
         } else if self.consume(&TokenKind::BangEq) {
-            Ok(Expr::Binary(BinaryExpr::new(Box::new(left), BinaryOp::BangEq, Box::new(self.compare()?))))
+            let off = self.prev_offset();
+            Ok(Expr::new(ExprKind::Binary(BinaryExpr::new(Box::new(left), BinaryOp::BangEq, Box::new(self.compare()?))), off))
         } else {
             Ok(left)
         }
     }
     
     fn compare(&mut self) -> Result<Expr> {
-        let expr = self.add_sub()?;
+        let expr = self.range()?;
         let Some(tok) = self.next() else {
             return Ok(expr);
         };
+        let off = tok.offset;
         let op = match &tok.kind {
+            TokenKind::Is => {
+                let tok = self.next_or_err()?;
+                let Token {kind: TokenKind::Identifier(name), offset: _} = tok else {
+                    return Err(Error::new("expected class or trait name after keyword `is`".to_owned(), tok.offset));
+                };
+                return Ok(Expr::new(ExprKind::Is(IsExpr::new(Box::new(expr), name.to_owned())), off));
+            }
             TokenKind::Gt => BinaryOp::Gt,
             TokenKind::Lt => BinaryOp::Lt,
             TokenKind::GtEq => BinaryOp::GtEq,
@@ -252,16 +450,31 @@ impl Parser {
                 return Ok(expr);
             }
         };
-        Ok(Expr::Binary(BinaryExpr::new(Box::new(expr), op, Box::new(self.add_sub()?))))
+        Ok(Expr::new(ExprKind::Binary(BinaryExpr::new(Box::new(expr), op, Box::new(self.range()?))), off))
+    }
+
+    fn range(&mut self) -> Result<Expr> {
+        let start = self.add_sub()?;
+        if self.consume(&TokenKind::DotDot) {
+            let off = self.prev_offset();
+            Ok(Expr::new(ExprKind::Range(RangeExpr::new(Box::new(start), Box::new(self.add_sub()?), false)), off))
+        } else if self.consume(&TokenKind::DotDotEq) {
+            let off = self.prev_offset();
+            Ok(Expr::new(ExprKind::Range(RangeExpr::new(Box::new(start), Box::new(self.add_sub()?), true)), off))
+        } else {
+            Ok(start)
+        }
     }
     
     fn add_sub(&mut self) -> Result<Expr> {
         let mut left = self.multiply_divide()?;
         loop {
             if self.consume(&TokenKind::Plus) {
-                left = Expr::Binary(BinaryExpr::new(Box::new(left), BinaryOp::Add, Box::new(self.multiply_divide()?)));
+                let off = self.prev_offset();
+                left = Expr::new(ExprKind::Binary(BinaryExpr::new(Box::new(left), BinaryOp::Add, Box::new(self.multiply_divide()?))), off);
             } else if self.consume(&TokenKind::Sub) {
-                left = Expr::Binary(BinaryExpr::new(Box::new(left), BinaryOp::Sub, Box::new(self.multiply_divide()?)));
+                let off = self.prev_offset();
+                left = Expr::new(ExprKind::Binary(BinaryExpr::new(Box::new(left), BinaryOp::Sub, Box::new(self.multiply_divide()?))), off);
             } else {
                 break;
             }
@@ -273,9 +486,11 @@ impl Parser {
         let mut left = self.unary()?;
         loop {
             if self.consume(&TokenKind::Star) {
-                left = Expr::Binary(BinaryExpr::new(Box::new(left), BinaryOp::Multiply, Box::new(self.unary()?)));
+                let off = self.prev_offset();
+                left = Expr::new(ExprKind::Binary(BinaryExpr::new(Box::new(left), BinaryOp::Multiply, Box::new(self.unary()?))), off);
             } else if self.consume(&TokenKind::Slash) {
-                left = Expr::Binary(BinaryExpr::new(Box::new(left), BinaryOp::Divide, Box::new(self.unary()?)));
+                let off = self.prev_offset();
+                left = Expr::new(ExprKind::Binary(BinaryExpr::new(Box::new(left), BinaryOp::Divide, Box::new(self.unary()?))), off);
             } else {
                 break;
             }
@@ -285,9 +500,11 @@ impl Parser {
     
     fn unary(&mut self) -> Result<Expr> {
         if self.consume(&TokenKind::Bang) {
-            Ok(Expr::Unary(UnaryExpr::new(UnaryOp::Bang, Box::new(self.unary()?))))
+            let off = self.prev_offset();
+            Ok(Expr::new(ExprKind::Unary(UnaryExpr::new(UnaryOp::Bang, Box::new(self.unary()?))), off))
         } else if self.consume(&TokenKind::Sub) {
-            Ok(Expr::Unary(UnaryExpr::new(UnaryOp::Neg, Box::new(self.unary()?))))
+            let off = self.prev_offset();
+            Ok(Expr::new(ExprKind::Unary(UnaryExpr::new(UnaryOp::Neg, Box::new(self.unary()?))), off))
         } else {
             self.call()
         }
@@ -297,6 +514,7 @@ impl Parser {
         let mut p = self.primary()?;
         loop {
             if self.consume(&TokenKind::LParen) {
+                let off = self.prev_offset();
                 let mut args = Vec::new();
                 loop {
                     if self.consume(&TokenKind::RParen) {
@@ -308,13 +526,34 @@ impl Parser {
                         break;
                     }
                 }
-                p = Expr::Call(CallExpr::new(Box::new(p), args));
+                p = Expr::new(ExprKind::Call(CallExpr::new(Box::new(p), args)), off);
             } else if self.consume(&TokenKind::Dot) {
+                let off = self.prev_offset();
                 let tok = self.next_or_err()?;
                 let Token {kind: TokenKind::Identifier(name), offset: _} = tok else {
                     return Err(Error::new("expected identifier".to_owned(), tok.offset));
                 };
-                p = Expr::Getter(GetterExpr::new(Box::new(p), name.to_owned()));
+                p = Expr::new(ExprKind::Getter(GetterExpr::new(Box::new(p), name.to_owned())), off);
+            } else if self.consume(&TokenKind::LBracket) {
+                let off = self.prev_offset();
+                // `s[i]`, `s[start:end]`, both `start` and `end` of slice are optional
+                let start = if self.peek().is_some_and(|t| t.kind == TokenKind::Colon) {
+                    None
+                } else {
+                    Some(Box::new(self.parse_expr()?))
+                };
+                if self.consume(&TokenKind::Colon) {
+                    let end = if self.peek().is_some_and(|t| t.kind == TokenKind::RBracket) {
+                        None
+                    } else {
+                        Some(Box::new(self.parse_expr()?))
+                    };
+                    self.consume_or_err(&TokenKind::RBracket)?;
+                    p = Expr::new(ExprKind::Slice(SliceExpr::new(Box::new(p), start, end)), off);
+                } else {
+                    self.consume_or_err(&TokenKind::RBracket)?;
+                    p = Expr::new(ExprKind::Index(IndexExpr::new(Box::new(p), start.unwrap())), off);
+                }
             } else {
                 break;
             }
@@ -324,24 +563,38 @@ impl Parser {
     
     fn primary(&mut self) -> Result<Expr> {
         let tok = self.next_or_err()?;
+        let off = tok.offset;
         
-        let expr = match &tok.kind {
-            TokenKind::Long(v) => Expr::Long(*v),
-            TokenKind::Double(v) => Expr::Double(*v),
-            TokenKind::String(v) => Expr::String(v.to_owned()),
-            TokenKind::True => Expr::True,
-            TokenKind::False => Expr::Flase,
-            TokenKind::This => Expr::This,
-            TokenKind::Null => Expr::Null,
-            TokenKind::Identifier(var) => Expr::GetVar(var.to_owned()),
+        let kind = match &tok.kind {
+            TokenKind::Long(v) => ExprKind::Long(*v),
+            TokenKind::Double(v) => ExprKind::Double(*v),
+            TokenKind::String(v) => ExprKind::String(v.to_owned()),
+            TokenKind::True => ExprKind::True,
+            TokenKind::False => ExprKind::False,
+            TokenKind::This => ExprKind::This,
+            TokenKind::Null => ExprKind::Null,
+            TokenKind::Identifier(var) => ExprKind::GetVar(var.to_owned()),
             TokenKind::LParen => {
                 let e = self.parse_expr()?;
-                self.consume_or_err(&TokenKind::RParen)?;
-                e
+                if self.consume(&TokenKind::Comma) {
+                    let mut elements = vec![e];
+                    loop {
+                        elements.push(self.parse_expr()?);
+                        if !self.consume(&TokenKind::Comma) {
+                            break;
+                        }
+                    }
+                    self.consume_or_err(&TokenKind::RParen)?;
+                    check_tuple_len(elements.len(), off)?;
+                    ExprKind::Tuple(elements)
+                } else {
+                    self.consume_or_err(&TokenKind::RParen)?;
+                    return Ok(e);
+                }
             }
             _ => return Err(Error::new(format!("unexpected token: {tok:?} in primary stmt"), tok.offset))
         };
-        Ok(expr)
+        Ok(Expr::new(kind, off))
     }
     
     fn parse_block_with_lbrace(&mut self) -> Result<Vec<Stmt>> {
@@ -355,7 +608,17 @@ impl Parser {
             if self.consume(&TokenKind::RBrace) {
                 break;
             }
-            stmts.push(self.parse_stmt()?);
+            match self.parse_stmt() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize();
+                    // the block is not closed, leave `func` & `class` to the global scope
+                    if matches!(self.peek().map(|t| &t.kind), None | Some(TokenKind::Func | TokenKind::Class)) {
+                        break;
+                    }
+                }
+            }
         }
         Ok(stmts)
     }
@@ -376,7 +639,12 @@ impl Parser {
                 self.advance();
                 Ok(())
             } else {
-                Err(Error::new(format!("expected token: {tok:?}, got: {:?}", t.kind), t.offset))
+                let e = Error::new(format!("expected token: {tok:?}, got: {:?}", t.kind), t.offset);
+                if *tok == TokenKind::Semi {
+                    Err(e.with_help("the previous statement may be missing a `;`"))
+                } else {
+                    Err(e)
+                }
             }
         } else {
             Err(Error::new(format!("failed to consume token: {tok:?}, end of file"), usize::MAX))
@@ -394,6 +662,13 @@ impl Parser {
         })
     }
     
+    fn peek_or_err(&self) -> Result<&Token> {
+        match self.tokens.get(self.offset) {
+            None => Err(Error::new("unexpected end of file".to_owned(), usize::MAX)),
+            Some(t) => Ok(t)
+        }
+    }
+
     fn next_or_err(&mut self) -> Result<&Token> {
         match self.tokens.get(self.offset) {
             None => Err(Error::new("unexpected end of file".to_owned(), usize::MAX)),
@@ -404,7 +679,36 @@ impl Parser {
         }
     }
 
+    // offset of the last consumed token
+    fn prev_offset(&self) -> usize {
+        self.tokens[self.offset - 1].offset
+    }
+
     fn advance(&mut self) {
         self.offset += 1;
     }
+}
+
+fn check_tuple_len(len: usize, off: usize) -> Result<()> {
+    if len < 2 {
+        Err(Error::new("tuple should have at least 2 elements".to_owned(), off))
+    } else if len > u8::MAX as usize {
+        Err(Error::new(format!("tuple has too many elements: {len}"), off))
+    } else {
+        Ok(())
+    }
+}
+
+fn expr_to_pattern(expr: Expr) -> Option<Pattern> {
+    match expr.kind {
+        ExprKind::GetVar(var) => Some(Pattern::Var(var)),
+        ExprKind::Tuple(elements) => {
+            let mut patterns = Vec::with_capacity(elements.len());
+            for e in elements {
+                patterns.push(expr_to_pattern(e)?);
+            }
+            Some(Pattern::Tuple(patterns))
+        }
+        _ => None
+    }
 }
\ No newline at end of file
@@ -0,0 +1,491 @@
+use ahash::{HashMap, HashMapExt, HashSet, HashSetExt};
+use crate::ast::{AssertStmt, AssignOp, ClassDecl, Expr, ExprKind, FuncDecl, Pattern, Program, Stmt, StmtKind, TraitDecl};
+use crate::lint::{Lint, Warning};
+use common::constant::{ENTRY_NAME, FFI_FUNCTIONS};
+
+// name resolution over the whole program, in the same scopes as code_gen:
+// unknown identifiers, unused locals & params, shadowed and redefined variables are reported as warnings.
+// the resolved names are kept as references for tools, such as go to definition of `charon-lsp`
+
+// a variable or declaration name in the source, and what it resolves to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub name: String,
+    pub offset: usize,
+    pub definition: Definition
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Definition {
+    // offset of the name where it's defined
+    Local(usize),
+    Param(usize),
+    Global(usize),
+    // function, class or ffi function, found by the name
+    Decl
+}
+
+pub fn resolve(program: &Program, source: &[u8]) -> Vec<Warning> {
+    run(program, source).warnings
+}
+
+// the definitions and uses of the variables, and the uses of the declarations, in source order.
+// unknown names are skipped
+pub fn references(program: &Program, source: &[u8]) -> Vec<Reference> {
+    let mut references = run(program, source).references;
+    references.sort_by_key(|r| r.offset);
+    references
+}
+
+fn run<'a>(program: &'a Program, source: &'a [u8]) -> Resolver<'a> {
+    let mut resolver = Resolver::new(program, source);
+    for func in &program.funcs {
+        if func.name == ENTRY_NAME {
+            resolver.entry(func);
+        } else {
+            resolver.callable(func);
+        }
+    }
+    for ClassDecl { methods, getters, setters, .. } in &program.classes {
+        for method in methods.iter().chain(getters).chain(setters) {
+            resolver.callable(method);
+        }
+    }
+    // default methods are resolved once, not in each class that copies them
+    for TraitDecl { provided, .. } in &program.traits {
+        for method in provided {
+            resolver.callable(method);
+        }
+    }
+    resolver
+}
+
+struct Var<'a> {
+    name: &'a str,
+    offset: usize,
+    param: bool,
+    used: bool
+}
+
+struct Resolver<'a> {
+    source: &'a [u8],
+    // functions, classes & ffi functions
+    decls: HashSet<&'a str>,
+    // all the variables defined in the global scope with the offset of the first definition,
+    // and the ones defined so far while resolving the entry
+    globals: HashMap<&'a str, usize>,
+    defined_globals: HashMap<&'a str, usize>,
+    // scopes of the current function, empty in the global scope
+    scopes: Vec<Vec<Var<'a>>>,
+    in_entry: bool,
+    warnings: Vec<Warning>,
+    references: Vec<Reference>
+}
+
+impl<'a> Resolver<'a> {
+    fn new(program: &'a Program, source: &'a [u8]) -> Self {
+        let mut decls = HashSet::new();
+        decls.extend(program.funcs.iter().filter(|func| func.name != ENTRY_NAME).map(|func| func.name.as_str()));
+        decls.extend(program.classes.iter().map(|class| class.name.as_str()));
+        decls.extend(FFI_FUNCTIONS.iter().map(|(name, _)| *name));
+
+        let mut globals = HashMap::new();
+        if let Some(entry) = program.funcs.iter().find(|func| func.name == ENTRY_NAME) {
+            for stmt in &entry.body {
+                if let StmtKind::VarDef(vardef) = &stmt.kind {
+                    pattern_names(&vardef.pattern, &mut |name| {
+                        globals.entry(name).or_insert_with(|| name_offset(source, stmt.offset, name));
+                    });
+                }
+            }
+        }
+
+        Self {
+            source,
+            decls,
+            globals,
+            defined_globals: HashMap::new(),
+            scopes: Vec::new(),
+            in_entry: false,
+            warnings: Vec::new(),
+            references: Vec::new()
+        }
+    }
+
+    fn entry(&mut self, func: &'a FuncDecl) {
+        self.in_entry = true;
+        self.stmts(&func.body);
+        self.in_entry = false;
+    }
+
+    fn callable(&mut self, func: &'a FuncDecl) {
+        self.scopes.push(Vec::new());
+        let mut from = func.offset + func.name.len();
+        for param in &func.params {
+            let offset = self.name_offset(from, param);
+            from = offset + param.len();
+            self.define(param, offset, true);
+        }
+        for stmt in &func.body {
+            self.stmt(stmt);
+        }
+        self.pop_scope();
+    }
+
+    fn block(&mut self, stmts: &'a [Stmt]) {
+        self.scopes.push(Vec::new());
+        self.stmts(stmts);
+        self.pop_scope();
+    }
+
+    fn stmts(&mut self, stmts: &'a [Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::VarDef(vardef) => {
+                // the initializer sees the variables defined before
+                if let Some(init) = &vardef.init {
+                    self.expr(init);
+                }
+                self.define_pattern(&vardef.pattern, stmt.offset);
+            }
+            StmtKind::Expr(e) | StmtKind::Defer(e) => self.expr(e),
+            StmtKind::SetVar(setvar) => {
+                self.expr(&setvar.value);
+                // compound assignment reads the variable
+                let read = setvar.op != AssignOp::Assign;
+                pattern_names(&setvar.to, &mut |name| {
+                    let offset = self.name_offset(stmt.offset, name);
+                    self.resolve(name, offset, read);
+                });
+            }
+            StmtKind::Setter(setter) => {
+                self.expr(&setter.owner);
+                self.expr(&setter.value);
+            }
+            StmtKind::If(ifstmt) => {
+                self.expr(&ifstmt.cond);
+                self.block(&ifstmt.then);
+                self.block(&ifstmt.els);
+            }
+            StmtKind::While(while_stmt) => {
+                self.expr(&while_stmt.cond);
+                self.block(&while_stmt.body);
+            }
+            StmtKind::ForIn(for_in) => {
+                self.expr(&for_in.iterable);
+                self.scopes.push(Vec::new());
+                self.define_pattern(&for_in.pattern, stmt.offset);
+                self.stmts(&for_in.body);
+                self.pop_scope();
+            }
+            StmtKind::Return(ret) => if let Some(e) = ret {
+                self.expr(e);
+            }
+            StmtKind::Assert(AssertStmt { cond, msg, .. }) => {
+                self.expr(cond);
+                if let Some(msg) = msg {
+                    self.expr(msg);
+                }
+            }
+            StmtKind::Block(stmts) => self.block(stmts),
+            StmtKind::Break | StmtKind::Continue => ()
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::GetVar(name) => self.resolve(name, expr.offset, true),
+            ExprKind::Binary(binary) => {
+                self.expr(&binary.left);
+                self.expr(&binary.right);
+            }
+            ExprKind::Logic(logic) => {
+                self.expr(&logic.left);
+                self.expr(&logic.right);
+            }
+            ExprKind::Unary(unary) => self.expr(&unary.expr),
+            ExprKind::Call(call) => {
+                self.expr(&call.owner);
+                for arg in &call.args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Getter(getter) => self.expr(&getter.owner),
+            ExprKind::Is(is) => self.expr(&is.expr),
+            ExprKind::Tuple(elements) => for e in elements {
+                self.expr(e);
+            }
+            ExprKind::Range(range) => {
+                self.expr(&range.start);
+                self.expr(&range.end);
+            }
+            ExprKind::Index(index) => {
+                self.expr(&index.owner);
+                self.expr(&index.index);
+            }
+            ExprKind::Slice(slice) => {
+                self.expr(&slice.owner);
+                if let Some(start) = &slice.start {
+                    self.expr(start);
+                }
+                if let Some(end) = &slice.end {
+                    self.expr(end);
+                }
+            }
+            ExprKind::True | ExprKind::False | ExprKind::Null | ExprKind::This
+            | ExprKind::Long(_) | ExprKind::Double(_) | ExprKind::String(_) => ()
+        }
+    }
+
+    fn define_pattern(&mut self, pattern: &'a Pattern, from: usize) {
+        pattern_names(pattern, &mut |name| {
+            let offset = self.name_offset(from, name);
+            self.define(name, offset, false);
+        });
+    }
+
+    fn define(&mut self, name: &'a str, offset: usize, param: bool) {
+        let definition = if self.scopes.is_empty() {
+            Definition::Global(offset)
+        } else if param {
+            Definition::Param(offset)
+        } else {
+            Definition::Local(offset)
+        };
+        self.references.push(Reference { name: name.to_owned(), offset, definition });
+
+        let Some((scope, outer)) = self.scopes.split_last_mut() else {
+            // global scope of the entry
+            if self.defined_globals.insert(name, offset).is_some() {
+                self.warnings.push(Warning::new(Lint::RedefinedVariable, format!("global variable `{name}` is redefined"), offset)
+                    .with_help("assign to it without `var`, or use another name"));
+            }
+            return;
+        };
+
+        if let Some(prev) = scope.iter_mut().rev().find(|v| v.name == name) {
+            let msg = if prev.param {
+                format!("parameter `{name}` is redefined")
+            } else {
+                format!("variable `{name}` is redefined in the same scope")
+            };
+            self.warnings.push(Warning::new(Lint::RedefinedVariable, msg, offset)
+                .with_help("assign to it without `var`, or use another name"));
+            // the previous one is not reported as unused again
+            prev.used = true;
+        } else if outer.iter().flatten().any(|v| v.name == name) {
+            self.warnings.push(Warning::new(Lint::ShadowedVariable, format!("variable `{name}` shadows a variable of the outer scope"), offset));
+        }
+        scope.push(Var { name, offset, param, used: false });
+    }
+
+    fn resolve(&mut self, name: &str, offset: usize, read: bool) {
+        if let Some(var) = self.scopes.iter_mut().rev().flat_map(|scope| scope.iter_mut().rev()).find(|v| v.name == name) {
+            var.used |= read;
+            let definition = if var.param { Definition::Param(var.offset) } else { Definition::Local(var.offset) };
+            self.references.push(Reference { name: name.to_owned(), offset, definition });
+            return;
+        }
+        if self.decls.contains(name) {
+            self.references.push(Reference { name: name.to_owned(), offset, definition: Definition::Decl });
+            return;
+        }
+        if let Some(first) = self.globals.get(name).copied() {
+            // the global scope runs in order, functions are called after the globals they use are defined
+            let defined = self.defined_globals.get(name).copied();
+            if self.in_entry && defined.is_none() {
+                self.warnings.push(Warning::new(Lint::UnknownIdentifier, format!("global variable `{name}` is used before its definition"), offset));
+            }
+            // in the entry, the latest definition before the use
+            let definition = Definition::Global(if self.in_entry { defined.unwrap_or(first) } else { first });
+            self.references.push(Reference { name: name.to_owned(), offset, definition });
+            return;
+        }
+
+        let mut warning = Warning::new(Lint::UnknownIdentifier, format!("cannot find `{name}` in this scope"), offset);
+        if let Some(similar) = self.similar_name(name) {
+            warning = warning.with_help(&format!("a variable or function with a similar name exists: `{similar}`"));
+        }
+        self.warnings.push(warning);
+    }
+
+    fn similar_name(&self, name: &str) -> Option<&str> {
+        let max_distance = (name.chars().count() / 3).max(1);
+        self.scopes.iter().flatten().map(|v| v.name)
+            .chain(self.decls.iter().copied())
+            .chain(self.globals.keys().copied())
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min()
+            .map(|(_, candidate)| candidate)
+    }
+
+    fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for var in scope {
+            if var.used || var.name.starts_with('_') {
+                continue;
+            }
+            let kind = if var.param { "parameter" } else { "variable" };
+            self.warnings.push(Warning::new(Lint::UnusedVariable, format!("unused {kind}: `{}`", var.name), var.offset)
+                .with_help(&format!("if this is intentional, prefix it with an underscore: `_{}`", var.name)));
+        }
+    }
+
+    fn name_offset(&self, from: usize, name: &str) -> usize {
+        name_offset(self.source, from, name)
+    }
+}
+
+// patterns and params have no offset, find the name in the source after `from`
+fn name_offset(source: &[u8], from: usize, name: &str) -> usize {
+    let is_ident = |c: u8| c == b'_' || c.is_ascii_alphanumeric() || c >= 0x80;
+    let name = name.as_bytes();
+    let mut i = from;
+    while i + name.len() <= source.len() {
+        if &source[i..i + name.len()] == name
+            && (i == 0 || !is_ident(source[i - 1]))
+            && source.get(i + name.len()).map_or(true, |c| !is_ident(*c)) {
+            return i;
+        }
+        i += 1;
+    }
+    from
+}
+
+fn pattern_names<'a>(pattern: &'a Pattern, f: &mut impl FnMut(&'a str)) {
+    match pattern {
+        Pattern::Var(name) => f(name),
+        Pattern::Tuple(patterns) => for pattern in patterns {
+            pattern_names(pattern, f);
+        }
+    }
+}
+
+// levenshtein distance in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::lint::{Lint, Warning};
+    use crate::parser::Parser;
+    use crate::resolver::{name_offset, resolve};
+
+    fn resolve_source(s: &str) -> Vec<Warning> {
+        let tokens = Lexer::new(s.as_bytes()).lex().unwrap();
+        let (program, errors) = Parser::new(tokens, s.as_bytes()).parse();
+        assert!(errors.is_empty());
+        resolve(&program, s.as_bytes())
+    }
+
+    fn lints(warnings: &[Warning]) -> Vec<(Lint, &str)> {
+        warnings.iter().map(|w| (w.lint, w.msg.as_str())).collect()
+    }
+
+    #[test]
+    fn test_unknown_name() {
+        let source = "func add(a, b) { return a + b; }\n__println(ad(1, 2));\n";
+        let warnings = resolve_source(source);
+
+        assert_eq!(lints(&warnings), vec![(Lint::UnknownIdentifier, "cannot find `ad` in this scope")]);
+        assert_eq!(warnings[0].offset, source.find("ad(").unwrap());
+        assert_eq!(warnings[0].help.as_deref(), Some("a variable or function with a similar name exists: `add`"));
+
+        // nothing is suggested for a name too far from the others
+        let warnings = resolve_source("var value = 1;\n__println(xyz + value);\n");
+        assert_eq!(lints(&warnings), vec![(Lint::UnknownIdentifier, "cannot find `xyz` in this scope")]);
+        assert_eq!(warnings[0].help, None);
+    }
+
+    #[test]
+    fn test_used_before_definition() {
+        let source = "__println(x);\nvar x = 1;\n__println(x);\n";
+        let warnings = resolve_source(source);
+
+        assert_eq!(lints(&warnings), vec![(Lint::UnknownIdentifier, "global variable `x` is used before its definition")]);
+        assert_eq!(warnings[0].offset, 10);
+
+        // functions are called after the whole entry is run, so they may use later globals
+        assert!(resolve_source("func f() { return x; }\nvar x = 1;\n__println(f());\n").is_empty());
+    }
+
+    #[test]
+    fn test_shadowed_and_redefined() {
+        let source = r#"func f(a) {
+    var b = a;
+    if (b) {
+        var b = 2;
+        __println(b);
+    }
+    var b = 3;
+    return b;
+}
+__println(f(1));
+"#;
+        let warnings = resolve_source(source);
+        assert_eq!(lints(&warnings), vec![
+            (Lint::ShadowedVariable, "variable `b` shadows a variable of the outer scope"),
+            (Lint::RedefinedVariable, "variable `b` is redefined in the same scope")
+        ]);
+        assert_eq!(warnings[0].offset, source.find("b = 2").unwrap());
+        assert_eq!(warnings[1].offset, source.find("b = 3").unwrap());
+
+        let warnings = resolve_source("func f(a) { var a = 1; return a; }\n__println(f(1));\n");
+        assert_eq!(lints(&warnings), vec![(Lint::RedefinedVariable, "parameter `a` is redefined")]);
+
+        let warnings = resolve_source("var g = 1;\nvar g = 2;\n__println(g);\n");
+        assert_eq!(lints(&warnings), vec![(Lint::RedefinedVariable, "global variable `g` is redefined")]);
+        assert_eq!(warnings[0].offset, 15);
+    }
+
+    #[test]
+    fn test_unused() {
+        let source = "func f(a, _b) {\n    var c = 1;\n    var _d = 2;\n    return 0;\n}\n__println(f(1, 2));\n";
+        let warnings = resolve_source(source);
+
+        assert_eq!(lints(&warnings), vec![
+            (Lint::UnusedVariable, "unused parameter: `a`"),
+            (Lint::UnusedVariable, "unused variable: `c`")
+        ]);
+        assert_eq!(warnings[0].offset, 7);
+        assert_eq!(warnings[1].offset, source.find("c = 1").unwrap());
+        assert_eq!(warnings[1].help.as_deref(), Some("if this is intentional, prefix it with an underscore: `_c`"));
+    }
+
+    #[test]
+    fn test_name_offset() {
+        let source = b"var ab = a; var (_a, a2) = t;";
+
+        // whole words only
+        assert_eq!(name_offset(source, 0, "ab"), 4);
+        assert_eq!(name_offset(source, 0, "a"), 9);
+        assert_eq!(name_offset(source, 0, "a2"), 21);
+        // searched after `from`
+        assert_eq!(name_offset(source, 12, "_a"), 17);
+        // falls back to `from` if not found, or only a part of a longer name
+        assert_eq!(name_offset(source, 5, "ab"), 5);
+        assert_eq!(name_offset(source, 3, "b"), 3);
+        assert_eq!(name_offset(source, 10, "a"), 10);
+        assert_eq!(name_offset(b"", 0, "a"), 0);
+    }
+}
@@ -10,6 +10,9 @@ pub enum TokenKind {
     Semi,// ;
     Comma,// ,
     Dot,// .
+    DotDot,// ..
+    DotDotEq,// ..=
+    Colon,// :
     Eq,// =
     Gt,// >
     Lt,// <
@@ -36,13 +39,20 @@ pub enum TokenKind {
     If,
     Else,
     While,
+    For,
+    In,
     Break,
     Continue,
     Return,
+    Defer,
+    Assert,
     Func,
     Class,
     This,
     Null,
+    Trait,
+    Impl,
+    Is,
 
     Identifier(String),
 
@@ -65,4 +75,20 @@ impl Token {
             offset
         }
     }
-}
\ No newline at end of file
+}
+
+// `// ...` or `# ...` without the line break, the shebang line is a `#` comment
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub offset: usize
+}
+
+impl Comment {
+    pub fn new(text: String, offset: usize) -> Self {
+        Self {
+            text,
+            offset
+        }
+    }
+}
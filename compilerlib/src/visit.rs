@@ -0,0 +1,328 @@
+use crate::ast::{AssertStmt, ClassDecl, Expr, ExprKind, FuncDecl, Pattern, Program, Stmt, StmtKind, TraitDecl};
+
+// traversal of the ast: functions, then classes, then traits, the nodes inside are visited in source order.
+// each `visit_*` method walks the children by default, override it to handle a node,
+// and call the matching `walk_*` function inside to keep visiting the children
+
+pub trait Visitor<'ast> {
+    fn visit_program(&mut self, program: &'ast Program) {
+        walk_program(self, program);
+    }
+
+    // functions, methods, getters, setters and the default methods of traits,
+    // the top-level code is the function named `ENTRY_NAME`
+    fn visit_func(&mut self, func: &'ast FuncDecl) {
+        walk_func(self, func);
+    }
+
+    fn visit_class(&mut self, class: &'ast ClassDecl) {
+        walk_class(self, class);
+    }
+
+    fn visit_trait(&mut self, t: &'ast TraitDecl) {
+        walk_trait(self, t);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr);
+    }
+
+    // defined or assigned variables of `var`, assignment and `for`
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        walk_pattern(self, pattern);
+    }
+}
+
+pub fn walk_program<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, program: &'ast Program) {
+    for func in &program.funcs {
+        v.visit_func(func);
+    }
+    for class in &program.classes {
+        v.visit_class(class);
+    }
+    for t in &program.traits {
+        v.visit_trait(t);
+    }
+}
+
+pub fn walk_func<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, func: &'ast FuncDecl) {
+    for stmt in &func.body {
+        v.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_class<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, class: &'ast ClassDecl) {
+    for method in class.methods.iter().chain(&class.getters).chain(&class.setters) {
+        v.visit_func(method);
+    }
+}
+
+// required methods have no body, only the provided ones are visited
+pub fn walk_trait<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, t: &'ast TraitDecl) {
+    for method in &t.provided {
+        v.visit_func(method);
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, stmt: &'ast Stmt) {
+    match &stmt.kind {
+        StmtKind::VarDef(vardef) => {
+            v.visit_pattern(&vardef.pattern);
+            if let Some(init) = &vardef.init {
+                v.visit_expr(init);
+            }
+        }
+        StmtKind::Expr(e) | StmtKind::Defer(e) | StmtKind::Return(Some(e)) => v.visit_expr(e),
+        StmtKind::SetVar(setvar) => {
+            v.visit_pattern(&setvar.to);
+            v.visit_expr(&setvar.value);
+        }
+        StmtKind::Setter(setter) => {
+            v.visit_expr(&setter.owner);
+            v.visit_expr(&setter.value);
+        }
+        StmtKind::If(ifstmt) => {
+            v.visit_expr(&ifstmt.cond);
+            for stmt in ifstmt.then.iter().chain(&ifstmt.els) {
+                v.visit_stmt(stmt);
+            }
+        }
+        StmtKind::While(while_stmt) => {
+            v.visit_expr(&while_stmt.cond);
+            for stmt in &while_stmt.body {
+                v.visit_stmt(stmt);
+            }
+        }
+        StmtKind::ForIn(for_in) => {
+            v.visit_pattern(&for_in.pattern);
+            v.visit_expr(&for_in.iterable);
+            for stmt in &for_in.body {
+                v.visit_stmt(stmt);
+            }
+        }
+        StmtKind::Assert(AssertStmt { cond, msg, .. }) => {
+            v.visit_expr(cond);
+            if let Some(msg) = msg {
+                v.visit_expr(msg);
+            }
+        }
+        StmtKind::Block(stmts) => for stmt in stmts {
+            v.visit_stmt(stmt);
+        }
+        StmtKind::Break | StmtKind::Continue | StmtKind::Return(None) => ()
+    }
+}
+
+pub fn walk_expr<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, expr: &'ast Expr) {
+    match &expr.kind {
+        ExprKind::Binary(binary) => {
+            v.visit_expr(&binary.left);
+            v.visit_expr(&binary.right);
+        }
+        ExprKind::Logic(logic) => {
+            v.visit_expr(&logic.left);
+            v.visit_expr(&logic.right);
+        }
+        ExprKind::Unary(unary) => v.visit_expr(&unary.expr),
+        ExprKind::Call(call) => {
+            v.visit_expr(&call.owner);
+            for arg in &call.args {
+                v.visit_expr(arg);
+            }
+        }
+        ExprKind::Getter(getter) => v.visit_expr(&getter.owner),
+        ExprKind::Is(is) => v.visit_expr(&is.expr),
+        ExprKind::Tuple(elements) => for e in elements {
+            v.visit_expr(e);
+        }
+        ExprKind::Range(range) => {
+            v.visit_expr(&range.start);
+            v.visit_expr(&range.end);
+        }
+        ExprKind::Index(index) => {
+            v.visit_expr(&index.owner);
+            v.visit_expr(&index.index);
+        }
+        ExprKind::Slice(slice) => {
+            v.visit_expr(&slice.owner);
+            for bound in [&slice.start, &slice.end].into_iter().flatten() {
+                v.visit_expr(bound);
+            }
+        }
+        ExprKind::True | ExprKind::False | ExprKind::Null | ExprKind::This | ExprKind::Long(_)
+        | ExprKind::Double(_) | ExprKind::String(_) | ExprKind::GetVar(_) => ()
+    }
+}
+
+pub fn walk_pattern<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, pattern: &'ast Pattern) {
+    if let Pattern::Tuple(patterns) = pattern {
+        for pattern in patterns {
+            v.visit_pattern(pattern);
+        }
+    }
+}
+
+// same as `Visitor`, but the nodes can be changed in place
+
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program);
+    }
+
+    fn visit_func_mut(&mut self, func: &mut FuncDecl) {
+        walk_func_mut(self, func);
+    }
+
+    fn visit_class_mut(&mut self, class: &mut ClassDecl) {
+        walk_class_mut(self, class);
+    }
+
+    fn visit_trait_mut(&mut self, t: &mut TraitDecl) {
+        walk_trait_mut(self, t);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        walk_pattern_mut(self, pattern);
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(v: &mut V, program: &mut Program) {
+    for func in &mut program.funcs {
+        v.visit_func_mut(func);
+    }
+    for class in &mut program.classes {
+        v.visit_class_mut(class);
+    }
+    for t in &mut program.traits {
+        v.visit_trait_mut(t);
+    }
+}
+
+pub fn walk_func_mut<V: VisitorMut + ?Sized>(v: &mut V, func: &mut FuncDecl) {
+    for stmt in &mut func.body {
+        v.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_class_mut<V: VisitorMut + ?Sized>(v: &mut V, class: &mut ClassDecl) {
+    let ClassDecl { methods, getters, setters, .. } = class;
+    for method in methods.iter_mut().chain(getters).chain(setters) {
+        v.visit_func_mut(method);
+    }
+}
+
+pub fn walk_trait_mut<V: VisitorMut + ?Sized>(v: &mut V, t: &mut TraitDecl) {
+    for method in &mut t.provided {
+        v.visit_func_mut(method);
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(v: &mut V, stmt: &mut Stmt) {
+    match &mut stmt.kind {
+        StmtKind::VarDef(vardef) => {
+            v.visit_pattern_mut(&mut vardef.pattern);
+            if let Some(init) = &mut vardef.init {
+                v.visit_expr_mut(init);
+            }
+        }
+        StmtKind::Expr(e) | StmtKind::Defer(e) | StmtKind::Return(Some(e)) => v.visit_expr_mut(e),
+        StmtKind::SetVar(setvar) => {
+            v.visit_pattern_mut(&mut setvar.to);
+            v.visit_expr_mut(&mut setvar.value);
+        }
+        StmtKind::Setter(setter) => {
+            v.visit_expr_mut(&mut setter.owner);
+            v.visit_expr_mut(&mut setter.value);
+        }
+        StmtKind::If(ifstmt) => {
+            v.visit_expr_mut(&mut ifstmt.cond);
+            for stmt in ifstmt.then.iter_mut().chain(&mut ifstmt.els) {
+                v.visit_stmt_mut(stmt);
+            }
+        }
+        StmtKind::While(while_stmt) => {
+            v.visit_expr_mut(&mut while_stmt.cond);
+            for stmt in &mut while_stmt.body {
+                v.visit_stmt_mut(stmt);
+            }
+        }
+        StmtKind::ForIn(for_in) => {
+            v.visit_pattern_mut(&mut for_in.pattern);
+            v.visit_expr_mut(&mut for_in.iterable);
+            for stmt in &mut for_in.body {
+                v.visit_stmt_mut(stmt);
+            }
+        }
+        StmtKind::Assert(AssertStmt { cond, msg, .. }) => {
+            v.visit_expr_mut(cond);
+            if let Some(msg) = msg {
+                v.visit_expr_mut(msg);
+            }
+        }
+        StmtKind::Block(stmts) => for stmt in stmts {
+            v.visit_stmt_mut(stmt);
+        }
+        StmtKind::Break | StmtKind::Continue | StmtKind::Return(None) => ()
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(v: &mut V, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::Binary(binary) => {
+            v.visit_expr_mut(&mut binary.left);
+            v.visit_expr_mut(&mut binary.right);
+        }
+        ExprKind::Logic(logic) => {
+            v.visit_expr_mut(&mut logic.left);
+            v.visit_expr_mut(&mut logic.right);
+        }
+        ExprKind::Unary(unary) => v.visit_expr_mut(&mut unary.expr),
+        ExprKind::Call(call) => {
+            v.visit_expr_mut(&mut call.owner);
+            for arg in &mut call.args {
+                v.visit_expr_mut(arg);
+            }
+        }
+        ExprKind::Getter(getter) => v.visit_expr_mut(&mut getter.owner),
+        ExprKind::Is(is) => v.visit_expr_mut(&mut is.expr),
+        ExprKind::Tuple(elements) => for e in elements {
+            v.visit_expr_mut(e);
+        }
+        ExprKind::Range(range) => {
+            v.visit_expr_mut(&mut range.start);
+            v.visit_expr_mut(&mut range.end);
+        }
+        ExprKind::Index(index) => {
+            v.visit_expr_mut(&mut index.owner);
+            v.visit_expr_mut(&mut index.index);
+        }
+        ExprKind::Slice(slice) => {
+            v.visit_expr_mut(&mut slice.owner);
+            for bound in [&mut slice.start, &mut slice.end].into_iter().flatten() {
+                v.visit_expr_mut(bound);
+            }
+        }
+        ExprKind::True | ExprKind::False | ExprKind::Null | ExprKind::This | ExprKind::Long(_)
+        | ExprKind::Double(_) | ExprKind::String(_) | ExprKind::GetVar(_) => ()
+    }
+}
+
+pub fn walk_pattern_mut<V: VisitorMut + ?Sized>(v: &mut V, pattern: &mut Pattern) {
+    if let Pattern::Tuple(patterns) = pattern {
+        for pattern in patterns {
+            v.visit_pattern_mut(pattern);
+        }
+    }
+}
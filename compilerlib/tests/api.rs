@@ -0,0 +1,189 @@
+// the public api used by tools: parsing into the ast, walking it with visitors, and compiling with options
+
+use common::loader::Loader;
+use common::opcode::{instruction_len, OP_GET_MEMBER};
+use compilerlib::ast::{Expr, ExprKind, Program};
+use compilerlib::visit::{walk_expr, walk_expr_mut};
+use compilerlib::{compile, compile_with_warnings, lex, parse, parse_partial, references, Definition, OptLevel, Options, Visitor, VisitorMut};
+
+const SOURCE: &str = r#"
+func add(a, b) {
+    return a + b;
+}
+
+class Counter {
+    func inc() {
+        this.count = add(this.count, 1);
+    }
+}
+
+__println(add(1, 2 * 3));
+"#;
+
+fn parse_source(source: &str) -> Program {
+    let tokens = lex(source.as_bytes()).unwrap();
+    parse(tokens, source.as_bytes()).unwrap()
+}
+
+// names of the called functions
+struct Calls<'a>(Vec<&'a str>);
+
+impl<'a> Visitor<'a> for Calls<'a> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Call(call) = &expr.kind {
+            if let ExprKind::GetVar(name) = &call.owner.kind {
+                self.0.push(name);
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+struct Rename;
+
+impl VisitorMut for Rename {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::GetVar(name) if name == "add" => "sum".clone_into(name),
+            _ => ()
+        }
+        walk_expr_mut(self, expr);
+    }
+}
+
+#[test]
+fn test_visitor() {
+    let program = parse_source(SOURCE);
+    let mut calls = Calls(Vec::new());
+    calls.visit_program(&program);
+    calls.0.sort();
+    assert_eq!(calls.0, ["__println", "add", "add"]);
+}
+
+#[test]
+fn test_visitor_mut() {
+    let mut program = parse_source(SOURCE);
+    Rename.visit_program_mut(&mut program);
+    let mut calls = Calls(Vec::new());
+    calls.visit_program(&program);
+    assert!(calls.0.iter().all(|name| *name != "add"));
+    assert_eq!(calls.0.iter().filter(|name| **name == "sum").count(), 2);
+}
+
+#[test]
+fn test_compile_options() {
+    let plain = compile(SOURCE.as_bytes(), &Options::default()).unwrap();
+    let mut options = Options::default();
+    options.opt_level = OptLevel::Basic;
+    let optimized = compile(SOURCE.as_bytes(), &options).unwrap();
+    let mut options = Options::default();
+    options.debug_info = false;
+    let stripped = compile(SOURCE.as_bytes(), &options).unwrap();
+    assert!(optimized.len() < plain.len());
+    assert!(stripped.len() < plain.len());
+
+    let errors = compile(b"func f() { return 1 }", &Options::default()).unwrap_err();
+    assert_eq!(errors.items.len(), 1);
+}
+
+#[test]
+fn test_call_arity() {
+    let errors = compile(b"func f(a) { return a; }\nf(1, 2);", &Options::default()).unwrap_err();
+    assert_eq!(errors.items[0].msg, "function: f's param count: 1, but got: 2");
+    assert!(compile(b"__println(1, 2);", &Options::default()).is_err());
+
+    // `f` is a global which is replaced, the call may be right
+    let source = b"func f(a) { return a; }\nfunc g(a, b, c) { return a + b + c; }\nf = g;\nf(1, 2, 3);";
+    assert!(compile(source, &Options::default()).is_ok());
+    let source = b"class A {}\nfunc f(a) { return a; }\nfunc main() { A = f; }\nA(1);";
+    assert!(compile(source, &Options::default()).is_ok());
+}
+
+#[test]
+fn test_semantic_errors() {
+    // each function & method is checked after the error of another one, the error of a default method is reported once
+    let source = b"trait T {\n    func t() { continue; }\n}\nclass A impl T {\n    func m() { break; }\n}\nclass B impl T {}\n\
+func f() { break; }\nfunc g() { var x = ; }\nreturn 1;";
+    let errors = compile(source, &Options::default()).unwrap_err();
+    let lines: Vec<(usize, &str)> = errors.items.iter().map(|e| (e.span.as_ref().unwrap().line, e.msg.as_str())).collect();
+    assert_eq!(lines, [
+        (2, "`continue` can only used in loop"),
+        (5, "`break` can only used in loop"),
+        (8, "`break` can only used in loop"),
+        (9, "unexpected token: Token { kind: Semi, offset: 139 } in primary stmt"),
+        (10, "`return` can only used in function or method")
+    ]);
+}
+
+#[test]
+fn test_dead_code_errors() {
+    // dead code is removed after the semantic checks
+    let cases: [(&[u8], &str); 4] = [
+        (b"func f() { return 1; this; }", "`this` can only used in methods"),
+        (b"func h(a) { if (false) { break; } return a; }", "`break` can only used in loop"),
+        (b"if (false) { return 1; }", "`return` can only used in function or method"),
+        (b"func k(a) { return a; }\nif (false) { k(1, 2); }", "function: k's param count: 1, but got: 2")
+    ];
+    for (source, msg) in cases {
+        let errors = compile(source, &Options::default()).unwrap_err();
+        assert_eq!(errors.items.len(), 1);
+        assert_eq!(errors.items[0].msg, msg);
+    }
+}
+
+#[test]
+fn test_assert_getter_once() {
+    // the failed assertion shows the value the getter returned for the condition, it doesn't call the getter again
+    let source = b"func f(c) {\n    assert c.x == 5;\n    assert c.y;\n}";
+    let bytes = compile(source, &Options::default()).unwrap();
+    let program = Loader::new(&bytes).load().unwrap();
+    let code = &program.functions["f"].code;
+    let mut pc = 0;
+    let mut getters = 0;
+    while pc < code.len() {
+        getters += usize::from(code[pc] == OP_GET_MEMBER);
+        pc += instruction_len(&code[pc..]).unwrap();
+    }
+    assert_eq!(getters, 2);
+}
+
+#[test]
+fn test_compile_warnings() {
+    let source = b"func f() { var unused = 1; }";
+    let (result, warnings) = compile_with_warnings(source, &Options::default());
+    assert!(result.is_ok());
+    assert_eq!(warnings.items.len(), 1);
+    assert_eq!(warnings.items[0].code.as_deref(), Some("W0006"));
+
+    let mut options = Options::default();
+    options.lints.allow("unused-variable");
+    let (_, warnings) = compile_with_warnings(source, &options);
+    assert!(warnings.is_empty());
+
+    let mut options = Options::default();
+    options.deny_warnings = true;
+    let errors = compile(source, &options).unwrap_err();
+    assert_eq!(errors.items.len(), 1);
+}
+
+#[test]
+fn test_references() {
+    let program = parse_source(SOURCE);
+    let found: Vec<_> = references(&program, SOURCE.as_bytes()).into_iter().map(|r| (r.name, r.definition)).collect();
+    let a = SOURCE.find("a, b").unwrap();
+    let param = |name: &str, offset| (name.to_owned(), Definition::Param(offset));
+    let decl = |name: &str| (name.to_owned(), Definition::Decl);
+    // definitions of the params, their uses, then the calls
+    assert_eq!(found, [param("a", a), param("b", a + 3), param("a", a), param("b", a + 3), decl("add"), decl("__println"), decl("add")]);
+
+    // the parts before and after a syntax error
+    let source = b"var x = 1;\nvar y = ;\n__println(x);";
+    let (program, errors) = parse_partial(lex(source).unwrap(), source);
+    assert_eq!(errors.items.len(), 1);
+    let found: Vec<_> = references(&program, source).into_iter().map(|r| (r.name, r.offset, r.definition)).collect();
+    assert_eq!(found, [
+        ("x".to_owned(), 4, Definition::Global(4)),
+        ("__println".to_owned(), 21, Definition::Decl),
+        ("x".to_owned(), 31, Definition::Global(4))
+    ]);
+}
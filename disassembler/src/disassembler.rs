@@ -1,44 +1,69 @@
+use std::fmt::Write;
 use common::constant::ConstantItem;
-use common::program::{Class, Program};
+use common::program::{Class, DebugInfo, Program};
 use common::opcode::*;
 use common::reader::LEReader;
 use common::Result;
 
-pub fn disassemble(program: &Program) -> Result<()> {
-    println!("version: {}.{}\n", program.major, program.minor);
+// the assembly listing of `charonp` and `charonc --emit=asm`
+pub fn disassemble(program: &Program) -> Result<String> {
+    let mut out = String::new();
+    let _ = writeln!(out, "version: {}.{}", program.major, program.minor);
+    let _ = writeln!(out, "source file: {}\n", program.source_file.as_deref().unwrap_or("(stripped)"));
 
-    println!("class count: {}\n", program.classes.len());
+    let _ = writeln!(out, "class count: {}\n", program.classes.len());
     for class in program.classes.values() {
-        disassemble_class(class, &program.constant_pool)?;
-        println!();
+        disassemble_class(class, &program.constant_pool, &mut out)?;
+        let _ = writeln!(out);
     }
 
-    println!("function count: {}\n", program.functions.len());
+    let _ = writeln!(out, "function count: {}\n", program.functions.len());
     for func in program.functions.values() {
-        println!("function name: {}, param count: {}, max locals: {}", func.name, func.params, func.max_locals);
-        disassemble_code(&func.code, &program.constant_pool, false)?;
-        println!();
+        let _ = writeln!(out, "function name: {}, param count: {}, max locals: {}, max stack: {}", func.name, func.params, func.max_locals, func.max_stack);
+        disassemble_code(&func.code, &func.debug_info, &program.constant_pool, false, &mut out)?;
+        let _ = writeln!(out);
     }
-    Ok(())
+    Ok(out)
 }
 
-fn disassemble_class(class: &Class, cp: &[ConstantItem]) -> Result<()> {
-    println!("class name: {}, method count: {}", class.name, class.methods.len());
+fn disassemble_class(class: &Class, cp: &[ConstantItem], out: &mut String) -> Result<()> {
+    let _ = writeln!(out, "class name: {}, method count: {}, getter count: {}, setter count: {}"
+             , class.name, class.methods.len(), class.getters.len(), class.setters.len());
+    if !class.traits.is_empty() {
+        let _ = writeln!(out, "    implemented traits: {}", class.traits.join(", "));
+    }
 
     for method in class.methods.values() {
-        println!("    method name: {}, param count: {}, max locals: {}", method.name, method.params, method.max_locals);
-        disassemble_code(&method.code, cp, true)?;
-        println!();
+        let _ = writeln!(out, "    method name: {}, param count: {}, max locals: {}, max stack: {}", method.name, method.params, method.max_locals, method.max_stack);
+        disassemble_code(&method.code, &method.debug_info, cp, true, out)?;
+        let _ = writeln!(out);
+    }
+    for getter in class.getters.values() {
+        let _ = writeln!(out, "    getter name: {}, max locals: {}, max stack: {}", getter.name, getter.max_locals, getter.max_stack);
+        disassemble_code(&getter.code, &getter.debug_info, cp, true, out)?;
+        let _ = writeln!(out);
+    }
+    for setter in class.setters.values() {
+        let _ = writeln!(out, "    setter name: {}, max locals: {}, max stack: {}", setter.name, setter.max_locals, setter.max_stack);
+        disassemble_code(&setter.code, &setter.debug_info, cp, true, out)?;
+        let _ = writeln!(out);
     }
     Ok(())
 }
 
-fn disassemble_code(code: &[u8], cp: &[ConstantItem], intent: bool) -> Result<()> {
+fn disassemble_code(code: &[u8], debug_info: &DebugInfo, cp: &[ConstantItem], intent: bool, out: &mut String) -> Result<()> {
     let mut reader = LEReader::new(code);
     let mut codeinfo = CodeInfo::new();
 
-    while let Ok(opcode) = reader.next_u8() {
-        codeinfo.add_line_byteoff((reader.offset() - 1) as u16);
+    while let Ok(mut opcode) = reader.next_u8() {
+        let pc = reader.offset() - 1;
+        codeinfo.add_line_byteoff(pc);
+
+        // the operand of the next instruction is widened
+        let wide = opcode == OP_WIDE;
+        if wide {
+            opcode = reader.next_u8()?;
+        }
 
         let inst = match opcode {
             OP_CONST_NULL => new_plain_inst("CONST_NULL"),
@@ -54,9 +79,9 @@ fn disassemble_code(code: &[u8], cp: &[ConstantItem], intent: bool) -> Result<()
             OP_LCONST_5 => new_plain_inst("LCONST_5"),
             
             OP_LDC => {
-                let idx = reader.next_u16()?;
+                let idx = next_index(&mut reader, wide)?;
                 let mut raw = format!("LDC {idx}    //");
-                let Some(item) = cp.get(idx as usize) else {
+                let Some(item) = cp.get(idx) else {
                     return Err(format!("`op_ldc`'s argument: {idx} not found in constant pool"));
                 };
                 match item {
@@ -92,15 +117,15 @@ fn disassemble_code(code: &[u8], cp: &[ConstantItem], intent: bool) -> Result<()
             OP_CMP_LTEQ => new_plain_inst("CMP_LTEQ"),
             
             OP_IF => {
-                let idx = reader.next_u16()?;
+                let idx = next_index(&mut reader, wide)?;
                 InstInfo::Jump(format!("IF  {idx}"), idx)
             }
             OP_IF_NOT => {
-                let idx = reader.next_u16()?;
+                let idx = next_index(&mut reader, wide)?;
                 InstInfo::Jump(format!("IF_NOT  {idx}"), idx)
             }
             OP_GOTO => {
-                let idx = reader.next_u16()?;
+                let idx = next_index(&mut reader, wide)?;
                 InstInfo::Jump(format!("GOTO  {idx}"), idx)
             }
             
@@ -110,86 +135,157 @@ fn disassemble_code(code: &[u8], cp: &[ConstantItem], intent: bool) -> Result<()
             OP_POP => new_plain_inst("POP"),
 
             OP_DEF_GLOBAL => {
-                let idx = reader.next_u16()?;
-                let Some(ConstantItem::String(arg)) = cp.get(idx as usize) else {
+                let idx = next_index(&mut reader, wide)?;
+                let Some(ConstantItem::String(arg)) = cp.get(idx) else {
                     return Err("`DEF_GLOBAL` expect string argument as arg name".to_owned());
                 };
                 InstInfo::Plain(format!("DEF_GLOBAL  {idx}    // {arg}"))
             }
             
             OP_SET_GLOBAL => {
-                let idx = reader.next_u16()?;
-                let Some(ConstantItem::String(arg)) = cp.get(idx as usize) else {
+                let idx = next_index(&mut reader, wide)?;
+                let Some(ConstantItem::String(arg)) = cp.get(idx) else {
                     return Err("`SET_GLOBAL` expect string argument as arg name".to_owned());
                 };
                 InstInfo::Plain(format!("SET_GLOBAL  {idx}    // {arg}"))
             }
 
             OP_GET_GLOBAL => {
-                let idx = reader.next_u16()?;
-                let Some(ConstantItem::String(arg)) = cp.get(idx as usize) else {
+                let idx = next_index(&mut reader, wide)?;
+                let Some(ConstantItem::String(arg)) = cp.get(idx) else {
                     return Err("`GET_GLOBAL` expect string argument as arg name".to_owned());
                 };
                 InstInfo::Plain(format!("GET_GLOBAL  {idx}    // {arg}"))
             }
             
-            OP_SET_LOCAL => InstInfo::Plain(format!("SET_LOCAL  {}", reader.next_u8()?)),
-            OP_GET_LOCAL => InstInfo::Plain(format!("GET_LOCAL  {}", reader.next_u8()?)),
+            OP_SET_LOCAL | OP_GET_LOCAL => {
+                let slot = next_slot(&mut reader, wide)? as u16;
+                let name = if opcode == OP_SET_LOCAL { "SET_LOCAL" } else { "GET_LOCAL" };
+                match debug_info.local_variable_name(slot, pc) {
+                    Some(var) => InstInfo::Plain(format!("{name}  {slot}    // {var}")),
+                    None => InstInfo::Plain(format!("{name}  {slot}"))
+                }
+            }
             
             OP_SET_FIELD => {
-                let idx = reader.next_u16()?;
-                let Some(ConstantItem::String(arg)) = cp.get(idx as usize) else {
+                let idx = next_index(&mut reader, wide)?;
+                let Some(ConstantItem::String(arg)) = cp.get(idx) else {
                     return Err("`SET_FIELD` expect string argument as arg name".to_owned());
                 };
                 InstInfo::Plain(format!("SET_FIELD  {idx}    // {arg}"))
             }
 
             OP_GET_MEMBER => {
-                let idx = reader.next_u16()?;
-                let Some(ConstantItem::String(arg)) = cp.get(idx as usize) else {
+                let idx = next_index(&mut reader, wide)?;
+                let Some(ConstantItem::String(arg)) = cp.get(idx) else {
                     return Err("`GET_MEMBER` expect string argument as arg name".to_owned());
                 };
                 InstInfo::Plain(format!("GET_MEMBER  {idx}    // {arg}"))
             }
             
             OP_DUP => new_plain_inst("DUP"),
+
+            OP_IS => {
+                let idx = next_index(&mut reader, wide)?;
+                let Some(ConstantItem::String(arg)) = cp.get(idx) else {
+                    return Err("`IS` expect string argument as class or trait name".to_owned());
+                };
+                InstInfo::Plain(format!("IS  {idx}    // {arg}"))
+            }
+
+            OP_NEW_TUPLE => InstInfo::Plain(format!("NEW_TUPLE  {}", reader.next_u8()?)),
+            OP_UNPACK => InstInfo::Plain(format!("UNPACK  {}", reader.next_u8()?)),
+
+            OP_RANGE => {
+                let inclusive = reader.next_u8()?;
+                InstInfo::Plain(format!("RANGE  {inclusive}    // {}", if inclusive != 0 {"..="} else {".."}))
+            }
+            OP_ITER_NEXT => {
+                let idx = next_index(&mut reader, wide)?;
+                InstInfo::Jump(format!("ITER_NEXT  {idx}"), idx)
+            }
+            OP_DEFER => {
+                let idx = next_index(&mut reader, wide)?;
+                InstInfo::Jump(format!("DEFER  {idx}"), idx)
+            }
+            OP_ASSERT => {
+                let idx = next_index(&mut reader, wide)?;
+                let count = reader.next_u8()?;
+                let Some(ConstantItem::String(desc)) = cp.get(idx) else {
+                    return Err("`ASSERT` expect string argument as description".to_owned());
+                };
+                let mut names = String::new();
+                for _ in 0 .. count {
+                    let idx = next_index(&mut reader, wide)?;
+                    let Some(ConstantItem::String(name)) = cp.get(idx) else {
+                        return Err("`ASSERT` expect string argument as operand name".to_owned());
+                    };
+                    names.push_str(&format!(" {idx}({name})"));
+                }
+                InstInfo::Plain(format!("ASSERT  {idx} {count}{names}    // {desc}"))
+            }
+            OP_INDEX => new_plain_inst("INDEX"),
+            OP_SLICE => new_plain_inst("SLICE"),
             
             _ => return Err(format!("unknown opcode: {opcode}"))
         };
+        let inst = match inst {
+            _ if !wide => inst,
+            InstInfo::Plain(s) => InstInfo::Plain(format!("WIDE {s}")),
+            InstInfo::Jump(s, off) => InstInfo::Jump(format!("WIDE {s}"), off)
+        };
         codeinfo.add_inst(inst);
     }
     
     for (idx, info) in codeinfo.insts.iter().enumerate() {
         if intent {
-            print!("    ");
+            let _ = write!(out, "    ");
         }
         
-        print!("{idx:>4}:  ");
+        let _ = write!(out, "{idx:>4}:  ");
         match info {
-            InstInfo::Plain(s) => println!("{s}"),
+            InstInfo::Plain(s) => {
+                let _ = writeln!(out, "{s}");
+            }
             InstInfo::Jump(s, off) => {
-                print!("{s}    // jump to: ");
-                let mut found = false;
-                for (line, byteoff) in codeinfo.line_byteoff.iter().enumerate() {
-                    if off == byteoff {
-                        println!("{line}");
-                        found = true;
-                        break;
-                    }
-                }
-                if !found {
+                let _ = write!(out, "{s}    // jump to: ");
+                // offsets are sorted, large functions have many instructions
+                let Ok(line) = codeinfo.line_byteoff.binary_search(off) else {
                     return Err(format!("jump byte offset: {off} error, in {s}"));
-                }
+                };
+                let _ = writeln!(out, "{line}");
             }
         }
     }
 
+    // instruction index: source line:column
+    if !debug_info.line_numbers.is_empty() {
+        let intent = if intent { "    " } else { "" };
+        let _ = writeln!(out, "{intent}line number table:");
+        for v in &debug_info.line_numbers {
+            let Ok(idx) = codeinfo.line_byteoff.binary_search(&(v.start_pc as usize)) else {
+                return Err(format!("line number table's start pc: {} is not an instruction", v.start_pc));
+            };
+            let _ = writeln!(out, "{intent}{idx:>4}:  {}:{}", v.line, v.column);
+        }
+    }
+
+    // slot: name, live instruction range
+    if !debug_info.local_variables.is_empty() {
+        let intent = if intent { "    " } else { "" };
+        let _ = writeln!(out, "{intent}local variable table:");
+        for v in &debug_info.local_variables {
+            let inst_idx = |pc: u32| codeinfo.line_byteoff.partition_point(|off| *off < pc as usize);
+            let _ = writeln!(out, "{intent}{:>4}:  {}, {}..{}", v.slot, v.name, inst_idx(v.start_pc), inst_idx(v.end_pc));
+        }
+    }
+
     Ok(())
 }
 
 enum InstInfo {
     Plain(String),
-    Jump(String, u16)// with jump offset in code byte array
+    Jump(String, usize)// with jump offset in code byte array
 }
 
 fn new_plain_inst(assembly: &str) -> InstInfo {
@@ -198,7 +294,7 @@ fn new_plain_inst(assembly: &str) -> InstInfo {
 
 struct CodeInfo {
     insts: Vec<InstInfo>,
-    line_byteoff: Vec<u16> // index is assembly code line, value is start offset in code byte array
+    line_byteoff: Vec<usize> // index is assembly code line, value is start offset in code byte array
 }
 
 impl CodeInfo {
@@ -213,7 +309,7 @@ impl CodeInfo {
         self.insts.push(inst)
     }
 
-    fn add_line_byteoff(&mut self, byteoff: u16) {
+    fn add_line_byteoff(&mut self, byteoff: usize) {
         self.line_byteoff.push(byteoff);
     }
 }
@@ -0,0 +1,3 @@
+mod disassembler;
+
+pub use crate::disassembler::disassemble;
@@ -2,9 +2,8 @@ use std::{env, fs};
 use std::process::exit;
 use common::err_println;
 use common::loader::Loader;
-use crate::disassembler::disassemble;
-
-mod disassembler;
+use common::verifier::verify;
+use charonp::disassemble;
 
 fn main() {
     let args: Vec<_> = env::args().collect();
@@ -27,8 +26,10 @@ fn main() {
     }
 }
 
+// files which fail the verification are shown as well, the error is printed after the code
 fn run(bytes: Vec<u8>) -> Result<(), String> {
-    let program = Loader::new(&bytes).load()?;
+    let program = Loader::new(&bytes).load_unverified()?;
     drop(bytes);
-    disassemble(&program)
+    print!("{}", disassemble(&program)?);
+    verify(&program)
 }
\ No newline at end of file
@@ -0,0 +1,441 @@
+use ahash::{HashMap, HashMapExt};
+use common::constant::ENTRY_NAME;
+use compilerlib::ast::*;
+use compilerlib::token::{Comment, Token, TokenKind};
+use compilerlib::{lex_with_comments, parse, token_len, Diagnostics};
+
+// reprints the ast in the canonical style: 4 spaces indentation, `{` at the end of the line,
+// one space around binary operators and after commas.
+// the literals keep their source text, the comments are kept around the nearest statement or declaration,
+// and one blank line is kept where the source has any. formatting the result again doesn't change it
+
+pub fn format(source: &[u8]) -> Result<String, Diagnostics> {
+    let (tokens, comments) = lex_with_comments(source)?;
+    let program = parse(tokens.clone(), source)?;
+    let mut formatter = Formatter::new(source, &tokens, comments);
+    formatter.program(&program);
+    Ok(formatter.out)
+}
+
+enum Item<'a> {
+    Stmt(&'a Stmt),
+    Func(&'a FuncDecl),
+    Class(&'a ClassDecl),
+    Trait(&'a TraitDecl)
+}
+
+enum Member<'a> {
+    Method(&'a FuncDecl),
+    Getter(&'a FuncDecl),
+    Setter(&'a FuncDecl),
+    Required(&'a FuncDecl)
+}
+
+struct Formatter<'a> {
+    source: &'a [u8],
+    tokens: &'a [Token],
+    // offset of `{` to the offset of the matching `}`
+    closing: HashMap<usize, usize>,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    indent: usize,
+    out: String
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a [u8], tokens: &'a [Token], comments: Vec<Comment>) -> Self {
+        let mut closing = HashMap::new();
+        let mut open = Vec::new();
+        for token in tokens {
+            match token.kind {
+                TokenKind::LBrace => open.push(token.offset),
+                TokenKind::RBrace => if let Some(start) = open.pop() {
+                    closing.insert(start, token.offset);
+                }
+                _ => ()
+            }
+        }
+        Self { source, tokens, closing, comments, next_comment: 0, indent: 0, out: String::new() }
+    }
+
+    fn program(&mut self, program: &'a Program) {
+        let mut items = Vec::new();
+        for func in &program.funcs {
+            if func.name == ENTRY_NAME {
+                items.extend(func.body.iter().map(|stmt| (stmt.offset, Item::Stmt(stmt))));
+            } else {
+                items.push((self.keyword_offset(func.offset), Item::Func(func)));
+            }
+        }
+        items.extend(program.classes.iter().map(|class| (self.keyword_offset(class.offset), Item::Class(class))));
+        items.extend(program.traits.iter().map(|t| (self.keyword_offset(t.offset), Item::Trait(t))));
+        items.sort_by_key(|(offset, _)| *offset);
+
+        for (offset, item) in items {
+            match item {
+                Item::Stmt(stmt) => self.stmt(stmt),
+                Item::Func(func) => {
+                    self.start(offset);
+                    self.func("func", func);
+                }
+                Item::Class(class) => {
+                    self.start(offset);
+                    self.class(class);
+                }
+                Item::Trait(t) => {
+                    self.start(offset);
+                    self.trait_decl(t);
+                }
+            }
+        }
+        self.comments_before(usize::MAX);
+    }
+
+    fn func(&mut self, keyword: &str, func: &FuncDecl) {
+        let head = format!("{keyword} {}({})", func.name, func.params.join(", "));
+        let open = self.lbrace_after(func.offset);
+        self.block(&head, &func.body, open);
+    }
+
+    fn class(&mut self, class: &'a ClassDecl) {
+        let mut head = format!("class {}", class.name);
+        if !class.traits.is_empty() {
+            head.push_str(" impl ");
+            head.push_str(&class.traits.join(", "));
+        }
+        let mut members: Vec<_> = class.methods.iter().map(Member::Method)
+            .chain(class.getters.iter().map(Member::Getter))
+            .chain(class.setters.iter().map(Member::Setter))
+            .collect();
+        let open = self.lbrace_after(class.offset);
+        self.members(&head, &mut members, open);
+    }
+
+    fn trait_decl(&mut self, t: &'a TraitDecl) {
+        let mut members: Vec<_> = t.required.iter().map(Member::Required)
+            .chain(t.provided.iter().map(Member::Method))
+            .collect();
+        let open = self.lbrace_after(t.offset);
+        self.members(&format!("trait {}", t.name), &mut members, open);
+    }
+
+    // methods, getters and setters are kept in the source order
+    fn members(&mut self, head: &str, members: &mut [Member], open: usize) {
+        let close = self.closing[&open];
+        if members.is_empty() && !self.has_comment_before(close) {
+            self.line(&format!("{head} {{}}"));
+            return;
+        }
+
+        self.line(&format!("{head} {{"));
+        self.indent += 1;
+        members.sort_by_key(|member| match member {
+            Member::Method(func) | Member::Getter(func) | Member::Setter(func) | Member::Required(func) => func.offset
+        });
+        for member in members.iter() {
+            match member {
+                Member::Method(func) => {
+                    self.start(self.keyword_offset(func.offset));
+                    self.func("func", func);
+                }
+                Member::Getter(func) => {
+                    self.start(self.keyword_offset(func.offset));
+                    self.func("get", func);
+                }
+                Member::Setter(func) => {
+                    self.start(self.keyword_offset(func.offset));
+                    self.func("set", func);
+                }
+                Member::Required(func) => {
+                    self.start(self.keyword_offset(func.offset));
+                    self.line(&format!("func {}({});", func.name, func.params.join(", ")));
+                }
+            }
+        }
+        self.comments_before(close);
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    // `head {`, the statements and `}`, or `head {}` if there's nothing inside
+    fn block(&mut self, head: &str, stmts: &[Stmt], open: usize) {
+        let close = self.closing[&open];
+        // a plain block has no head
+        let head = if head.is_empty() { String::new() } else { format!("{head} ") };
+        if stmts.is_empty() && !self.has_comment_before(close) {
+            self.line(&format!("{head}{{}}"));
+            return;
+        }
+
+        self.line(&format!("{head}{{"));
+        self.body(stmts, close);
+        self.line("}");
+    }
+
+    fn body(&mut self, stmts: &[Stmt], close: usize) {
+        self.indent += 1;
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.comments_before(close);
+        self.indent -= 1;
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.start(stmt.offset);
+        match &stmt.kind {
+            StmtKind::VarDef(vardef) => match &vardef.init {
+                Some(init) => self.line(&format!("var {} = {};", pattern(&vardef.pattern), self.expr(init))),
+                None => self.line(&format!("var {};", pattern(&vardef.pattern)))
+            }
+            StmtKind::Expr(e) => self.line(&format!("{};", self.expr(e))),
+            StmtKind::SetVar(setvar) => {
+                self.line(&format!("{} {} {};", pattern(&setvar.to), assign_op(&setvar.op), self.expr(&setvar.value)));
+            }
+            StmtKind::Setter(setter) => {
+                let owner = self.operand(&setter.owner, POSTFIX);
+                self.line(&format!("{owner}.{} {} {};", setter.field, assign_op(&setter.op), self.expr(&setter.value)));
+            }
+            StmtKind::If(ifstmt) => self.if_stmt("", ifstmt, stmt.offset),
+            StmtKind::While(while_stmt) => {
+                let head = format!("while ({})", self.expr(&while_stmt.cond));
+                self.block(&head, &while_stmt.body, self.lbrace_after(stmt.offset));
+            }
+            StmtKind::ForIn(for_in) => {
+                let head = format!("for ({} in {})", pattern(&for_in.pattern), self.expr(&for_in.iterable));
+                self.block(&head, &for_in.body, self.lbrace_after(stmt.offset));
+            }
+            StmtKind::Break => self.line("break;"),
+            StmtKind::Continue => self.line("continue;"),
+            StmtKind::Return(None) => self.line("return;"),
+            StmtKind::Return(Some(e)) => self.line(&format!("return {};", self.expr(e))),
+            StmtKind::Defer(e) => self.line(&format!("defer {};", self.expr(e))),
+            StmtKind::Assert(assert) => match &assert.msg {
+                Some(msg) => self.line(&format!("assert {}, {};", self.expr(&assert.cond), self.expr(msg))),
+                None => self.line(&format!("assert {};", self.expr(&assert.cond)))
+            }
+            StmtKind::Block(stmts) => self.block("", stmts, stmt.offset)
+        }
+    }
+
+    // `prefix` is `} else ` for `else if`, `offset` is the offset of `if`
+    fn if_stmt(&mut self, prefix: &str, ifstmt: &IfStmt, offset: usize) {
+        let head = format!("{prefix}if ({})", self.expr(&ifstmt.cond));
+        let open = self.lbrace_after(offset);
+        let close = self.closing[&open];
+        let Some(els) = self.token_after(close).filter(|t| t.kind == TokenKind::Else) else {
+            self.block(&head, &ifstmt.then, open);
+            return;
+        };
+
+        self.line(&format!("{head} {{"));
+        self.body(&ifstmt.then, close);
+        match (self.token_after(els.offset), ifstmt.els.as_slice()) {
+            (Some(Token { kind: TokenKind::If, offset }), [Stmt { kind: StmtKind::If(inner), .. }]) => {
+                self.if_stmt("} else ", inner, *offset);
+            }
+            (Some(Token { kind: TokenKind::LBrace, offset }), els) => {
+                let close = self.closing[offset];
+                self.line("} else {");
+                self.body(els, close);
+                self.line("}");
+            }
+            _ => unreachable!("`else` is followed by `if` or a block")
+        }
+    }
+
+    fn expr(&self, e: &Expr) -> String {
+        match &e.kind {
+            ExprKind::True => "true".to_owned(),
+            ExprKind::False => "false".to_owned(),
+            ExprKind::Null => "null".to_owned(),
+            ExprKind::This => "this".to_owned(),
+            // `0xFF`, `1_000`, escapes and raw strings are kept
+            ExprKind::Long(_) | ExprKind::Double(_) | ExprKind::String(_) => {
+                let len = token_len(self.source, e.offset);
+                String::from_utf8_lossy(&self.source[e.offset..e.offset + len]).into_owned()
+            }
+            ExprKind::GetVar(name) => name.to_owned(),
+            ExprKind::Binary(binary) => {
+                let (op, left, right) = match binary.op {
+                    BinaryOp::Add => ("+", ADD, MULTIPLY),
+                    BinaryOp::Sub => ("-", ADD, MULTIPLY),
+                    BinaryOp::Multiply => ("*", MULTIPLY, UNARY),
+                    BinaryOp::Divide => ("/", MULTIPLY, UNARY),
+                    BinaryOp::Gt => (">", RANGE, RANGE),
+                    BinaryOp::Lt => ("<", RANGE, RANGE),
+                    BinaryOp::GtEq => (">=", RANGE, RANGE),
+                    BinaryOp::LtEq => ("<=", RANGE, RANGE),
+                    BinaryOp::EqEq => ("==", COMPARE, COMPARE),
+                    BinaryOp::BangEq => ("!=", COMPARE, COMPARE)
+                };
+                format!("{} {op} {}", self.operand(&binary.left, left), self.operand(&binary.right, right))
+            }
+            ExprKind::Logic(logic) => match logic.op {
+                LogicOp::Or => format!("{} || {}", self.operand(&logic.left, OR), self.operand(&logic.right, AND)),
+                LogicOp::And => format!("{} && {}", self.operand(&logic.left, AND), self.operand(&logic.right, EQUAL))
+            }
+            ExprKind::Unary(unary) => {
+                let op = match unary.op {
+                    UnaryOp::Bang => "!",
+                    UnaryOp::Neg => "-"
+                };
+                format!("{op}{}", self.operand(&unary.expr, UNARY))
+            }
+            ExprKind::Call(call) => {
+                let args: Vec<_> = call.args.iter().map(|arg| self.expr(arg)).collect();
+                format!("{}({})", self.operand(&call.owner, POSTFIX), args.join(", "))
+            }
+            ExprKind::Getter(getter) => format!("{}.{}", self.operand(&getter.owner, POSTFIX), getter.member),
+            ExprKind::Is(is) => format!("{} is {}", self.operand(&is.expr, RANGE), is.name),
+            ExprKind::Tuple(elements) => {
+                let elements: Vec<_> = elements.iter().map(|e| self.expr(e)).collect();
+                format!("({})", elements.join(", "))
+            }
+            ExprKind::Range(range) => {
+                let op = if range.inclusive { "..=" } else { ".." };
+                format!("{}{op}{}", self.operand(&range.start, ADD), self.operand(&range.end, ADD))
+            }
+            ExprKind::Index(index) => format!("{}[{}]", self.operand(&index.owner, POSTFIX), self.expr(&index.index)),
+            ExprKind::Slice(slice) => {
+                let start = slice.start.as_ref().map(|e| self.expr(e)).unwrap_or_default();
+                let end = slice.end.as_ref().map(|e| self.expr(e)).unwrap_or_default();
+                format!("{}[{start}:{end}]", self.operand(&slice.owner, POSTFIX))
+            }
+        }
+    }
+
+    // parenthesized if `e` binds looser than the parser expects at the position
+    fn operand(&self, e: &Expr, min: u8) -> String {
+        let s = self.expr(e);
+        if precedence(e) < min {
+            format!("({s})")
+        } else {
+            s
+        }
+    }
+
+    // the comments before `offset`, and a blank line if the source has any before it
+    fn start(&mut self, offset: usize) {
+        self.comments_before(offset);
+        self.blank_line(offset);
+    }
+
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment).filter(|c| c.offset < offset) {
+            let text = comment.text.clone();
+            let comment_offset = comment.offset;
+            self.next_comment += 1;
+            if self.is_trailing(comment_offset) && self.out.ends_with('\n') {
+                // at the end of the last line
+                self.out.pop();
+                self.out.push(' ');
+                self.out.push_str(&text);
+                self.out.push('\n');
+            } else {
+                self.blank_line(comment_offset);
+                self.line(&text);
+            }
+        }
+    }
+
+    fn has_comment_before(&self, offset: usize) -> bool {
+        self.comments.get(self.next_comment).is_some_and(|c| c.offset < offset)
+    }
+
+    // the comment follows code in the same line
+    fn is_trailing(&self, offset: usize) -> bool {
+        self.source[..offset].iter().rev()
+            .take_while(|c| **c != b'\n')
+            .any(|c| !c.is_ascii_whitespace())
+    }
+
+    // at most one blank line, not at the start of the file or a block
+    fn blank_line(&mut self, offset: usize) {
+        let newlines = self.source[..offset.min(self.source.len())].iter().rev()
+            .take_while(|c| c.is_ascii_whitespace())
+            .filter(|c| **c == b'\n')
+            .count();
+        if newlines >= 2 && !self.out.is_empty() && !self.out.ends_with("{\n") && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    // offset of `func`, `get`, `set`, `class` or `trait` before the name
+    fn keyword_offset(&self, name_offset: usize) -> usize {
+        let i = self.tokens.partition_point(|t| t.offset < name_offset);
+        i.checked_sub(1).map_or(name_offset, |i| self.tokens[i].offset)
+    }
+
+    // the first `{` after `offset`, expressions have no braces
+    fn lbrace_after(&self, offset: usize) -> usize {
+        let i = self.tokens.partition_point(|t| t.offset <= offset);
+        self.tokens[i..].iter().find(|t| t.kind == TokenKind::LBrace).map_or(offset, |t| t.offset)
+    }
+
+    fn token_after(&self, offset: usize) -> Option<&'a Token> {
+        let i = self.tokens.partition_point(|t| t.offset <= offset);
+        self.tokens.get(i)
+    }
+}
+
+// binding power of the parser's levels, from `||` to the primary expressions
+const OR: u8 = 1;
+const AND: u8 = 2;
+const EQUAL: u8 = 3;
+const COMPARE: u8 = 4;
+const RANGE: u8 = 5;
+const ADD: u8 = 6;
+const MULTIPLY: u8 = 7;
+const UNARY: u8 = 8;
+const POSTFIX: u8 = 9;
+const PRIMARY: u8 = 10;
+
+fn precedence(e: &Expr) -> u8 {
+    match &e.kind {
+        ExprKind::Logic(logic) => match logic.op {
+            LogicOp::Or => OR,
+            LogicOp::And => AND
+        }
+        ExprKind::Binary(binary) => match binary.op {
+            BinaryOp::EqEq | BinaryOp::BangEq => EQUAL,
+            BinaryOp::Gt | BinaryOp::Lt | BinaryOp::GtEq | BinaryOp::LtEq => COMPARE,
+            BinaryOp::Add | BinaryOp::Sub => ADD,
+            BinaryOp::Multiply | BinaryOp::Divide => MULTIPLY
+        }
+        ExprKind::Is(_) => COMPARE,
+        ExprKind::Range(_) => RANGE,
+        ExprKind::Unary(_) => UNARY,
+        ExprKind::Call(_) | ExprKind::Getter(_) | ExprKind::Index(_) | ExprKind::Slice(_) => POSTFIX,
+        ExprKind::True | ExprKind::False | ExprKind::Null | ExprKind::This | ExprKind::Long(_) | ExprKind::Double(_)
+        | ExprKind::String(_) | ExprKind::GetVar(_) | ExprKind::Tuple(_) => PRIMARY
+    }
+}
+
+fn pattern(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Var(name) => name.to_owned(),
+        Pattern::Tuple(patterns) => {
+            let patterns: Vec<_> = patterns.iter().map(self::pattern).collect();
+            format!("({})", patterns.join(", "))
+        }
+    }
+}
+
+fn assign_op(op: &AssignOp) -> &'static str {
+    match op {
+        AssignOp::Assign => "=",
+        AssignOp::AddAssign => "+=",
+        AssignOp::SubAssign => "-=",
+        AssignOp::MultiplyAssign => "*=",
+        AssignOp::DivideAssign => "/="
+    }
+}
@@ -0,0 +1,85 @@
+use std::{env, fs};
+use std::io::{self, Read, Write};
+use std::process::exit;
+use common::diagnostic::{set_error_format, Diagnostic, ErrorFormat};
+use crate::formatter::format;
+
+mod formatter;
+
+fn main() {
+    let mut check = false;
+    let mut paths = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if arg.starts_with("--error-format=") => match ErrorFormat::from_arg(&arg["--error-format=".len()..]) {
+                Some(format) => set_error_format(format),
+                None => usage()
+            }
+            _ if arg == "-" || !arg.starts_with('-') => paths.push(arg),
+            _ => usage()
+        }
+    }
+    // stdin to stdout
+    if paths.is_empty() {
+        paths.push("-".to_owned());
+    }
+
+    // the exit code is the count of the files which failed to parse, or are not formatted with `--check`
+    let mut failed = 0;
+    for path in &paths {
+        if let Err(e) = run(path, check) {
+            e.emit();
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        exit(failed.min(u8::MAX as usize) as i32);
+    }
+}
+
+fn usage() -> ! {
+    Diagnostic::error("usage: charonfmt [--check] [--error-format=human|json] [charon source file paths, `-` or none for stdin]".to_owned()).emit();
+    exit(1);
+}
+
+// the files are formatted in place, `--check` only reports the files which would be changed
+fn run(path: &str, check: bool) -> Result<(), Box<Diagnostic>> {
+    let name = if path == "-" { "<stdin>" } else { path };
+    let source = read(path).map_err(|e| Box::new(Diagnostic::error(format!("failed to read source code file: {name}, with error: {e}"))))?;
+    let formatted = match format(&source) {
+        Ok(v) => v,
+        Err(e) => {
+            e.print(name);
+            return Err(Box::new(Diagnostic::error(format!("failed to format {name}, it has syntax errors"))));
+        }
+    };
+
+    if check {
+        if formatted.as_bytes() != source {
+            let mut e = Diagnostic::error(format!("{name} is not formatted"));
+            e.help.push(format!("run `charonfmt {path}` to format it"));
+            return Err(Box::new(e));
+        }
+        return Ok(());
+    }
+
+    let written = if path == "-" {
+        io::stdout().write_all(formatted.as_bytes())
+    } else if formatted.as_bytes() != source {
+        fs::write(path, formatted)
+    } else {
+        Ok(())
+    };
+    written.map_err(|e| Box::new(Diagnostic::error(format!("failed to write {name}, with error: {e}"))))
+}
+
+fn read(path: &str) -> io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        fs::read(path)
+    }
+}
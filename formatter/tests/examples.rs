@@ -0,0 +1,97 @@
+// every example is formatted into a fixed point: formatting the result again doesn't change it,
+// and the formatted code compiles to the same bytecode as the original
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use compilerlib::{compile, Options};
+
+fn examples() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples");
+    let mut paths: Vec<_> = fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "charon"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn format(source: &[u8]) -> Vec<u8> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_charonfmt"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(source).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+// line numbers and the source text of asserts differ
+fn bytecode(source: &[u8]) -> Vec<u8> {
+    let mut options = Options::default();
+    options.debug_info = false;
+    options.strip_asserts = true;
+    compile(source, &options).unwrap()
+}
+
+#[test]
+fn test_format_examples() {
+    let paths = examples();
+    assert!(!paths.is_empty());
+    for path in paths {
+        let name = path.display();
+        let source = fs::read(&path).unwrap();
+        let formatted = format(&source);
+        assert_eq!(String::from_utf8_lossy(&format(&formatted)), String::from_utf8_lossy(&formatted), "formatting {name} twice");
+        assert_eq!(bytecode(&source), bytecode(&formatted), "bytecode of formatted {name}");
+    }
+}
+
+#[test]
+fn test_check() {
+    let path = examples().into_iter().next().unwrap();
+    let formatted = format(&fs::read(path).unwrap());
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let formatted_path = dir.join("formatted.charon");
+    fs::write(&formatted_path, formatted).unwrap();
+    let unformatted_path = dir.join("unformatted.charon");
+    fs::write(&unformatted_path, b"var  a=1;").unwrap();
+
+    let check = |path: &Path| Command::new(env!("CARGO_BIN_EXE_charonfmt")).arg("--check").arg(path).output().unwrap();
+    assert!(check(&formatted_path).status.success());
+    let output = check(&unformatted_path);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not formatted"));
+    // `--check` doesn't write the file
+    assert_eq!(fs::read(&unformatted_path).unwrap(), b"var  a=1;");
+}
+
+#[test]
+fn test_comments_and_parentheses() {
+    let source = br#"#!/usr/bin/env charon
+var a=(1+2)*3;   // trailing
+
+
+if(a>1&&(b<2||a==3)){ // after brace
+    __println(-(a - 1) - -a);
+}
+else {
+    // inside else
+}
+class A {}
+"#;
+    let expected = r#"#!/usr/bin/env charon
+var a = (1 + 2) * 3; // trailing
+
+if (a > 1 && (b < 2 || a == 3)) { // after brace
+    __println(-(a - 1) - -a);
+} else {
+    // inside else
+}
+class A {}
+"#;
+    assert_eq!(String::from_utf8_lossy(&format(source)), expected);
+}
@@ -0,0 +1,256 @@
+use ahash::{HashSet, HashSetExt};
+use common::constant::{ENTRY_NAME, FFI_FUNCTIONS};
+use common::json::Json;
+use compilerlib::ast::{FuncDecl, Program};
+use compilerlib::token::TokenKind;
+use compilerlib::Definition;
+use crate::document::Document;
+
+// go to definition, hover, completion & document symbols on the analysis of a document.
+// objects are not typed, a member such as `a.area()` is any method, getter or setter with the name
+
+// lsp `CompletionItemKind` & `SymbolKind`
+const COMPLETION_METHOD: i64 = 2;
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_VARIABLE: i64 = 6;
+const COMPLETION_CLASS: i64 = 7;
+const COMPLETION_PROPERTY: i64 = 10;
+const SYMBOL_CLASS: i64 = 5;
+const SYMBOL_METHOD: i64 = 6;
+const SYMBOL_PROPERTY: i64 = 7;
+const SYMBOL_INTERFACE: i64 = 11;
+const SYMBOL_FUNCTION: i64 = 12;
+const SYMBOL_VARIABLE: i64 = 13;
+
+#[derive(Clone, Copy, PartialEq)]
+enum MemberKind {
+    Method,
+    Getter,
+    Setter
+}
+
+// a method, getter or setter of a class, or a method of a trait
+struct Member<'a> {
+    owner: &'a str,
+    kind: MemberKind,
+    func: &'a FuncDecl
+}
+
+impl Member<'_> {
+    fn signature(&self) -> String {
+        let keyword = match self.kind {
+            MemberKind::Method => "func",
+            MemberKind::Getter => "get",
+            MemberKind::Setter => "set"
+        };
+        format!("{keyword} {}.{}({})", self.owner, self.func.name, self.func.params.join(", "))
+    }
+}
+
+fn members(program: &Program) -> Vec<Member> {
+    let mut members = Vec::new();
+    for class in &program.classes {
+        let owner = class.name.as_str();
+        members.extend(class.methods.iter().map(|func| Member { owner, kind: MemberKind::Method, func }));
+        members.extend(class.getters.iter().map(|func| Member { owner, kind: MemberKind::Getter, func }));
+        members.extend(class.setters.iter().map(|func| Member { owner, kind: MemberKind::Setter, func }));
+    }
+    for t in &program.traits {
+        let owner = t.name.as_str();
+        members.extend(t.required.iter().chain(&t.provided).map(|func| Member { owner, kind: MemberKind::Method, func }));
+    }
+    members
+}
+
+fn funcs(program: &Program) -> impl Iterator<Item = &FuncDecl> {
+    program.funcs.iter().filter(|func| func.name != ENTRY_NAME)
+}
+
+fn func_signature(func: &FuncDecl) -> String {
+    format!("func {}({})", func.name, func.params.join(", "))
+}
+
+fn ffi_signature(name: &str, arity: u8) -> String {
+    let params: Vec<_> = (0..arity).map(|i| format!("arg{i}")).collect();
+    format!("func {name}({})", params.join(", "))
+}
+
+// offsets of the functions, classes and traits with the name
+fn decl_offsets(program: &Program, name: &str) -> Vec<usize> {
+    let mut offsets: Vec<_> = funcs(program).filter(|func| func.name == name).map(|func| func.offset).collect();
+    offsets.extend(program.classes.iter().filter(|class| class.name == name).map(|class| class.offset));
+    offsets.extend(program.traits.iter().filter(|t| t.name == name).map(|t| t.offset));
+    offsets
+}
+
+// `a.name`, the name starts at `start`
+fn is_member(text: &str, start: usize) -> bool {
+    let before = text.get(..start).unwrap_or_default().trim_end();
+    before.ends_with('.') && !before.ends_with("..")
+}
+
+// offsets and lengths of the names where the identifier at the offset is defined
+pub fn definition(doc: &Document, offset: usize) -> Vec<(usize, usize)> {
+    let Some((name, start)) = doc.identifier_at(offset) else {
+        return Vec::new();
+    };
+    let members = members(&doc.program);
+    if is_member(&doc.text, start) {
+        return members.iter().filter(|m| m.func.name == name).map(|m| (m.func.offset, name.len())).collect();
+    }
+    // the name of a method is its own definition
+    if let Some(m) = members.iter().find(|m| m.func.offset == start) {
+        return vec![(m.func.offset, name.len())];
+    }
+
+    match doc.references.iter().find(|r| r.offset == start).map(|r| r.definition) {
+        Some(Definition::Local(offset) | Definition::Param(offset) | Definition::Global(offset)) => vec![(offset, name.len())],
+        // ffi functions are not in the source
+        _ => decl_offsets(&doc.program, name).into_iter().map(|offset| (offset, name.len())).collect()
+    }
+}
+
+// the signature in a code block, and what the name is
+pub fn hover(doc: &Document, offset: usize) -> Option<(String, usize, usize)> {
+    let (name, start) = doc.identifier_at(offset)?;
+    let members = members(&doc.program);
+    let (code, description) = if is_member(&doc.text, start) {
+        let signatures: Vec<_> = members.iter().filter(|m| m.func.name == name).map(|m| m.signature()).collect();
+        if signatures.is_empty() {
+            return None;
+        }
+        (signatures.join("\n"), "member")
+    } else if let Some(m) = members.iter().find(|m| m.func.offset == start) {
+        (m.signature(), "member")
+    } else {
+        match doc.references.iter().find(|r| r.offset == start).map(|r| r.definition) {
+            Some(Definition::Local(_)) => (format!("var {name}"), "local variable"),
+            Some(Definition::Param(_)) => (name.to_owned(), "parameter"),
+            Some(Definition::Global(_)) => (format!("var {name}"), "global variable"),
+            _ => decl_hover(&doc.program, name)?
+        }
+    };
+    Some((format!("```charon\n{code}\n```\n{description}"), start, name.len()))
+}
+
+fn decl_hover(program: &Program, name: &str) -> Option<(String, &'static str)> {
+    if let Some(func) = funcs(program).find(|func| func.name == name) {
+        return Some((func_signature(func), "function"));
+    }
+    if let Some(class) = program.classes.iter().find(|class| class.name == name) {
+        let code = if class.traits.is_empty() {
+            format!("class {name}")
+        } else {
+            format!("class {name} impl {}", class.traits.join(", "))
+        };
+        return Some((code, "class"));
+    }
+    if program.traits.iter().any(|t| t.name == name) {
+        return Some((format!("trait {name}"), "trait"));
+    }
+    FFI_FUNCTIONS.iter().find(|(ffi, _)| *ffi == name).map(|(_, arity)| (ffi_signature(name, *arity), "ffi function"))
+}
+
+// the members after `.`, otherwise the globals, functions, classes and ffi functions
+pub fn completion(doc: &Document, offset: usize) -> Vec<Json> {
+    let offset = offset.min(doc.text.len());
+    let start = doc.text.get(..offset).unwrap_or_default().trim_end_matches(|c: char| c == '_' || c.is_alphanumeric()).len();
+    let mut items = Vec::new();
+    let mut labels = HashSet::new();
+    let mut push = |label: &str, kind: i64, detail: String| {
+        if labels.insert(label.to_owned()) {
+            items.push(Json::object([("label", Json::string(label)), ("kind", Json::Long(kind)), ("detail", Json::String(detail))]));
+        }
+    };
+
+    if is_member(&doc.text, start) {
+        for m in members(&doc.program) {
+            let kind = if m.kind == MemberKind::Method { COMPLETION_METHOD } else { COMPLETION_PROPERTY };
+            push(&m.func.name, kind, m.signature());
+        }
+        return items;
+    }
+
+    for r in &doc.references {
+        if r.definition == Definition::Global(r.offset) {
+            push(&r.name, COMPLETION_VARIABLE, format!("var {}", r.name));
+        }
+    }
+    for func in funcs(&doc.program) {
+        push(&func.name, COMPLETION_FUNCTION, func_signature(func));
+    }
+    for class in &doc.program.classes {
+        push(&class.name, COMPLETION_CLASS, format!("class {}", class.name));
+    }
+    for (name, arity) in FFI_FUNCTIONS {
+        push(name, COMPLETION_FUNCTION, ffi_signature(name, arity));
+    }
+    items
+}
+
+// functions, classes with their members, traits and global variables, in source order
+pub fn document_symbols(doc: &Document) -> Vec<Json> {
+    let mut symbols = Vec::new();
+    for func in funcs(&doc.program) {
+        symbols.push((func.offset, symbol(doc, &func.name, func_signature(func), SYMBOL_FUNCTION, func.offset, Vec::new())));
+    }
+    let members = members(&doc.program);
+    let children = |owner: &str| -> Vec<Json> {
+        members.iter().filter(|m| m.owner == owner).map(|m| {
+            let kind = if m.kind == MemberKind::Method { SYMBOL_METHOD } else { SYMBOL_PROPERTY };
+            symbol(doc, &m.func.name, m.signature(), kind, m.func.offset, Vec::new())
+        }).collect()
+    };
+    for class in &doc.program.classes {
+        let detail = format!("class {}", class.name);
+        symbols.push((class.offset, symbol(doc, &class.name, detail, SYMBOL_CLASS, class.offset, children(&class.name))));
+    }
+    for t in &doc.program.traits {
+        let detail = format!("trait {}", t.name);
+        symbols.push((t.offset, symbol(doc, &t.name, detail, SYMBOL_INTERFACE, t.offset, children(&t.name))));
+    }
+    for r in &doc.references {
+        if r.definition == Definition::Global(r.offset) {
+            let range = doc.range(r.offset, r.name.len());
+            symbols.push((r.offset, document_symbol(&r.name, format!("var {}", r.name), SYMBOL_VARIABLE, range.clone(), range, Vec::new())));
+        }
+    }
+    symbols.sort_by_key(|(offset, _)| *offset);
+    symbols.into_iter().map(|(_, symbol)| symbol).collect()
+}
+
+// the range is from the keyword to the end of the body
+fn symbol(doc: &Document, name: &str, detail: String, kind: i64, offset: usize, children: Vec<Json>) -> Json {
+    let (start, end) = decl_range(doc, offset).unwrap_or((offset, offset + name.len()));
+    let range = doc.range(start, end - start);
+    document_symbol(name, detail, kind, range, doc.range(offset, name.len()), children)
+}
+
+fn document_symbol(name: &str, detail: String, kind: i64, range: Json, selection_range: Json, children: Vec<Json>) -> Json {
+    Json::object([
+        ("name", Json::string(name)),
+        ("detail", Json::String(detail)),
+        ("kind", Json::Long(kind)),
+        ("range", range),
+        ("selectionRange", selection_range),
+        ("children", Json::Array(children))
+    ])
+}
+
+// the keyword before the name, to the `}` of the body or `;` of a required method
+fn decl_range(doc: &Document, offset: usize) -> Option<(usize, usize)> {
+    let tokens = &doc.tokens;
+    let i = doc.token_index(offset)?;
+    let start = tokens[i.checked_sub(1)?].offset;
+    let mut depth = 0;
+    for token in &tokens[i..] {
+        match token.kind {
+            TokenKind::Semi if depth == 0 => return Some((start, token.offset + 1)),
+            TokenKind::LBrace => depth += 1,
+            TokenKind::RBrace if depth == 1 => return Some((start, token.offset + 1)),
+            TokenKind::RBrace => depth -= 1,
+            _ => ()
+        }
+    }
+    None
+}
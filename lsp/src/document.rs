@@ -0,0 +1,106 @@
+use common::json::Json;
+use compilerlib::ast::Program;
+use compilerlib::token::{Token, TokenKind};
+use compilerlib::{lex, parse_partial, references, Reference};
+
+// an open `.charon` file, with the analysis of its latest text.
+// positions of lsp are 0-based lines and utf-16 code units in the line, offsets are bytes of the text
+
+pub struct Document {
+    pub text: String,
+    line_starts: Vec<usize>,
+    // empty if the text doesn't lex, the offsets always refer to `text`
+    pub tokens: Vec<Token>,
+    pub program: Program,
+    pub references: Vec<Reference>
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let mut doc = Self {
+            text: String::new(),
+            line_starts: Vec::new(),
+            tokens: Vec::new(),
+            program: Program::new(Vec::new(), Vec::new(), Vec::new()),
+            references: Vec::new()
+        };
+        doc.update(text);
+        doc
+    }
+
+    pub fn update(&mut self, text: String) {
+        self.line_starts = vec![0];
+        self.line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        self.text = text;
+
+        let bytes = self.text.as_bytes();
+        let Ok(tokens) = lex(bytes) else {
+            self.tokens.clear();
+            self.program = Program::new(Vec::new(), Vec::new(), Vec::new());
+            self.references.clear();
+            return;
+        };
+        // the parts with syntax errors are skipped
+        let (program, _) = parse_partial(tokens.clone(), bytes);
+        self.references = references(&program, bytes);
+        self.program = program;
+        self.tokens = tokens;
+    }
+
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let start = self.line_starts[line];
+        let character = self.text.get(start..offset).map_or(0, |s| s.encode_utf16().count());
+        (line, character)
+    }
+
+    // the offset of a position, positions after the end of the line are the end of the line
+    pub fn offset(&self, line: usize, character: usize) -> usize {
+        let Some(start) = self.line_starts.get(line).copied() else {
+            return self.text.len();
+        };
+        let end = self.line_starts.get(line + 1).map_or(self.text.len(), |next| next - 1);
+        let mut units = 0;
+        for (i, c) in self.text[start..end].char_indices() {
+            if units >= character {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        end
+    }
+
+    // the offset of a 0-based line and a count of chars, as the spans of diagnostics
+    pub fn char_offset(&self, line: usize, chars: usize) -> usize {
+        let Some(start) = self.line_starts.get(line).copied() else {
+            return self.text.len();
+        };
+        let end = self.line_starts.get(line + 1).map_or(self.text.len(), |next| next - 1);
+        self.text[start..end].char_indices().nth(chars).map_or(end, |(i, _)| start + i)
+    }
+
+    pub fn range(&self, offset: usize, len: usize) -> Json {
+        Json::object([("start", self.position_json(offset)), ("end", self.position_json(offset + len))])
+    }
+
+    fn position_json(&self, offset: usize) -> Json {
+        let (line, character) = self.position(offset);
+        Json::object([("line", Json::Long(line as i64)), ("character", Json::Long(character as i64))])
+    }
+
+    // the identifier token at the offset, the offset may be right after it
+    pub fn identifier_at(&self, offset: usize) -> Option<(&str, usize)> {
+        let i = self.tokens.partition_point(|t| t.offset <= offset);
+        // the token starting at the offset, or the one before it
+        self.tokens[i.saturating_sub(2)..i].iter().rev().find_map(|token| match &token.kind {
+            TokenKind::Identifier(name) if offset <= token.offset + name.len() => Some((name.as_str(), token.offset)),
+            _ => None
+        })
+    }
+
+    // index of the token at the offset
+    pub fn token_index(&self, offset: usize) -> Option<usize> {
+        self.tokens.binary_search_by_key(&offset, |t| t.offset).ok()
+    }
+}
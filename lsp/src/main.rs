@@ -0,0 +1,79 @@
+use std::io::{self, BufRead, Write};
+use std::process::exit;
+use common::diagnostic::Diagnostic;
+use common::json::Json;
+use crate::server::Server;
+
+mod document;
+mod analysis;
+mod server;
+
+// language server of `.charon` files, json-rpc messages over stdin & stdout:
+// `Content-Length: <bytes>\r\n\r\n<json>`, errors are logged to stderr
+
+fn main() {
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    let mut server = Server::new();
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(v)) => v,
+            // the client closed stdin without `exit`
+            Ok(None) => exit(1),
+            Err(e) => fail(e)
+        };
+        let message = match Json::parse(&message) {
+            Ok(v) => v,
+            Err(e) => {
+                let error = Json::object([("code", Json::Long(-32700)), ("message", Json::String(format!("parse error: {e}")))]);
+                let response = Json::object([("jsonrpc", Json::string("2.0")), ("id", Json::Null), ("error", error)]);
+                write_message(&mut output, &response).unwrap_or_else(|e| fail(e));
+                continue;
+            }
+        };
+        for response in server.handle(&message) {
+            write_message(&mut output, &response).unwrap_or_else(|e| fail(e));
+        }
+        if let Some(code) = server.exit_code() {
+            exit(code);
+        }
+    }
+}
+
+fn fail(e: io::Error) -> ! {
+    Diagnostic::error(format!("charon-lsp: {e}")).emit();
+    exit(1);
+}
+
+// the content of the next message, `None` at the end of the input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        // other headers, such as `Content-Type`, are ignored
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                len = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(len) = len else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header"));
+    };
+    let mut content = vec![0; len];
+    input.read_exact(&mut content)?;
+    String::from_utf8(content).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+    output.flush()
+}
@@ -0,0 +1,206 @@
+use ahash::{HashMap, HashMapExt};
+use common::diagnostic::{Diagnostic, Level};
+use common::json::Json;
+use compilerlib::{compile_with_warnings, Options};
+use crate::analysis::{completion, definition, document_symbols, hover};
+use crate::document::Document;
+
+// the state of the session: open documents, and whether `shutdown` was requested.
+// text is synchronized in full on every change
+
+// json-rpc error codes
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    exit_code: Option<i32>
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self {
+            documents: HashMap::new(),
+            shutdown: false,
+            exit_code: None
+        }
+    }
+
+    // set after `exit`, 0 if `shutdown` was requested before
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    // the responses & notifications to send
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or_default();
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+
+        let result = if self.shutdown {
+            Err((INVALID_REQUEST, "the server is shut down".to_owned()))
+        } else {
+            self.request(method, params)
+        };
+        let mut response = vec![("jsonrpc".to_owned(), Json::string("2.0")), ("id".to_owned(), id.clone())];
+        match result {
+            Ok(result) => response.push(("result".to_owned(), result)),
+            Err((code, msg)) => {
+                let error = Json::object([("code", Json::Long(code)), ("message", Json::String(msg))]);
+                response.push(("error".to_owned(), error));
+            }
+        }
+        vec![Json::Object(response)]
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i64, String)> {
+        match method {
+            "initialize" => Ok(initialize_result()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => {
+                let (uri, doc, offset) = self.position(params)?;
+                let locations = definition(doc, offset).into_iter().map(|(offset, len)| {
+                    Json::object([("uri", Json::string(uri)), ("range", doc.range(offset, len))])
+                }).collect();
+                Ok(Json::Array(locations))
+            }
+            "textDocument/hover" => {
+                let (_, doc, offset) = self.position(params)?;
+                Ok(hover(doc, offset).map_or(Json::Null, |(markdown, start, len)| Json::object([
+                    ("contents", Json::object([("kind", Json::string("markdown")), ("value", Json::String(markdown))])),
+                    ("range", doc.range(start, len))
+                ])))
+            }
+            "textDocument/completion" => {
+                let (_, doc, offset) = self.position(params)?;
+                Ok(Json::Array(completion(doc, offset)))
+            }
+            "textDocument/documentSymbol" => {
+                let (_, doc) = self.document(params)?;
+                Ok(Json::Array(document_symbols(doc)))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method: {method}")))
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let text_document = params.get("textDocument").unwrap_or(&Json::Null);
+        let Some(uri) = text_document.get("uri").and_then(Json::as_str) else {
+            if method == "exit" {
+                self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+            }
+            return Vec::new();
+        };
+        match method {
+            "textDocument/didOpen" => {
+                let text = text_document.get("text").and_then(Json::as_str).unwrap_or_default();
+                self.documents.insert(uri.to_owned(), Document::new(text.to_owned()));
+            }
+            // full sync, the last change is the whole text
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or_default();
+                let text = changes.last().and_then(|change| change.get("text")).and_then(Json::as_str);
+                match (self.documents.get_mut(uri), text) {
+                    (Some(doc), Some(text)) => doc.update(text.to_owned()),
+                    (None, Some(text)) => {
+                        self.documents.insert(uri.to_owned(), Document::new(text.to_owned()));
+                    }
+                    _ => return Vec::new()
+                }
+            }
+            // the diagnostics of a closed file are cleared
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, Vec::new())];
+            }
+            _ => return Vec::new()
+        }
+        let doc = &self.documents[uri];
+        vec![publish_diagnostics(uri, diagnostics(doc))]
+    }
+
+    fn document<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a Document), (i64, String)> {
+        let uri = params.get("textDocument").and_then(|v| v.get("uri")).and_then(Json::as_str)
+            .ok_or_else(|| (INVALID_PARAMS, "missing textDocument.uri".to_owned()))?;
+        match self.documents.get(uri) {
+            Some(doc) => Ok((uri, doc)),
+            None => Err((INVALID_PARAMS, format!("document is not open: {uri}")))
+        }
+    }
+
+    // the document and the offset of `params.position`
+    fn position<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a Document, usize), (i64, String)> {
+        let (uri, doc) = self.document(params)?;
+        let position = params.get("position").unwrap_or(&Json::Null);
+        let line = position.get("line").and_then(Json::as_i64);
+        let character = position.get("character").and_then(Json::as_i64);
+        let (Some(line), Some(character)) = (line, character) else {
+            return Err((INVALID_PARAMS, "missing position".to_owned()));
+        };
+        Ok((uri, doc, doc.offset(line.max(0) as usize, character.max(0) as usize)))
+    }
+}
+
+fn initialize_result() -> Json {
+    Json::object([
+        ("capabilities", Json::object([
+            ("textDocumentSync", Json::Long(1)),
+            ("definitionProvider", Json::Bool(true)),
+            ("hoverProvider", Json::Bool(true)),
+            ("completionProvider", Json::object([("triggerCharacters", Json::Array(vec![Json::string(".")]))])),
+            ("documentSymbolProvider", Json::Bool(true))
+        ])),
+        ("serverInfo", Json::object([("name", Json::string("charon-lsp")), ("version", Json::string(env!("CARGO_PKG_VERSION")))]))
+    ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object([
+        ("jsonrpc", Json::string("2.0")),
+        ("method", Json::string("textDocument/publishDiagnostics")),
+        ("params", Json::object([("uri", Json::string(uri)), ("diagnostics", Json::Array(diagnostics))]))
+    ])
+}
+
+// the errors of lexing, parsing & `check_and_gen`, and the warnings
+fn diagnostics(doc: &Document) -> Vec<Json> {
+    let (result, warnings) = compile_with_warnings(doc.text.as_bytes(), &Options::default());
+    let errors = result.err().map(|e| e.items).unwrap_or_default();
+    errors.iter().chain(&warnings.items).map(|item| diagnostic(doc, item)).collect()
+}
+
+fn diagnostic(doc: &Document, item: &Diagnostic) -> Json {
+    let range = match &item.span {
+        Some(span) => {
+            let start = doc.char_offset(span.line - 1, span.column - 1);
+            let end = doc.char_offset(span.line - 1, span.column - 1 + span.len.max(1));
+            doc.range(start, end - start)
+        }
+        None => doc.range(0, 0)
+    };
+    let mut message = item.msg.clone();
+    for note in &item.notes {
+        message.push_str(&format!("\nnote: {note}"));
+    }
+    for help in &item.help {
+        message.push_str(&format!("\nhelp: {help}"));
+    }
+    let severity = if item.level == Level::Error { 1 } else { 2 };
+    let mut entries = vec![
+        ("range".to_owned(), range),
+        ("severity".to_owned(), Json::Long(severity)),
+        ("source".to_owned(), Json::string("charon")),
+        ("message".to_owned(), Json::String(message))
+    ];
+    if let Some(code) = &item.code {
+        entries.push(("code".to_owned(), Json::string(code)));
+    }
+    Json::Object(entries)
+}
@@ -0,0 +1,291 @@
+// the server is driven over stdio with scripted json-rpc messages, as an editor does
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use common::json::Json;
+
+const URI: &str = "file:///tmp/shapes.charon";
+
+const SOURCE: &str = r#"func add(a, b) {
+    return a + b;
+}
+
+class Circle {
+    func area() {
+        return 3 * this.r * this.r;
+    }
+
+    get diameter() {
+        return 2 * this.r;
+    }
+}
+
+var c = Circle();
+c.r = 2;
+var total = add(c.area(), 1);
+__println(total);
+"#;
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: i64
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_charon-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut client = Self { child, stdin, stdout, next_id: 1 };
+        let result = client.request("initialize", Json::object([("capabilities", Json::object([]))]));
+        assert!(result.get("capabilities").and_then(|v| v.get("definitionProvider")).is_some());
+        client.notify("initialized", Json::object([]));
+        client
+    }
+
+    fn send(&mut self, message: Json) {
+        let content = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{content}", content.len()).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Json {
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(v) = line.strip_prefix("Content-Length:") {
+                len = v.trim().parse().unwrap();
+            }
+        }
+        let mut content = vec![0; len];
+        self.stdout.read_exact(&mut content).unwrap();
+        Json::parse(&String::from_utf8(content).unwrap()).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Json) {
+        self.send(Json::object([("jsonrpc", Json::string("2.0")), ("method", Json::string(method)), ("params", params)]));
+    }
+
+    // the whole response, notifications before it are skipped
+    fn call(&mut self, method: &str, params: Json) -> Json {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(Json::object([
+            ("jsonrpc", Json::string("2.0")),
+            ("id", Json::Long(id)),
+            ("method", Json::string(method)),
+            ("params", params)
+        ]));
+        loop {
+            let message = self.receive();
+            if message.get("id") == Some(&Json::Long(id)) {
+                return message;
+            }
+        }
+    }
+
+    fn request(&mut self, method: &str, params: Json) -> Json {
+        let response = self.call(method, params);
+        match response.get("result") {
+            Some(result) => result.clone(),
+            None => panic!("{method} failed: {response}")
+        }
+    }
+
+    fn diagnostics(&mut self) -> Vec<Json> {
+        let message = self.receive();
+        assert_eq!(message.get("method").and_then(Json::as_str), Some("textDocument/publishDiagnostics"));
+        let params = message.get("params").unwrap();
+        assert_eq!(params.get("uri").and_then(Json::as_str), Some(URI));
+        params.get("diagnostics").and_then(Json::as_array).unwrap().to_vec()
+    }
+
+    fn open(&mut self, text: &str) -> Vec<Json> {
+        let document = Json::object([
+            ("uri", Json::string(URI)),
+            ("languageId", Json::string("charon")),
+            ("version", Json::Long(1)),
+            ("text", Json::string(text))
+        ]);
+        self.notify("textDocument/didOpen", Json::object([("textDocument", document)]));
+        self.diagnostics()
+    }
+
+    // full sync, the whole text
+    fn change(&mut self, text: &str, version: i64) -> Vec<Json> {
+        let change = Json::object([("text", Json::string(text))]);
+        self.notify("textDocument/didChange", Json::object([
+            ("textDocument", Json::object([("uri", Json::string(URI)), ("version", Json::Long(version))])),
+            ("contentChanges", Json::Array(vec![change]))
+        ]));
+        self.diagnostics()
+    }
+
+    // `needle` is found in the source, the position is `delta` chars after its start
+    fn at(&mut self, method: &str, needle: &str, delta: usize) -> Json {
+        let (line, character) = position(SOURCE, needle, delta);
+        self.at_position(method, line, character)
+    }
+
+    fn at_position(&mut self, method: &str, line: i64, character: i64) -> Json {
+        self.request(method, Json::object([
+            ("textDocument", Json::object([("uri", Json::string(URI))])),
+            ("position", Json::object([("line", Json::Long(line)), ("character", Json::Long(character))]))
+        ]))
+    }
+
+    fn exit(mut self) -> Option<i32> {
+        assert_eq!(self.request("shutdown", Json::Null), Json::Null);
+        self.notify("exit", Json::Null);
+        self.child.wait().unwrap().code()
+    }
+}
+
+fn position(source: &str, needle: &str, delta: usize) -> (i64, i64) {
+    let offset = source.find(needle).unwrap_or_else(|| panic!("{needle} is not in the source")) + delta;
+    let line = source[..offset].matches('\n').count();
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    (line as i64, source[line_start..offset].encode_utf16().count() as i64)
+}
+
+fn start(range: &Json) -> (i64, i64) {
+    let start = range.get("start").unwrap();
+    (start.get("line").and_then(Json::as_i64).unwrap(), start.get("character").and_then(Json::as_i64).unwrap())
+}
+
+fn definitions(result: &Json) -> Vec<(i64, i64)> {
+    result.as_array().unwrap().iter().map(|location| {
+        assert_eq!(location.get("uri").and_then(Json::as_str), Some(URI));
+        start(location.get("range").unwrap())
+    }).collect()
+}
+
+fn hover_text(result: &Json) -> String {
+    result.get("contents").and_then(|v| v.get("value")).and_then(Json::as_str).unwrap().to_owned()
+}
+
+fn labels(items: &Json) -> Vec<String> {
+    items.as_array().unwrap().iter().map(|item| item.get("label").and_then(Json::as_str).unwrap().to_owned()).collect()
+}
+
+#[test]
+fn test_diagnostics() {
+    let mut client = Client::start();
+    assert!(client.open(SOURCE).is_empty());
+
+    // a syntax error, a semantic error of `check_and_gen`, and a warning
+    let diagnostics = client.change("func f(unused) {\n    return 1;\n}\nvar a = ;\nbreak;\n", 2);
+    let summary: Vec<_> = diagnostics.iter().map(|d| (
+        d.get("severity").and_then(Json::as_i64).unwrap(),
+        d.get("code").and_then(Json::as_str).map(str::to_owned),
+        start(d.get("range").unwrap())
+    )).collect();
+    assert_eq!(summary, [
+        (1, None, (3, 8)),
+        (1, None, (4, 0)),
+        (2, Some("W0006".to_owned()), (0, 7))
+    ], "{diagnostics:?}");
+    let message = diagnostics[2].get("message").and_then(Json::as_str).unwrap();
+    assert!(message.starts_with("unused parameter: `unused`") && message.contains("help:"), "{message}");
+
+    client.notify("textDocument/didClose", Json::object([("textDocument", Json::object([("uri", Json::string(URI))]))]));
+    assert!(client.diagnostics().is_empty());
+    assert_eq!(client.exit(), Some(0));
+}
+
+#[test]
+fn test_definition_and_hover() {
+    let mut client = Client::start();
+    client.open(SOURCE);
+
+    assert_eq!(definitions(&client.at("textDocument/definition", "add(c.", 1)), [(0, 5)]);
+    // the parameter, from the end of the name
+    assert_eq!(definitions(&client.at("textDocument/definition", "a + b", 5)), [(0, 12)]);
+    assert_eq!(definitions(&client.at("textDocument/definition", "Circle()", 0)), [(4, 6)]);
+    assert_eq!(definitions(&client.at("textDocument/definition", "total)", 0)), [(16, 4)]);
+    assert_eq!(definitions(&client.at("textDocument/definition", "area()", 0)), [(5, 9)]);
+    assert_eq!(definitions(&client.at("textDocument/definition", "c.area", 2)), [(5, 9)]);
+    assert_eq!(client.at("textDocument/definition", "__println", 0), Json::Array(Vec::new()));
+
+    let hover = client.at("textDocument/hover", "add(c.", 0);
+    assert_eq!(hover_text(&hover), "```charon\nfunc add(a, b)\n```\nfunction");
+    assert_eq!(start(hover.get("range").unwrap()), (16, 12));
+    assert!(hover_text(&client.at("textDocument/hover", "__println", 3)).contains("func __println(arg0)"));
+    assert!(hover_text(&client.at("textDocument/hover", "c.area", 2)).contains("func Circle.area()"));
+    assert!(hover_text(&client.at("textDocument/hover", "a + b", 4)).contains("parameter"));
+    assert_eq!(client.at("textDocument/hover", "return a", 0), Json::Null);
+    assert_eq!(client.exit(), Some(0));
+}
+
+#[test]
+fn test_completion_and_symbols() {
+    let mut client = Client::start();
+    client.open(SOURCE);
+
+    let members = labels(&client.at("textDocument/completion", "c.area", 2));
+    assert_eq!(members, ["area", "diameter"]);
+    let globals = labels(&client.at("textDocument/completion", "__println", 2));
+    for name in ["c", "total", "add", "Circle", "__print", "__println", "__len"] {
+        assert!(globals.iter().any(|label| label == name), "{name} in {globals:?}");
+    }
+    assert!(!globals.iter().any(|label| label == "area" || label == "a"));
+
+    let symbols = client.request("textDocument/documentSymbol", Json::object([
+        ("textDocument", Json::object([("uri", Json::string(URI))]))
+    ]));
+    let symbols = symbols.as_array().unwrap();
+    assert_eq!(labels_of(symbols, "name"), ["add", "Circle", "c", "total"]);
+    let circle = &symbols[1];
+    assert_eq!(labels_of(circle.get("children").and_then(Json::as_array).unwrap(), "name"), ["area", "diameter"]);
+    // from `class` to the closing brace
+    let range = circle.get("range").unwrap();
+    assert_eq!(start(range), (4, 0));
+    assert_eq!(range.get("end").and_then(|v| v.get("line")).and_then(Json::as_i64), Some(12));
+    assert_eq!(client.exit(), Some(0));
+}
+
+#[test]
+fn test_text_which_does_not_lex() {
+    let mut client = Client::start();
+    client.open("aa+b;");
+    // an unterminated string, the tokens of the old text don't fit the new one
+    assert_eq!(client.change("\u{e9}\u{e9}\"", 2).len(), 1);
+    assert_eq!(client.at_position("textDocument/hover", 0, 2), Json::Null);
+    assert_eq!(client.at_position("textDocument/definition", 0, 1), Json::Array(Vec::new()));
+    assert!(client.at_position("textDocument/completion", 0, 2).as_array().is_some());
+
+    assert!(client.change("var b = 1;\nb;", 3).is_empty());
+    assert!(hover_text(&client.at_position("textDocument/hover", 1, 0)).contains("var b"));
+    assert_eq!(client.exit(), Some(0));
+}
+
+fn labels_of(items: &[Json], key: &str) -> Vec<String> {
+    items.iter().map(|item| item.get(key).and_then(Json::as_str).unwrap().to_owned()).collect()
+}
+
+#[test]
+fn test_errors_and_exit() {
+    let mut client = Client::start();
+    let response = client.call("textDocument/unknown", Json::object([]));
+    assert_eq!(response.get("error").and_then(|e| e.get("code")).and_then(Json::as_i64), Some(-32601));
+    let response = client.call("textDocument/hover", Json::object([
+        ("textDocument", Json::object([("uri", Json::string("file:///tmp/closed.charon"))])),
+        ("position", Json::object([("line", Json::Long(0)), ("character", Json::Long(0))]))
+    ]));
+    assert_eq!(response.get("error").and_then(|e| e.get("code")).and_then(Json::as_i64), Some(-32602));
+
+    // `exit` without `shutdown`
+    client.notify("exit", Json::Null);
+    assert_eq!(client.child.wait().unwrap().code(), Some(1));
+}
@@ -30,19 +30,19 @@ impl Ffi for StdPrintln {
     }
 }
 
-fn print(v: &Value) {
-    match v {
-        Value::Null => print!("null"),
-        Value::True => print!("true"),
-        Value::False => print!("false"),
-        Value::Bool(v) => print!("{}", if *v {"true"} else {"false"}),
-        Value::Long(v) => print!("{v}"),
-        Value::Double(v) => print!("{v}"),
-        Value::String(v) => print!("{v}"),
-        Value::Class(c) => print!("<class: {}>", unsafe { &(**c).name }),
-        Value::Instance(i) => print!("<class: {}'s instance>", unsafe {&(**i).class_name()}),
-        Value::Function(f) => print!("<function: {}>", unsafe {&(**f).name}),
-        Value::Method(m) => print!("<class: {}'s method: {}>", m.class_name(), m.name()),
-        Value::ForeignFunction(ff) => print!("<foreign function: {}>", ff.name)
+// length of string in unicode scalar values (chars), or element count of tuple, otherwise null
+pub struct StdLen;
+
+impl Ffi for StdLen {
+    fn invoke(&self, args: VecDeque<Value>) -> Value {
+        match args.front() {
+            Some(Value::String(s)) => Value::Long(s.chars().count() as i64),
+            Some(Value::Tuple(t)) => Value::Long(t.elements().len() as i64),
+            _ => Value::Null
+        }
     }
+}
+
+fn print(v: &Value) {
+    print!("{v}");
 }
\ No newline at end of file
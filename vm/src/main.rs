@@ -1,11 +1,11 @@
 use std::{env, fs};
 use std::error::Error;
+use std::path::Path;
 use std::process::exit;
 use common::constant::MAGIC;
-use common::err_println;
+use common::diagnostic::{set_error_format, Diagnostic, ErrorFormat};
 use common::loader::Loader;
-use compilerlib::code_gen::check_and_gen;
-use compilerlib::{lex, parse};
+use compilerlib::{compile, OptLevel, Options};
 use crate::runtime::exec;
 
 mod value;
@@ -14,26 +14,43 @@ mod runtime;
 mod ffi;
 
 fn main() {
-    let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        err_println("usage: charon [charon byte code file path]");
-        exit(1);
+    let mut path = None;
+    let mut optimize = false;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "-O" => optimize = true,
+            _ if arg.starts_with("--error-format=") => match ErrorFormat::from_arg(&arg["--error-format=".len()..]) {
+                Some(format) => set_error_format(format),
+                None => usage()
+            }
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => usage()
+        }
     }
+    let Some(path) = path else {
+        usage();
+    };
 
-    if let Err(e) = run(unsafe {args.get_unchecked(1)}) {
-        err_println(&format!("{e}"));
+    if let Err(e) = run(&path, optimize) {
+        Diagnostic::error(e.to_string()).emit();
         exit(1);
     }
 }
 
-fn run(path: &str) -> Result<(), Box<dyn Error>> {
+fn usage() -> ! {
+    Diagnostic::error("usage: charon [-O] [--error-format=human|json] [charon byte code file path]".to_owned()).emit();
+    exit(1);
+}
+
+// `optimize` is used when compiling source code
+fn run(path: &str, optimize: bool) -> Result<(), Box<dyn Error>> {
     let mut bytes = fs::read(path)?;
     
     if !is_bytecode(&bytes) {
-        let tokens = lex(&bytes)?;
-        let program = parse(tokens, &bytes)?;
-        drop(bytes);
-        bytes = check_and_gen(&program)?;
+        let mut options = Options::default();
+        options.source_file = Path::new(path).file_name().map(|v| v.to_string_lossy().into_owned());
+        options.opt_level = if optimize { OptLevel::Basic } else { OptLevel::None };
+        bytes = compile(&bytes, &options).unwrap_or_else(|e| e.print_and_exit(path));
     }
     
     let program = Loader::new(&bytes).load()?;
@@ -1,17 +1,20 @@
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
+use std::fmt::Write;
 use std::mem;
+use std::mem::ManuallyDrop;
 use std::process::exit;
 use std::rc::Rc;
 use ahash::{HashMap, HashMapExt};
 use common::constant::{ConstantItem, ENTRY_NAME};
-use common::program::{Class, Function, Method, Program};
+use common::program::{Class, DebugInfo, Function, Method, Program};
 use common::reader::LEReader;
-use common::{err_println, Result};
+use common::diagnostic::Diagnostic;
+use common::Result;
 use common::opcode::*;
-use crate::ffi::{StdPrint, StdPrintln};
+use crate::ffi::{StdLen, StdPrint, StdPrintln};
 use crate::stack::{Stack, STACK_ERROR_NOTIFIER, StackError};
-use crate::value::{ForeignFunction, Instance, MemMethod, Value};
+use crate::value::{ForeignFunction, Instance, MemMethod, Range, Tuple, Value};
 
 enum FrameType {
     Func(*const Function),
@@ -22,7 +25,10 @@ struct Frame {
     frame_type: FrameType,
     pc: Cell<usize>,
     sb: Cell<usize>,
-    sp: Cell<usize>
+    sp: Cell<usize>,
+    discard_return: bool, // setter's return value is not pushed back to the caller
+    defers: RefCell<Vec<usize>>, // start pc of registered deferred blocks
+    unwinding: Cell<bool> // running deferred blocks for an error, the frame is popped without returning
 }
 
 impl Frame {
@@ -31,7 +37,10 @@ impl Frame {
             frame_type,
             pc: Cell::new(0),
             sb: Cell::new(0),
-            sp: Cell::new(0)
+            sp: Cell::new(0),
+            discard_return: false,
+            defers: RefCell::new(Vec::new()),
+            unwinding: Cell::new(false)
         }
     }
 
@@ -41,6 +50,13 @@ impl Frame {
             FrameType::Method(m) => unsafe {&(*m).code}
         }
     }
+
+    fn debug_info(&self) -> &DebugInfo {
+        match self.frame_type {
+            FrameType::Func(f) => unsafe {&(*f).debug_info},
+            FrameType::Method(m) => unsafe {&(*m).debug_info}
+        }
+    }
 }
 
 
@@ -117,14 +133,27 @@ pub fn exec(program: Program) -> Result<()> {
             entry: Rc::new(StdPrintln)
         };
         globals.insert(__println.name.clone(), Value::ForeignFunction(__println));
+
+        let __len = ForeignFunction {
+            name: "__len".to_owned(),
+            params: 1,
+            entry: Rc::new(StdLen)
+        };
+        globals.insert(__len.name.clone(), Value::ForeignFunction(__len));
     }
     
     unsafe {
-        STACK_ERROR_NOTIFIER = Some(Box::new(ScopedStackError::new(&frames)));
+        STACK_ERROR_NOTIFIER = Some(Box::new(ScopedStackError::new(&frames, program.source_file.clone())));
     }
 
     // create first frame
-    frames.push(Frame::new(FrameType::Func(entry as *const Function)));
+    let entry_frame = Frame::new(FrameType::Func(entry as *const Function));
+    check_room(&stack, 0, entry.max_locals, entry.max_stack)?;
+    entry_frame.sp.set(entry.max_locals as usize);// locals of the blocks in global scope
+    frames.push(entry_frame);
+
+    // error with stack frames, printed after the deferred blocks have run
+    let mut error: Option<Diagnostic> = None;
 
     loop {
         let Some(frame) = frames.last() else {
@@ -134,21 +163,41 @@ pub fn exec(program: Program) -> Result<()> {
         match run_code(frame, &stack, &mut globals, &program) {
             Ok(res) => match res {
                 Some(new_frame) => frames.push(new_frame),
+                None if frame.unwinding.get() => {
+                    frames.pop();
+                    unwind(&mut frames);
+                }
                 None => {
                     let return_value = pop_stack(frame, &stack);
+                    let discard_return = frame.discard_return;
                     frames.pop();
                     if let Some(frame) = frames.last() {
-                        push_stack(frame, &stack, return_value);
+                        if !discard_return {
+                            push_stack(frame, &stack, return_value);
+                        }
                     }
                 }
             }
-            Err(e) => print_error_and_exit(&e, &frames)
+            Err(e) => {
+                if let Some(error) = &error {
+                    // deferred block failed while unwinding
+                    error.emit();
+                    print_error_and_exit(&e, &frames, program.source_file.as_deref(), &stack);
+                }
+                error = Some(runtime_error(&e, &frames, program.source_file.as_deref(), Some(&stack)));
+                unwind(&mut frames);
+            }
         }
     }
 
     unsafe {
         STACK_ERROR_NOTIFIER = None;
     }
+
+    if let Some(error) = error {
+        error.emit();
+        exit(1);
+    }
     
     Ok(())
 }
@@ -156,8 +205,22 @@ pub fn exec(program: Program) -> Result<()> {
 fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, Value>, program: &Program) -> Result<Option<Frame>> {
     let mut reader = LEReader::new(frame.code());
     reader.set_offset(frame.pc.get())?;
+    let res = execute(frame, stack, globals, program, &mut reader);
+    if res.is_err() {
+        // right after the bytes of the failed instruction which were read, for the source position of the error
+        frame.pc.set(reader.offset());
+    }
+    res
+}
 
-    while let Ok(opcode) = reader.next_u8() {
+// the pc is stored to the frame by calls, and by `run_code` on errors
+fn execute(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, Value>, program: &Program, reader: &mut LEReader) -> Result<Option<Frame>> {
+    while let Ok(mut opcode) = reader.next_u8() {
+        // the operand of the next instruction is widened
+        let wide = opcode == OP_WIDE;
+        if wide {
+            opcode = reader.next_u8()?;
+        }
         match opcode {
             OP_CONST_NULL => push_stack(frame, stack, Value::Null),
             OP_CONST_TRUE => push_stack(frame, stack, Value::True),
@@ -172,8 +235,8 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
             OP_LCONST_5 => push_stack(frame, stack, Value::Long(5)),
 
             OP_LDC => {
-                let idx = reader.next_u16()?;
-                let Some(item) = program.constant_pool.get(idx as usize) else {
+                let idx = next_index(reader, wide)?;
+                let Some(item) = program.constant_pool.get(idx) else {
                     return Err(format!("`op_ldc`'s argument: {idx} not found in constant pool"));
                 };
                 match item {
@@ -208,55 +271,9 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
                         Value::Double(r) => Value::Double(l + r),
                         _ => return Err("`+`'s right operand can only support long & double when left operand is double".to_owned())
                     }
-                    Value::String(mut l) => match r {
-                        Value::Null => {
-                            l.push_str("null");
-                            Value::String(l)
-                        }
-                        Value::True => {
-                            l.push_str("true");
-                            Value::String(l)
-                        }
-                        Value::False => {
-                            l.push_str("false");
-                            Value::String(l)
-                        }
-                        Value::Bool(v) => {
-                            l.push_str(if v {"true"} else {"false"});
-                            Value::String(l)
-                        }
-                        Value::Long(v) => {
-                            l.push_str(&v.to_string());
-                            Value::String(l)
-                        }
-                        Value::Double(v) => {
-                            l.push_str(&v.to_string());
-                            Value::String(l)
-                        }
-                        Value::String(v) => {
-                            l.push_str(&v);
-                            Value::String(l)
-                        }
-                        Value::Class(c) => {
-                            l.push_str(&format!("<class: {}>", unsafe {&(*c).name}));
-                            Value::String(l)
-                        }
-                        Value::Instance(i) => {
-                            l.push_str(&format!("<class: {}'s instance>", unsafe {(&*i).class_name()}));
-                            Value::String(l)
-                        }
-                        Value::Function(f) => {
-                            l.push_str(&format!("<function: {}>", unsafe {&(*f).name}));
-                            Value::String(l)
-                        }
-                        Value::Method(m) => {
-                            l.push_str(&format!("<class: {}'s method: {}>", m.class_name(), m.name()));
-                            Value::String(l)
-                        }
-                        Value::ForeignFunction(ff) => {
-                            l.push_str(&format!("<foreign function: {}>", ff.name));
-                            Value::String(l)
-                        }
+                    Value::String(mut l) => {
+                        let _ = write!(l, "{r}");
+                        Value::String(l)
                     }
                     _ => return Err("`+` can only used between long, double and string".to_owned())
                 };
@@ -299,22 +316,22 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
             OP_CMP_LTEQ => cmp_op!(frame, stack, <=),
 
             OP_IF => {
-                let idx = reader.next_u16()?;
+                let idx = next_index(reader, wide)?;
                 let v = pop_stack(frame, stack);
                 if is_true(&v) {
-                    reader.set_offset(idx as usize)?;
+                    reader.set_offset(idx)?;
                 }
             }
             OP_IF_NOT => {
-                let idx = reader.next_u16()?;
+                let idx = next_index(reader, wide)?;
                 let v = pop_stack(frame, stack);
                 if is_false(&v) {
-                    reader.set_offset(idx as usize)?;
+                    reader.set_offset(idx)?;
                 }
             }
             OP_GOTO => {
-                let idx = reader.next_u16()?;
-                reader.set_offset(idx as usize)?;
+                let idx = next_index(reader, wide)?;
+                reader.set_offset(idx)?;
             }
 
             OP_INVOKE => {
@@ -336,6 +353,7 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
                             return Err(format!("function: {}'s param count: {}, but got: {params}", func.name, func.params));
                         }
                         let sp = frame.sp.get();
+                        check_room(stack, sp - params as usize, func.max_locals, func.max_stack)?;
                         new_frame.sb.set(sp - params as usize);
                         new_frame.sp.set(new_frame.sb.get() + func.max_locals as usize);
 
@@ -345,19 +363,11 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
                         return Ok(Some(new_frame));
                     }
                     Value::Method(method) => {
-                        let new_frame = Frame::new(FrameType::Method(method.method));
                         if method.param_count() != params {
                             return Err(format!("method: {}'s param count: {}, but got: {params}", method.name(), method.param_count()));
                         }
-                        let sp = frame.sp.get();
-                        stack.write(sp as isize, Value::Instance(method.instance));// this
-                        new_frame.sb.set(sp - params as usize);
-                        new_frame.sp.set(new_frame.sb.get() + method.max_locals() as usize);
-
                         frame.pc.set(reader.offset());
-                        frame.sp.set(sp - params as usize - 1);// -1 the method owner
-
-                        return Ok(Some(new_frame));
+                        return Ok(Some(new_method_frame(frame, stack, &method)?));
                     }
                     Value::ForeignFunction(ff) => {
                         if ff.params != params {
@@ -377,15 +387,28 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
                 }
             }
 
-            OP_RETURN => return Ok(None),
+            OP_RETURN => {
+                // run deferred blocks in LIFO order, each one ends with `return`
+                let next = frame.defers.borrow_mut().pop();
+                match next {
+                    Some(pc) => reader.set_offset(pc)?,
+                    None => return Ok(None)
+                }
+            }
+
+            OP_DEFER => {
+                let idx = next_index(reader, wide)?;
+                frame.defers.borrow_mut().push(reader.offset());
+                reader.set_offset(idx)?;
+            }
 
             OP_POP => {
                 pop_stack(frame, stack);
             }
 
             OP_DEF_GLOBAL => {
-                let idx = reader.next_u16()?;
-                let Some(ConstantItem::String(var)) = program.constant_pool.get(idx as usize) else {
+                let idx = next_index(reader, wide)?;
+                let Some(ConstantItem::String(var)) = program.constant_pool.get(idx) else {
                     return Err("`DEF_GLOBAL` expect string argument as global variable name".to_owned());
                 };
                 let v = pop_stack(frame, stack);
@@ -396,8 +419,8 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
             }
             
             OP_SET_GLOBAL => {
-                let idx = reader.next_u16()?;
-                let Some(ConstantItem::String(var)) = program.constant_pool.get(idx as usize) else {
+                let idx = next_index(reader, wide)?;
+                let Some(ConstantItem::String(var)) = program.constant_pool.get(idx) else {
                     return Err("`SET_GLOBAL` expect string argument as global variable name".to_owned());
                 };
                 if !globals.contains_key(var) {
@@ -410,8 +433,8 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
                 globals.insert(var.to_owned(), v);
             }
             OP_GET_GLOBAL => {
-                let idx = reader.next_u16()?;
-                let Some(ConstantItem::String(var)) = program.constant_pool.get(idx as usize) else {
+                let idx = next_index(reader, wide)?;
+                let Some(ConstantItem::String(var)) = program.constant_pool.get(idx) else {
                     return Err("`GET_GLOBAL` expect string argument as global variable name".to_owned());
                 };
                 let Some(v) = globals.get(var) else {
@@ -420,7 +443,7 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
                 push_stack(frame, stack, v.clone());
             }
             OP_SET_LOCAL => {
-                let idx = reader.next_u8()?;
+                let idx = next_slot(reader, wide)?;
                 let v = pop_stack(frame, stack);
                 if matches!(v, Value::Method(_)) {
                     return Err("method can't assign to variable".to_owned());
@@ -428,46 +451,64 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
                 stack.write(frame.sb.get() as isize + idx as isize, v);
             }
             OP_GET_LOCAL => {
-                let idx = reader.next_u8()?;
+                let idx = next_slot(reader, wide)?;
                 let v = stack.read(frame.sb.get() as isize + idx as isize);
                 push_stack(frame, stack, v);
             }
 
             OP_SET_FIELD => {
-                let idx = reader.next_u16()?;
-                let Some(ConstantItem::String(var)) = program.constant_pool.get(idx as usize) else {
+                let idx = next_index(reader, wide)?;
+                let Some(ConstantItem::String(var)) = program.constant_pool.get(idx) else {
                     return Err("`SET_FIELD` expect string argument as field name".to_owned());
                 };
                 let v = pop_stack(frame, stack);
                 if matches!(v, Value::Method(_)) {
                     return Err("method can't assign to class's field".to_owned());
                 }
-                let owner = pop_stack(frame, stack);
+                let owner = stack.read(frame.sp.get() as isize - 1);
                 match owner {
                     Value::Instance(instance) => {
-                        let instance = unsafe {&mut *instance};
-                        if unsafe {(&*(instance.class)).methods.contains_key(var)} {
-                            return Err(format!("class: {} already has method named: {var}, can't assign new value to it"
-                                               , instance.class_name()));
+                        let class = unsafe {&*(*instance).class};
+                        if let Some(setter) = class.setters.get(var) {
+                            // the layout is the same as `OP_INVOKE` with 1 argument, the owner is replaced by 'this'
+                            push_stack(frame, stack, v);
+                            frame.pc.set(reader.offset());
+                            let mut new_frame = new_method_frame(frame, stack, &MemMethod::new(instance, setter))?;
+                            new_frame.discard_return = true;
+                            return Ok(Some(new_frame));
+                        }
+                        if class.getters.contains_key(var) {
+                            return Err(format!("property: {var} of class: {} has no setter, can't assign new value to it"
+                                               , class.name));
                         }
-                        instance.fields.insert(var.to_owned(), v);
+                        pop_stack(frame, stack);// owner
+                        unsafe {&mut *instance}.fields.insert(var.to_owned(), v);
                     }
                     _ => return Err("`SET_FIELD` owner should be class's instance".to_owned())
                 }
             }
 
             OP_GET_MEMBER => {
-                let idx = reader.next_u16()?;
-                let Some(ConstantItem::String(name)) = program.constant_pool.get(idx as usize) else {
+                let idx = next_index(reader, wide)?;
+                let Some(ConstantItem::String(name)) = program.constant_pool.get(idx) else {
                     return Err("`GET_MEMBER` expect string argument as member name".to_owned());
                 };
-                let owner = pop_stack(frame, stack);
+                let owner = stack.read(frame.sp.get() as isize - 1);
                 match owner {
                     Value::Instance(instance) => {
                         let class = unsafe {&*(*instance).class};
+                        if let Some(getter) = class.getters.get(name) {
+                            // the layout is the same as `OP_INVOKE` with 0 argument, the owner is replaced by 'this'
+                            frame.pc.set(reader.offset());
+                            return Ok(Some(new_method_frame(frame, stack, &MemMethod::new(instance, getter))?));
+                        }
+                        if class.setters.contains_key(name) {
+                            return Err(format!("property: {name} of class: {} has no getter, can't read it", class.name));
+                        }
+                        pop_stack(frame, stack);// owner
                         let v = if let Some(method) = class.methods.get(name) {
                             Value::Method(MemMethod::new(instance, method as *const Method))
-                        } else if let Some(v) = unsafe {(&*instance).fields.get(name)} {
+                        } else if let Some(v) = unsafe {(*instance).fields.get(name)} {
                             v.clone()
                         } else {
                             Value::Null
@@ -477,6 +518,141 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
                     _ => return Err("`GET_MEMBER` owner should be class's instance".to_owned())
                 }
             }
+
+            OP_DUP => {
+                let v = stack.read(frame.sp.get() as isize - 1);
+                push_stack(frame, stack, v);
+            }
+
+            OP_IS => {
+                let idx = next_index(reader, wide)?;
+                let Some(ConstantItem::String(name)) = program.constant_pool.get(idx) else {
+                    return Err("`IS` expect string argument as class or trait name".to_owned());
+                };
+                let res = match pop_stack(frame, stack) {
+                    Value::Instance(instance) => {
+                        let class = unsafe {&*(*instance).class};
+                        class.name == *name || class.traits.contains(name)
+                    }
+                    _ => false
+                };
+                push_stack(frame, stack, Value::Bool(res));
+            }
+
+            OP_NEW_TUPLE => {
+                let count = reader.next_u8()? as usize;
+                let mut elements = Vec::with_capacity(count);
+                for _ in 0 .. count {
+                    let v = pop_stack(frame, stack);
+                    if matches!(v, Value::Method(_)) {
+                        return Err("method can't be element of tuple".to_owned());
+                    }
+                    elements.push(v);
+                }
+                elements.reverse();
+                push_stack(frame, stack, Value::Tuple(Tuple::new(elements)));
+            }
+            OP_UNPACK => {
+                let count = reader.next_u8()? as usize;
+                let Value::Tuple(tuple) = pop_stack(frame, stack) else {
+                    return Err("only tuple can be destructured".to_owned());
+                };
+                let elements = tuple.elements();
+                if elements.len() != count {
+                    return Err(format!("tuple has {} elements, but {count} expected", elements.len()));
+                }
+                for e in elements {
+                    push_stack(frame, stack, e.clone());
+                }
+            }
+
+            OP_RANGE => {
+                let inclusive = reader.next_u8()? != 0;
+                let end = pop_stack(frame, stack);
+                let start = pop_stack(frame, stack);
+                let (Value::Long(start), Value::Long(end)) = (start, end) else {
+                    return Err("bounds of range can only be long".to_owned());
+                };
+                push_stack(frame, stack, Value::Range(Range::new(start, end, inclusive)));
+            }
+            OP_ITER_NEXT => {
+                let idx = next_index(reader, wide)?;
+                // the iterator is on the top of the operand stack, update it in place
+                let top = frame.sp.get() as isize - 1;
+                match stack.read(top) {
+                    Value::Range(range) => {
+                        if range.is_empty() {
+                            reader.set_offset(idx)?;
+                        } else {
+                            stack.write(top, Value::Range(range.advance()));
+                            push_stack(frame, stack, Value::Long(range.start));
+                        }
+                    }
+                    _ => return Err("only range can be iterated".to_owned())
+                }
+            }
+            OP_ASSERT => {
+                let idx = next_index(reader, wide)?;
+                let Some(ConstantItem::String(desc)) = program.constant_pool.get(idx) else {
+                    return Err("`ASSERT` expect string argument as description".to_owned());
+                };
+                let count = reader.next_u8()?;
+                let mut names = Vec::with_capacity(count as usize);
+                for _ in 0 .. count {
+                    let idx = next_index(reader, wide)?;
+                    let Some(ConstantItem::String(name)) = program.constant_pool.get(idx) else {
+                        return Err("`ASSERT` expect string argument as operand name".to_owned());
+                    };
+                    names.push(name);
+                }
+
+                let msg = pop_stack(frame, stack);
+                let mut values = VecDeque::with_capacity(count as usize);
+                for _ in 0 .. count {
+                    values.push_front(pop_stack(frame, stack));
+                }
+
+                let mut err = format!("assertion {desc}");
+                if !matches!(msg, Value::Null) {
+                    let _ = write!(err, ": {msg}");
+                }
+                for (name, value) in names.iter().zip(values) {
+                    let _ = write!(err, "\n      {name}: {value}");
+                }
+                return Err(err);
+            }
+
+            // index & slice count unicode scalar values (chars), not bytes
+            OP_INDEX => {
+                let index = pop_stack(frame, stack);
+                let owner = pop_stack(frame, stack);
+                let Value::String(s) = owner else {
+                    return Err("only string can be indexed".to_owned());
+                };
+                let Value::Long(index) = index else {
+                    return Err("index can only be long".to_owned());
+                };
+                let len = s.chars().count();
+                let i = resolve_index(index, len);
+                if i < 0 || i >= len as i64 {
+                    return Err(format!("index: {index} out of range, length: {len}"));
+                }
+                let c = s.chars().nth(i as usize).unwrap();
+                push_stack(frame, stack, Value::String(c.to_string()));
+            }
+            OP_SLICE => {
+                let end = pop_stack(frame, stack);
+                let start = pop_stack(frame, stack);
+                let owner = pop_stack(frame, stack);
+                let Value::String(s) = owner else {
+                    return Err("only string can be sliced".to_owned());
+                };
+                let len = s.chars().count();
+                let start = slice_bound(&start, 0, len)?;
+                let end = slice_bound(&end, len, len)?.max(start);
+                let sub: String = s.chars().skip(start).take(end - start).collect();
+                push_stack(frame, stack, Value::String(sub));
+            }
             _ => return Err(format!("unknown opcode: {opcode}"))
         }
     }
@@ -485,6 +661,29 @@ fn run_code(frame: &Frame, stack: &Stack<Value>, globals: &mut HashMap<String, V
 }
 
 
+// the owner & arguments are on the top of the caller's operand stack
+fn new_method_frame(frame: &Frame, stack: &Stack<Value>, method: &MemMethod) -> Result<Frame> {
+    let params = method.param_count() as usize;
+    let new_frame = Frame::new(FrameType::Method(method.method));
+    let sp = frame.sp.get();
+    check_room(stack, sp - params, method.max_locals(), method.max_stack())?;
+    stack.write(sp as isize, Value::Instance(method.instance));// this
+    new_frame.sb.set(sp - params);
+    new_frame.sp.set(new_frame.sb.get() + method.max_locals() as usize);
+
+    frame.sp.set(sp - params - 1);// -1 the method owner
+    Ok(new_frame)
+}
+
+// the locals & the operand stack of a new frame from `sb` fit in the stack,
+// the code is verified not to go deeper than `max_stack`, so calls are the only place to check
+fn check_room(stack: &Stack<Value>, sb: usize, max_locals: u16, max_stack: u16) -> Result<()> {
+    if sb + max_locals as usize + max_stack as usize > stack.capacity() {
+        return Err("stack overflow".to_owned());
+    }
+    Ok(())
+}
+
 fn push_stack(frame: &Frame, stack: &Stack<Value>, value: Value) {
     let sp = frame.sp.get();
     stack.write(sp as isize, value);
@@ -498,6 +697,24 @@ fn pop_stack(frame: &Frame, stack: &Stack<Value>) -> Value {
     stack.read(sp as isize)
 }
 
+// negative index counts from the end
+fn resolve_index(index: i64, len: usize) -> i64 {
+    if index < 0 {
+        index + len as i64
+    } else {
+        index
+    }
+}
+
+// `null` means no bound, out of range bound is clamped to [0, len]
+fn slice_bound(bound: &Value, default: usize, len: usize) -> Result<usize> {
+    match bound {
+        Value::Null => Ok(default),
+        Value::Long(v) => Ok(resolve_index(*v, len).clamp(0, len as i64) as usize),
+        _ => Err("bound of slice can only be long".to_owned())
+    }
+}
+
 fn is_true(v: &Value) -> bool {
     match v {
         Value::Null => false,
@@ -511,7 +728,9 @@ fn is_true(v: &Value) -> bool {
         Value::Instance(_) => true,
         Value::Function(_) => true,
         Value::Method(_) => true,
-        Value::ForeignFunction(_) => true
+        Value::ForeignFunction(_) => true,
+        Value::Tuple(_) => true,
+        Value::Range(_) => true
     }
 }
 
@@ -528,48 +747,141 @@ fn is_false(v: &Value) -> bool {
         Value::Instance(_) => false,
         Value::Function(_) => false,
         Value::Method(_) => false,
-        Value::ForeignFunction(_) => false
+        Value::ForeignFunction(_) => false,
+        Value::Tuple(_) => false,
+        Value::Range(_) => false
+    }
+}
+
+// pop frames until one has a pending deferred block, and continue at that block
+fn unwind(frames: &mut Vec<Frame>) {
+    while let Some(frame) = frames.last() {
+        let next = frame.defers.borrow_mut().pop();
+        if let Some(pc) = next {
+            frame.pc.set(pc);
+            frame.unwinding.set(true);
+            return;
+        }
+        frames.pop();
     }
 }
 
-fn print_error_and_exit(msg: &str, frames: &[Frame]) -> ! {
-    err_println(&format!("Error:  {msg}"));
-    print_stack_frames(frames);
+fn print_error_and_exit(msg: &str, frames: &[Frame], source_file: Option<&str>, stack: &Stack<Value>) -> ! {
+    runtime_error(msg, frames, source_file, Some(stack)).emit();
     exit(1);
 }
 
-fn print_stack_frames(frames: &[Frame]) {
-    for frame in frames.iter().rev() {
-        match &frame.frame_type {
+// the stack frames are the notes, innermost first, with the source position if the bytecode has debug info
+// the local variables of the innermost frame are shown if the stack is given
+fn runtime_error(msg: &str, frames: &[Frame], source_file: Option<&str>, stack: Option<&Stack<Value>>) -> Diagnostic {
+    let mut error = Diagnostic::error(msg.to_owned());
+    let mut callers = Vec::new();
+    for (i, frame) in frames.iter().rev().enumerate() {
+        // pc is right after `invoke` of the callers, and in the failed instruction of the innermost frame
+        let pc = frame.pc.get().saturating_sub(1);
+        let at = match (frame.debug_info().line_number(pc), source_file) {
+            (Some((line, column)), Some(file)) => format!(" at {file}:{line}:{column}"),
+            (Some((line, column)), None) => format!(" at {line}:{column}"),
+            (None, _) => String::new()
+        };
+        let note = match &frame.frame_type {
             FrameType::Func(f) => {
                 let name = unsafe {&(**f).name};
                 if name != ENTRY_NAME {
-                    err_println(&format!("      in function: {name}"));
+                    Some(format!("in function: {name}{at}"))
+                } else if !at.is_empty() {
+                    Some(at.trim_start().to_owned())
+                } else {
+                    None
                 }
             }
             FrameType::Method(m) => {
                 let method = unsafe {&**m};
-                err_println(&format!("      in method:  {}.{}", method.class_name, method.name));
+                Some(format!("in method: {}.{}{at}", method.class_name, method.name))
+            }
+        };
+        if i > 0 {
+            callers.extend(note);
+            continue;
+        }
+        error.notes.extend(note);
+        if let Some(stack) = stack {
+            // the failed instruction may be the `SET_LOCAL` defining a variable, whose slot isn't written yet
+            let start = instruction_start(frame.code(), pc);
+            let defining = frame.code().get(start..).is_some_and(|code| code.starts_with(&[OP_SET_LOCAL]) || code.starts_with(&[OP_WIDE, OP_SET_LOCAL]));
+            let live = |start_pc: u32| !defining || start_pc as usize != start;
+            let locals: Vec<_> = frame.debug_info().local_variables(pc).filter(|v| live(v.start_pc)).map(|v| {
+                // a copy of the slot, which must not be dropped
+                let value = ManuallyDrop::new(stack.read((frame.sb.get() + v.slot as usize) as isize));
+                format!("{} = {}", v.name, *value)
+            }).collect();
+            if !locals.is_empty() {
+                error.notes.push(format!("local variables: {}", locals.join(", ")));
+            }
+        }
+    }
+    error.notes.extend(collapse_repeated(callers));
+    error
+}
+
+// start of the instruction containing `pc`, decoded from the start of the code
+fn instruction_start(code: &[u8], pc: usize) -> usize {
+    let mut start = 0;
+    while let Some(len) = code.get(start..).and_then(instruction_len) {
+        if start + len > pc {
+            break;
+        }
+        start += len;
+    }
+    start
+}
+
+// the longest run of frames which is looked for repeating, as the calls of mutual recursion
+const MAX_REPEATED_FRAMES: usize = 8;
+
+// a run of frames repeating 3 times or more, as in deep recursion, is shown once
+fn collapse_repeated(notes: Vec<String>) -> Vec<String> {
+    let mut collapsed = Vec::new();
+    let mut i = 0;
+    'notes: while i < notes.len() {
+        for len in 1..=MAX_REPEATED_FRAMES {
+            let Some(run) = notes.get(i..i + len) else {
+                break;
+            };
+            let mut count = 1;
+            while notes.get(i + count * len..).is_some_and(|rest| rest.starts_with(run)) {
+                count += 1;
+            }
+            if count >= 3 {
+                collapsed.extend_from_slice(run);
+                collapsed.push(format!("... {} more frames like the above", (count - 1) * len));
+                i += count * len;
+                continue 'notes;
             }
         }
+        collapsed.push(notes[i].clone());
+        i += 1;
     }
+    collapsed
 }
 
 struct ScopedStackError {
-    frames: *const Vec<Frame>
+    frames: *const Vec<Frame>,
+    source_file: Option<String>
 }
 
 impl ScopedStackError {
-    fn new(frames: &Vec<Frame>) -> Self {
+    fn new(frames: &Vec<Frame>, source_file: Option<String>) -> Self {
         Self {
-            frames: frames as *const Vec<Frame>
+            frames: frames as *const Vec<Frame>,
+            source_file
         }
     }
 }
 
 impl StackError for ScopedStackError {
-    fn on_error(&self) {
+    fn on_error(&self, msg: &str) {
         let frames = unsafe {&*self.frames};
-        print_stack_frames(frames);
+        runtime_error(msg, frames, self.source_file.as_deref(), None).emit();
     }
 }
\ No newline at end of file
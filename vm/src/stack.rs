@@ -31,6 +31,11 @@ impl<T> Stack<T> {
         }
     }
 
+    // count of values the stack holds
+    pub fn capacity(&self) -> usize {
+        STACK_SIZE / mem::size_of::<T>()
+    }
+
     pub fn read(&self, off: isize) -> T {
         unsafe {
             self.base.offset(off).read()
@@ -55,7 +60,7 @@ impl<T> Drop for Stack<T> {
 
 const PAGE_SIZE: size_t = 4096;
 const STACK_SIZE: size_t = PAGE_SIZE * 256;
-const MAP_SIZE: size_t = STACK_SIZE + PAGE_SIZE * 2;// with guard page
+const MAP_SIZE: size_t = STACK_SIZE + PAGE_SIZE * 2;// with guard page, the vm checks the room of every frame before touching it
 
 static mut MAPPED: Vec<usize> = Vec::new();
 
@@ -131,18 +136,20 @@ unsafe fn handle_out_of_stack(siginfo: *const siginfo_t) {
     for addr in &MAPPED {
         let addr = *addr;
         if fault_addr >= addr && fault_addr < addr + PAGE_SIZE {
-            err_println("stack underflow");
             if let Some(notifier) = &STACK_ERROR_NOTIFIER {
-                notifier.on_error();
+                notifier.on_error("stack underflow");
+            } else {
+                err_println("stack underflow");
             }
             exit(1);
         }
         
         if fault_addr >= addr + STACK_SIZE + PAGE_SIZE
             && fault_addr < addr + STACK_SIZE + PAGE_SIZE * 2 {
-            err_println("stack overflow");
             if let Some(notifier) = &STACK_ERROR_NOTIFIER {
-                notifier.on_error();
+                notifier.on_error("stack overflow");
+            } else {
+                err_println("stack overflow");
             }
             exit(1);
         }
@@ -182,7 +189,7 @@ unsafe fn install_sigaction_for_segv() -> Result<(), String> {
 }
 
 pub trait StackError {
-    fn on_error(&self);
+    fn on_error(&self, msg: &str);
 }
 
 pub static mut STACK_ERROR_NOTIFIER: Option<Box<dyn StackError>> = None;
\ No newline at end of file
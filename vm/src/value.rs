@@ -1,3 +1,4 @@
+use std::fmt::{Display, Formatter};
 use std::rc::Rc;
 use ahash::{HashMap, HashMapExt};
 use common::program::{Class, Function, Method};
@@ -16,7 +17,9 @@ pub enum Value {
     Instance(*mut Instance),
     Function(*const Function),
     Method(MemMethod),
-    ForeignFunction(ForeignFunction)
+    ForeignFunction(ForeignFunction),
+    Tuple(Tuple),
+    Range(Range)
 }
 
 impl Value {
@@ -40,6 +43,36 @@ impl Value {
     }
 }
 
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::True => write!(f, "true"),
+            Value::False => write!(f, "false"),
+            Value::Bool(v) => write!(f, "{}", if *v {"true"} else {"false"}),
+            Value::Long(v) => write!(f, "{v}"),
+            Value::Double(v) => write!(f, "{v}"),
+            Value::String(v) => write!(f, "{v}"),
+            Value::Class(c) => write!(f, "<class: {}>", unsafe { &(**c).name }),
+            Value::Instance(i) => write!(f, "<class: {}'s instance>", unsafe {(**i).class_name()}),
+            Value::Function(func) => write!(f, "<function: {}>", unsafe {&(**func).name}),
+            Value::Method(m) => write!(f, "<class: {}'s method: {}>", m.class_name(), m.name()),
+            Value::ForeignFunction(ff) => write!(f, "<foreign function: {}>", ff.name),
+            Value::Tuple(t) => {
+                write!(f, "(")?;
+                for (i, e) in t.elements().iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{e}")?;
+                }
+                write!(f, ")")
+            }
+            Value::Range(r) => write!(f, "{}{}{}", r.start, if r.inclusive {"..="} else {".."}, r.end)
+        }
+    }
+}
+
 #[derive(PartialEq)]
 pub struct Instance {
     pub class: *const Class,
@@ -91,9 +124,13 @@ impl MemMethod {
         unsafe {(*self.method).params}
     }
     
-    pub fn max_locals(&self) -> u8 {
+    pub fn max_locals(&self) -> u16 {
         unsafe {(*self.method).max_locals}
     }
+
+    pub fn max_stack(&self) -> u16 {
+        unsafe {(*self.method).max_stack}
+    }
     
     pub fn name(&self) -> &str {
         unsafe {&(*self.method).name}
@@ -103,3 +140,58 @@ impl MemMethod {
         unsafe {&(*self.method).class_name}
     }
 }
+
+// tuple is immutable, compare by elements
+#[derive(Clone)]
+pub struct Tuple {
+    elements: *const Vec<Value>
+}
+
+impl Tuple {
+    pub fn new(elements: Vec<Value>) -> Self {
+        Self {
+            elements: Box::into_raw(Box::new(elements))
+        }
+    }
+
+    pub fn elements(&self) -> &[Value] {
+        unsafe {&*self.elements}
+    }
+}
+
+impl PartialEq for Tuple {
+    fn eq(&self, other: &Self) -> bool {
+        self.elements() == other.elements()
+    }
+}
+
+// range is lazy, iterate it doesn't need to allocate
+#[derive(Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub inclusive: bool
+}
+
+impl Range {
+    pub fn new(start: i64, end: i64, inclusive: bool) -> Self {
+        Self { start, end, inclusive }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        if self.inclusive {
+            self.start > self.end
+        } else {
+            self.start >= self.end
+        }
+    }
+
+    // the rest of range after taking `start` out, should not be called on empty range
+    pub fn advance(&self) -> Self {
+        if self.start == self.end {// inclusive, avoid overflow when end is i64::MAX
+            Self::new(self.end, self.end, false)
+        } else {
+            Self::new(self.start + 1, self.end, self.inclusive)
+        }
+    }
+}
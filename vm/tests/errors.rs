@@ -0,0 +1,64 @@
+// runtime errors are reported with the frames, and the program exits with 1
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// the source is written to a file named after the test, the notes of the error are returned
+fn error_notes(name: &str, source: &str) -> Vec<String> {
+    let path: PathBuf = [env!("CARGO_TARGET_TMPDIR"), &format!("{name}.charon")].iter().collect();
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_charon")).arg(&path).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    stderr.lines().filter_map(|line| line.strip_prefix(" = note: ")).map(|line| line.to_owned()).collect()
+}
+
+#[test]
+fn test_stack_overflow() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/stackoverflow.charon");
+    let output = Command::new(env!("CARGO_BIN_EXE_charon")).arg(path).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+
+    // the calls of the mutual recursion are shown once
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<_> = stderr.lines().collect();
+    assert_eq!(lines[0], "error: stack overflow");
+    assert!(lines.len() < 10, "{stderr}");
+    assert!(lines.iter().any(|line| line.ends_with("more frames like the above")), "{stderr}");
+    assert_eq!(lines.iter().filter(|line| line.contains("at stackoverflow.charon:11:3")).count(), 1);
+}
+
+#[test]
+fn test_local_variables() {
+    // `SET_LOCAL` of `m` fails, its slot still holds the string of `g`'s frame, which is dropped
+    let source = "class A { func m() { return 1; } }
+func g() { return \"a longer string to get a heap buffer\"; }
+func f() { var a = A(); var m = a.m; return m; }
+g();
+f();
+";
+    let notes = error_notes("local_variables", source);
+    assert_eq!(notes[0], "in function: f at local_variables.charon:3:25");
+    assert_eq!(notes[1], "local variables: a = <class: A's instance>");
+
+    // the params are written by the call, even if the first instruction fails
+    let notes = error_notes("local_variables_params", "func h(p) { return missing + p; }\nh(1);\n");
+    assert_eq!(notes[1], "local variables: p = 1");
+}
+
+#[test]
+fn test_assert_temporaries() {
+    // `+` fails before the getter of the right operand is called, its temporary is still null
+    let source = "class C {
+    get x() { return null; }
+    get y() { return \"a longer string to get a heap buffer\"; }
+}
+func g() { return \"another longer string to get a heap buffer\"; }
+func f(c) { assert c.x + 1 == c.y; }
+g();
+f(C());
+";
+    let notes = error_notes("assert_temporaries", source);
+    assert_eq!(notes[1], "local variables: c = <class: C's instance>, c.y = null");
+}
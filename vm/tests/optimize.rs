@@ -0,0 +1,37 @@
+// differential tests: every example prints the same output and fails the same way with and without `-O`
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn examples() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples");
+    let mut paths: Vec<_> = fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "charon"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn run(path: &Path, optimize: bool) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_charon"));
+    if optimize {
+        command.arg("-O");
+    }
+    command.arg(path).output().unwrap()
+}
+
+#[test]
+fn test_optimized_examples() {
+    let paths = examples();
+    assert!(!paths.is_empty());
+    for path in paths {
+        let unoptimized = run(&path, false);
+        let optimized = run(&path, true);
+        let name = path.display();
+        assert_eq!(unoptimized.status.code(), optimized.status.code(), "exit code of {name}");
+        assert_eq!(String::from_utf8_lossy(&unoptimized.stdout), String::from_utf8_lossy(&optimized.stdout), "stdout of {name}");
+        assert_eq!(String::from_utf8_lossy(&unoptimized.stderr), String::from_utf8_lossy(&optimized.stderr), "stderr of {name}");
+    }
+}
@@ -0,0 +1,64 @@
+// programs over the u16 & u8 limits of the format before 1.8: the compiler emits `WIDE`, the loader & the vm follow it
+
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use common::loader::Loader;
+use common::program::Program;
+use compilerlib::{compile, Options};
+
+// the source is written to a file named after the test, charon prints the output
+fn run(name: &str, source: &str) -> String {
+    let path: PathBuf = [env!("CARGO_TARGET_TMPDIR"), &format!("{name}.charon")].iter().collect();
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_charon")).arg(&path).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn load(source: &str) -> Program {
+    let bytes = compile(source.as_bytes(), &Options::default()).unwrap();
+    Loader::new(&bytes).load().unwrap()
+}
+
+#[test]
+fn test_long_function() {
+    // the jump over the body of `if` is longer than 64 KiB
+    let mut source = "func f(n) {\n    var s = 0;\n    if (n > 0) {\n".to_owned();
+    for _ in 0..15000 {
+        source.push_str("        s = s + 1;\n");
+    }
+    source.push_str("    }\n    return s;\n}\n__println(f(1));\n__println(f(0));\n");
+
+    let program = load(&source);
+    assert!(program.functions["f"].code.len() > u16::MAX as usize);
+    assert_eq!(run("long_function", &source), "15000\n0\n");
+}
+
+#[test]
+fn test_many_constants() {
+    let mut source = "var s = 0;\n".to_owned();
+    for i in 0..70000 {
+        let _ = writeln!(source, "s = s + {};", 1000 + i);
+    }
+    source.push_str("__println(s);\n");
+
+    let program = load(&source);
+    assert!(program.constant_pool.len() > u16::MAX as usize);
+    let sum: i64 = (0..70000).map(|i| 1000 + i).sum();
+    assert_eq!(run("many_constants", &source), format!("{sum}\n"));
+}
+
+#[test]
+fn test_many_locals() {
+    let mut source = "func f() {\n".to_owned();
+    for i in 0..300 {
+        let _ = writeln!(source, "    var v{i} = {i};");
+    }
+    source.push_str("    return v0 + v150 + v299;\n}\n__println(f());\n");
+
+    let program = load(&source);
+    assert!(program.functions["f"].max_locals > u8::MAX as u16);
+    assert_eq!(run("many_locals", &source), "449\n");
+}